@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS playback_event
+(
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id    TEXT     NOT NULL,
+    user_id       TEXT     REFERENCES user (id) ON DELETE SET NULL,
+    info_hash     TEXT     NOT NULL,
+    file_idx      INTEGER  NOT NULL,
+    stream_kind   TEXT     NOT NULL,
+    quality       TEXT     NOT NULL,
+    event_type    TEXT     NOT NULL,
+    timestamp_ms  INTEGER  NOT NULL,
+    position      REAL,
+    duration_ms   INTEGER,
+    bitrate       INTEGER,
+    message       TEXT,
+    created_at    DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS playback_event_session_id ON playback_event (session_id);
+CREATE INDEX IF NOT EXISTS playback_event_file ON playback_event (info_hash, file_idx);
+CREATE INDEX IF NOT EXISTS playback_event_user_id ON playback_event (user_id);
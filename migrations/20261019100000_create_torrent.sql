@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS torrent
+(
+    info_hash  TEXT PRIMARY KEY NOT NULL,
+    user_id    TEXT REFERENCES user (id) ON DELETE SET NULL,
+    tmdb_id    INTEGER,
+    media_type TEXT,
+    season     INTEGER,
+    episode    INTEGER,
+    file_idx   INTEGER,
+    added_at   DATETIME         NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS torrent_user_id ON torrent (user_id);
+CREATE INDEX IF NOT EXISTS torrent_tmdb_id ON torrent (tmdb_id, media_type);
@@ -0,0 +1 @@
+ALTER TABLE torrent ADD COLUMN library_path TEXT;
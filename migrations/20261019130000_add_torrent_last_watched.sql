@@ -0,0 +1 @@
+ALTER TABLE torrent ADD COLUMN last_watched_at DATETIME;
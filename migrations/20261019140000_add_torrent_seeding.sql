@@ -0,0 +1,4 @@
+ALTER TABLE torrent ADD COLUMN finished_at DATETIME;
+ALTER TABLE torrent ADD COLUMN seed_ratio_limit REAL;
+ALTER TABLE torrent ADD COLUMN seed_time_limit_hours INTEGER;
+ALTER TABLE torrent ADD COLUMN seed_limit_action TEXT;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS notification
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id    TEXT     NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    info_hash  TEXT,
+    kind       TEXT     NOT NULL,
+    message    TEXT     NOT NULL,
+    read_at    DATETIME,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS notification_user_id ON notification (user_id, read_at);
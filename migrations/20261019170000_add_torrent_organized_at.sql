@@ -0,0 +1 @@
+ALTER TABLE torrent ADD COLUMN organized_at DATETIME;
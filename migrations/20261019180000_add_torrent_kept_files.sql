@@ -0,0 +1 @@
+ALTER TABLE torrent ADD COLUMN kept_files TEXT;
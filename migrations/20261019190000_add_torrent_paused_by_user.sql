@@ -0,0 +1 @@
+ALTER TABLE torrent ADD COLUMN paused_by_user BOOLEAN NOT NULL DEFAULT FALSE;
@@ -5,6 +5,8 @@ use actix_web::http::StatusCode;
 pub enum AuthError {
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Forbidden")]
+    Forbidden,
     #[error("Invalid credentials")]
     InvalidCredentials,
     #[error("Failed to create token")]
@@ -17,18 +19,25 @@ pub enum AuthError {
     FailedToRequestUserInfo,
     #[error("Invalid provider")]
     InvalidProvider,
+    #[error("Invalid stream token")]
+    InvalidStreamToken,
+    #[error("Stream token expired")]
+    StreamTokenExpired,
 }
 
 impl ApiErrorImpl for AuthError {
     fn get_codes(&self) -> (StatusCode, &str) {
         match self {
             AuthError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            AuthError::Forbidden => (StatusCode::FORBIDDEN, "forbidden"),
             AuthError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "invalid_credentials"),
             AuthError::FailedToCreateToken => (StatusCode::INTERNAL_SERVER_ERROR, "failed_to_create_token"),
             AuthError::FailedToValidateToken => (StatusCode::INTERNAL_SERVER_ERROR, "failed_to_validate_token"),
             AuthError::FailedToRequestToken => (StatusCode::INTERNAL_SERVER_ERROR, "failed_to_request_token"),
             AuthError::FailedToRequestUserInfo => (StatusCode::INTERNAL_SERVER_ERROR, "failed_to_request_user_info"),
             AuthError::InvalidProvider => (StatusCode::INTERNAL_SERVER_ERROR, "invalid_provider"),
+            AuthError::InvalidStreamToken => (StatusCode::UNAUTHORIZED, "invalid_stream_token"),
+            AuthError::StreamTokenExpired => (StatusCode::UNAUTHORIZED, "stream_token_expired"),
         }
     }
 }
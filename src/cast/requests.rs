@@ -0,0 +1,31 @@
+use apistos::ApiComponent;
+use garde::Validate;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema)]
+pub struct ListRenderers {
+    pub refresh: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct CastMedia {
+    #[garde(length(min = 40, max = 40))]
+    pub info_hash: String,
+    #[garde(skip)]
+    pub file_idx: usize,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct CastPlay {
+    /// Torrent file to load, playback of the current media resumes when unset
+    #[garde(dive)]
+    pub media: Option<CastMedia>,
+    #[garde(length(max = 255))]
+    pub title: Option<String>,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema)]
+pub struct CastSeek {
+    pub position: u64,
+}
@@ -0,0 +1,181 @@
+use crate::auth::error::AuthError;
+use crate::cast::requests::{CastPlay, CastSeek, ListRenderers};
+use crate::error::ApiError;
+use crate::infrastructure::authorization::security::{GetUserFromSession, Security};
+use crate::infrastructure::authorization::stream::StreamSigner;
+use crate::infrastructure::dlna::Renderer;
+use crate::infrastructure::models::user::User;
+use crate::infrastructure::torrent::{get_torrent_file, get_torrent_handle};
+use crate::state::ApplicationState;
+use crate::torrents::ensure_can_manage;
+use actix_web::web;
+use apistos::actix::NoContent;
+use apistos::api_operation;
+use apistos::web::{get, post, resource, scope, ServiceConfig};
+use garde::Validate;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tracing::instrument;
+
+pub fn config_cast(cfg: &mut ServiceConfig) {
+    cfg.service(
+        scope("/cast").service(
+            scope("/renderers")
+                .service(resource("").route(get().to(list_renderers)))
+                .service(
+                    scope("/{renderer_id}")
+                        .service(resource("/play").route(post().to(cast_play)))
+                        .service(resource("/pause").route(post().to(cast_pause)))
+                        .service(resource("/seek").route(post().to(cast_seek)))
+                        .service(resource("/stop").route(post().to(cast_stop))),
+                ),
+        ),
+    );
+}
+
+/// Only the user who loaded the media playing on a renderer, or an admin, may control it
+fn ensure_can_control(state: &ApplicationState, renderer: &Renderer, user: &User) -> Result<(), ApiError> {
+    match state.renderer_control().caster(renderer) {
+        Some(caster) if caster == user.id => Ok(()),
+        _ if user.is_admin() => Ok(()),
+        _ => Err(AuthError::Forbidden.into()),
+    }
+}
+
+#[api_operation(
+    tag = "cast",
+    operation_id = "list_renderers",
+    summary = "List the media renderers found on the local network"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn list_renderers(
+    query: web::Query<ListRenderers>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<Vec<Renderer>>, ApiError> {
+    security.get_user(&pool).await?;
+
+    let renderer_control = state.renderer_control();
+    let renderers = match query.refresh.unwrap_or(false) || renderer_control.renderers().is_empty() {
+        true => renderer_control.discover().await?,
+        false => renderer_control.renderers(),
+    };
+
+    Ok(web::Json(renderers))
+}
+
+#[api_operation(
+    tag = "cast",
+    operation_id = "cast_play",
+    summary = "Start playback on a renderer, loading the given torrent file first if any"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn cast_play(
+    path: web::Path<String>,
+    body: web::Json<CastPlay>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    let user = security.get_user(&pool).await?;
+
+    let renderer_control = state.renderer_control();
+    let renderer = renderer_control.get(&path.into_inner())?;
+
+    match body.media {
+        Some(media) => {
+            // Taking over a renderer another user is casting to is the same as controlling their playback
+            if renderer_control.caster(&renderer).is_some() {
+                ensure_can_control(&state, &renderer, &user)?;
+            }
+            ensure_can_manage(&pool, &user, &media.info_hash).await?;
+
+            let handle = get_torrent_handle(state.manager(), &media.info_hash)?;
+            let file = get_torrent_file(state.download_dir(), &handle, media.file_idx)?;
+
+            // Renderers cannot send the session cookie, the url must be absolute and carry its own token
+            let info_hash = handle.info_hash().as_string();
+            let token = state
+                .stream_signer()
+                .sign(&StreamSigner::file_resource(&info_hash, media.file_idx), Some(user.id));
+            let url = format!(
+                "{}/api/torrents/{}/files/{}/stream?token={}",
+                state.lan_base_url(),
+                info_hash,
+                media.file_idx,
+                urlencoding::encode(&token)
+            );
+
+            let mime = mime_guess::from_path(&file.name).first_or_octet_stream().to_string();
+            let title = body.title.unwrap_or(file.name);
+
+            renderer_control.load(&renderer, &url, &title, &mime).await?;
+            renderer_control.set_caster(&renderer, Some(user.id));
+        }
+        None => ensure_can_control(&state, &renderer, &user)?,
+    }
+    renderer_control.play(&renderer).await?;
+
+    Ok(NoContent)
+}
+
+#[api_operation(tag = "cast", operation_id = "cast_pause", summary = "Pause playback on a renderer")]
+#[instrument(skip(security, pool, state))]
+pub async fn cast_pause(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    let renderer = state.renderer_control().get(&path.into_inner())?;
+    ensure_can_control(&state, &renderer, &user)?;
+    state.renderer_control().pause(&renderer).await?;
+
+    Ok(NoContent)
+}
+
+#[api_operation(
+    tag = "cast",
+    operation_id = "cast_seek",
+    summary = "Seek to a position in seconds on a renderer"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn cast_seek(
+    path: web::Path<String>,
+    body: web::Json<CastSeek>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    let renderer = state.renderer_control().get(&path.into_inner())?;
+    ensure_can_control(&state, &renderer, &user)?;
+    state.renderer_control().seek(&renderer, body.position).await?;
+
+    Ok(NoContent)
+}
+
+#[api_operation(tag = "cast", operation_id = "cast_stop", summary = "Stop playback on a renderer")]
+#[instrument(skip(security, pool, state))]
+pub async fn cast_stop(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    let renderer = state.renderer_control().get(&path.into_inner())?;
+    ensure_can_control(&state, &renderer, &user)?;
+    state.renderer_control().stop(&renderer).await?;
+    state.renderer_control().set_caster(&renderer, None);
+
+    Ok(NoContent)
+}
@@ -1,5 +1,10 @@
+use crate::infrastructure::torrent::parse_schedule;
+use crate::utils::net::IpNetwork;
+use apistos::ApiComponent;
 use dotenvy::dotenv;
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
 use std::path::PathBuf;
 
 #[cfg(test)]
@@ -12,7 +17,27 @@ fn load_env() {
     dotenv().ok();
 }
 
-#[derive(Debug, Default, Clone, Deserialize, PartialEq, Eq)]
+/// What happens when a new torrent does not fit in the disk quota
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPolicy {
+    #[default]
+    Reject,
+    /// Delete the least recently watched finished torrents until it fits
+    Evict,
+}
+
+/// What happens to a finished torrent once its seeding rules are met
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ApiComponent, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SeedAction {
+    #[default]
+    Pause,
+    /// Remove the torrent from the session, the downloaded files are kept
+    Remove,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
 pub struct Config {
     pub api_version: String,
     pub host: String,
@@ -21,9 +46,46 @@ pub struct Config {
     pub telemetry_collector_endpoint: Option<String>,
     pub database_url: String,
     pub download_dir: PathBuf,
+    pub library_dir: PathBuf,
     pub tmdb_api_key: String,
     pub prowlarr_api_key: String,
     pub prowlarr_api_url: String,
+    pub dlna_enabled: bool,
+    pub dlna_friendly_name: String,
+    pub dlna_base_url: Option<String>,
+    /// Networks allowed to browse and play from the media server
+    pub dlna_allowed_networks: Vec<String>,
+    pub stream_token_secret: Option<String>,
+    pub stream_token_ttl: u64,
+    pub max_streams_per_user: usize,
+    pub streaming_readahead_mb: u64,
+    pub unwatched_retention_days: u32,
+    pub disk_quota_gb: u64,
+    pub disk_quota_policy: QuotaPolicy,
+    pub download_limit_kib: u32,
+    pub upload_limit_kib: u32,
+    pub rate_limit_unlimited_hours: Option<String>,
+    pub seed_ratio_limit: f64,
+    pub seed_time_limit_hours: u64,
+    pub seed_limit_action: SeedAction,
+    pub max_active_downloads: usize,
+    /// `6881-6891`, an ephemeral port is used when unset
+    pub listen_port_range: Option<String>,
+    pub dht_enabled: bool,
+    pub upnp_enabled: bool,
+    pub peer_connect_timeout_secs: u64,
+    /// Peer connections open at once across every torrent, 0 for unlimited
+    pub max_peer_connections: usize,
+    /// Added to the trackers of every magnet link
+    pub trackers: Vec<String>,
+    pub trackers_enabled: bool,
+    /// Overrides the announce interval of the trackers, 0 keeps theirs
+    pub tracker_interval_secs: u64,
+    pub socks_proxy_url: Option<String>,
+    /// Receive the torrent lifecycle events as JSON POST requests
+    pub webhook_urls: Vec<String>,
+    /// Key of the HMAC-SHA256 signature of the webhook bodies
+    pub webhook_secret: Option<String>,
 }
 
 impl Config {
@@ -35,6 +97,9 @@ impl Config {
                 config::Environment::default()
                     .list_separator(",")
                     .with_list_parse_key("origins")
+                    .with_list_parse_key("dlna_allowed_networks")
+                    .with_list_parse_key("trackers")
+                    .with_list_parse_key("webhook_urls")
                     .try_parsing(true),
             )
             .set_default("api_version", env!("CARGO_PKG_VERSION"))
@@ -43,12 +108,178 @@ impl Config {
             .unwrap()
             .set_default("port", 3000)
             .unwrap()
+            .set_default("library_dir", "./library")
+            .unwrap()
+            .set_default("dlna_enabled", false)
+            .unwrap()
+            .set_default("dlna_friendly_name", "Hypertube")
+            .unwrap()
+            .set_default("dlna_allowed_networks", Vec::<String>::new())
+            .unwrap()
+            .set_default("stream_token_ttl", 21600)
+            .unwrap()
+            .set_default("max_streams_per_user", 3)
+            .unwrap()
+            .set_default("streaming_readahead_mb", 64)
+            .unwrap()
+            .set_default("unwatched_retention_days", 30)
+            .unwrap()
+            .set_default("disk_quota_gb", 0)
+            .unwrap()
+            .set_default("disk_quota_policy", "reject")
+            .unwrap()
+            .set_default("download_limit_kib", 0)
+            .unwrap()
+            .set_default("upload_limit_kib", 0)
+            .unwrap()
+            .set_default("seed_ratio_limit", 0.0)
+            .unwrap()
+            .set_default("seed_time_limit_hours", 0)
+            .unwrap()
+            .set_default("seed_limit_action", "pause")
+            .unwrap()
+            .set_default("max_active_downloads", 3)
+            .unwrap()
+            .set_default("dht_enabled", true)
+            .unwrap()
+            .set_default("upnp_enabled", false)
+            .unwrap()
+            .set_default("peer_connect_timeout_secs", 10)
+            .unwrap()
+            .set_default("max_peer_connections", 0)
+            .unwrap()
+            .set_default("trackers", Vec::<String>::new())
+            .unwrap()
+            .set_default("trackers_enabled", true)
+            .unwrap()
+            .set_default("tracker_interval_secs", 0)
+            .unwrap()
+            .set_default("webhook_urls", Vec::<String>::new())
+            .unwrap()
             .build()?;
 
         let cfg: Config = config.try_deserialize()?;
+        cfg.validate()?;
 
         Ok(cfg)
     }
+
+    fn validate(&self) -> Result<(), config::ConfigError> {
+        let dlna_allowed_networks = self.dlna_allowed_networks()?;
+        if self.dlna_enabled && dlna_allowed_networks.is_empty() {
+            return Err(config::ConfigError::Message(
+                "DLNA_ENABLED requires the DLNA_ALLOWED_NETWORKS renderers connect from".to_string(),
+            ));
+        }
+
+        if let Some(schedule) = &self.rate_limit_unlimited_hours {
+            parse_schedule(schedule)
+                .map_err(|err| config::ConfigError::Message(format!("Invalid RATE_LIMIT_UNLIMITED_HOURS: {}", err)))?;
+        }
+
+        self.listen_port_range()?;
+        self.tracker_urls()?;
+        self.webhook_urls()?;
+
+        if self.upnp_enabled && self.listen_port_range.is_none() {
+            return Err(config::ConfigError::Message(
+                "UPNP_ENABLED requires a LISTEN_PORT_RANGE to forward".to_string(),
+            ));
+        }
+
+        if self.peer_connect_timeout_secs == 0 {
+            return Err(config::ConfigError::Message(
+                "PEER_CONNECT_TIMEOUT_SECS must be greater than 0".to_string(),
+            ));
+        }
+
+        if !self.trackers_enabled && !self.dht_enabled {
+            return Err(config::ConfigError::Message(
+                "TRACKERS_ENABLED and DHT_ENABLED cannot both be false, no peer could be found".to_string(),
+            ));
+        }
+
+        if let Some(proxy) = &self.socks_proxy_url {
+            let url = reqwest::Url::parse(proxy)
+                .map_err(|err| config::ConfigError::Message(format!("Invalid SOCKS_PROXY_URL {}: {}", proxy, err)))?;
+            if url.scheme() != "socks5" || url.host_str().is_none() || url.port().is_none() {
+                return Err(config::ConfigError::Message(format!(
+                    "Invalid SOCKS_PROXY_URL {}: expected socks5://[user:password@]host:port",
+                    proxy
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn dlna_allowed_networks(&self) -> Result<Vec<IpNetwork>, config::ConfigError> {
+        self.dlna_allowed_networks
+            .iter()
+            .filter(|network| !network.trim().is_empty())
+            .map(|network| network.parse().map_err(config::ConfigError::Message))
+            .collect()
+    }
+
+    /// Inclusive range of ports librqbit may listen on
+    pub fn listen_port_range(&self) -> Result<Option<Range<u16>>, config::ConfigError> {
+        let Some(range) = &self.listen_port_range else {
+            return Ok(None);
+        };
+
+        let invalid = || {
+            config::ConfigError::Message(format!(
+                "Invalid LISTEN_PORT_RANGE {}: expected start-end, like 6881-6891",
+                range
+            ))
+        };
+        let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+        let start = start.trim().parse::<u16>().map_err(|_| invalid())?;
+        let end = end.trim().parse::<u16>().map_err(|_| invalid())?;
+        if start == 0 || start > end || end == u16::MAX {
+            return Err(invalid());
+        }
+
+        Ok(Some(start..end + 1))
+    }
+
+    pub fn webhook_urls(&self) -> Result<Vec<reqwest::Url>, config::ConfigError> {
+        self.webhook_urls
+            .iter()
+            .map(|url| url.trim())
+            .filter(|url| !url.is_empty())
+            .map(|url| {
+                reqwest::Url::parse(url)
+                    .ok()
+                    .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+                    .ok_or_else(|| {
+                        config::ConfigError::Message(format!(
+                            "Invalid webhook url {}: expected an http or https url",
+                            url
+                        ))
+                    })
+            })
+            .collect()
+    }
+
+    pub fn tracker_urls(&self) -> Result<Vec<reqwest::Url>, config::ConfigError> {
+        self.trackers
+            .iter()
+            .map(|tracker| tracker.trim())
+            .filter(|tracker| !tracker.is_empty())
+            .map(|tracker| {
+                reqwest::Url::parse(tracker)
+                    .ok()
+                    .filter(|url| matches!(url.scheme(), "http" | "https" | "udp"))
+                    .ok_or_else(|| {
+                        config::ConfigError::Message(format!(
+                            "Invalid tracker {}: expected an http, https or udp url",
+                            tracker
+                        ))
+                    })
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Default, Clone, Deserialize, PartialEq, Eq)]
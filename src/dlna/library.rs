@@ -0,0 +1,275 @@
+use crate::infrastructure::dlna::soap::escape_xml;
+use crate::infrastructure::torrent::{get_torrent_file, get_torrent_handles, is_file_selected};
+use crate::state::ApplicationState;
+use crate::torrents::create_torrent_playlist_items;
+use regex::Regex;
+use std::sync::LazyLock;
+
+pub const ROOT_ID: &str = "0";
+const MOVIES_ID: &str = "movies";
+const SHOWS_ID: &str = "shows";
+
+static EPISODE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(?P<show>.+?)[\s._-]+S(?P<season>\d{1,2})[\s._-]*E(?P<episode>\d{1,3})").unwrap()
+});
+static MOVIE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?P<title>.+?)[\s._(\[-]+(?P<year>(?:19|20)\d{2})\b").unwrap());
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaKind {
+    Movie,
+    Episode { show: String, season: u32, episode: u32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaEntry {
+    pub id: String,
+    pub info_hash: String,
+    pub file_idx: usize,
+    pub title: String,
+    pub mime: String,
+    pub size: u64,
+    pub kind: MediaKind,
+}
+
+pub enum LibraryObject {
+    Container {
+        id: String,
+        parent_id: String,
+        title: String,
+        child_count: usize,
+    },
+    Item {
+        parent_id: String,
+        entry: MediaEntry,
+    },
+}
+
+fn clean_name(name: &str) -> String {
+    name.replace(['.', '_'], " ").trim().to_string()
+}
+
+fn show_id(show: &str) -> String {
+    format!("show:{}", urlencoding::encode(&show.to_lowercase()))
+}
+
+/// Guess whether a release is a movie or an episode from its file name
+fn parse_release_name(file_name: &str) -> (String, MediaKind) {
+    let stem = file_name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(file_name);
+
+    if let Some(cap) = EPISODE_RE.captures(stem) {
+        let show = clean_name(&cap["show"]);
+        let season = cap["season"].parse().unwrap_or_default();
+        let episode = cap["episode"].parse().unwrap_or_default();
+
+        return (
+            format!("{} S{:02}E{:02}", show, season, episode),
+            MediaKind::Episode { show, season, episode },
+        );
+    }
+
+    match MOVIE_RE.captures(stem) {
+        Some(cap) => (
+            format!("{} ({})", clean_name(&cap["title"]), &cap["year"]),
+            MediaKind::Movie,
+        ),
+        None => (clean_name(stem), MediaKind::Movie),
+    }
+}
+
+/// Playable selected files of every finished torrent in the session
+pub fn collect_library(state: &ApplicationState) -> Vec<MediaEntry> {
+    let handles = get_torrent_handles(state.manager());
+
+    let mut entries = handles
+        .iter()
+        .filter(|handle| handle.stats().finished)
+        .flat_map(|handle| {
+            let info_hash = handle.info_hash().as_string();
+            let items = create_torrent_playlist_items(handle).unwrap_or_default();
+
+            items.into_iter().filter_map(move |(file_idx, _)| {
+                // A finished torrent only has the selected files, the others would never play
+                if !is_file_selected(handle, file_idx) {
+                    return None;
+                }
+
+                let file = get_torrent_file(state.download_dir(), handle, file_idx).ok()?;
+                let (title, kind) = parse_release_name(&file.name);
+                let mime = mime_guess::from_path(&file.name).first_or_octet_stream().to_string();
+
+                Some(MediaEntry {
+                    id: format!("item:{}:{}", info_hash, file_idx),
+                    info_hash: info_hash.clone(),
+                    file_idx,
+                    title,
+                    mime,
+                    size: file.length,
+                    kind,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|left, right| left.title.cmp(&right.title));
+
+    entries
+}
+
+fn container(id: String, parent_id: &str, title: String, child_count: usize) -> LibraryObject {
+    LibraryObject::Container {
+        id,
+        parent_id: parent_id.to_string(),
+        title,
+        child_count,
+    }
+}
+
+fn shows(entries: &[MediaEntry]) -> Vec<(String, usize)> {
+    let mut shows: Vec<(String, usize)> = Vec::new();
+    for entry in entries {
+        if let MediaKind::Episode { show, .. } = &entry.kind {
+            match shows.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(show)) {
+                Some((_, count)) => *count += 1,
+                None => shows.push((show.clone(), 1)),
+            }
+        }
+    }
+    shows.sort();
+
+    shows
+}
+
+fn parent_id(entry: &MediaEntry) -> String {
+    match &entry.kind {
+        MediaKind::Movie => MOVIES_ID.to_string(),
+        MediaKind::Episode { show, .. } => show_id(show),
+    }
+}
+
+fn root_containers(entries: &[MediaEntry]) -> Vec<LibraryObject> {
+    let movies = entries.iter().filter(|entry| entry.kind == MediaKind::Movie).count();
+
+    vec![
+        container(MOVIES_ID.to_string(), ROOT_ID, "Movies".to_string(), movies),
+        container(SHOWS_ID.to_string(), ROOT_ID, "Shows".to_string(), shows(entries).len()),
+    ]
+}
+
+/// Children of a container, `None` when the object does not exist
+pub fn children(entries: &[MediaEntry], object_id: &str) -> Option<Vec<LibraryObject>> {
+    match object_id {
+        ROOT_ID => Some(root_containers(entries)),
+        SHOWS_ID => Some(
+            shows(entries)
+                .into_iter()
+                .map(|(show, count)| container(show_id(&show), SHOWS_ID, show, count))
+                .collect(),
+        ),
+        MOVIES_ID => Some(
+            entries
+                .iter()
+                .filter(|entry| entry.kind == MediaKind::Movie)
+                .map(|entry| LibraryObject::Item {
+                    parent_id: MOVIES_ID.to_string(),
+                    entry: entry.clone(),
+                })
+                .collect(),
+        ),
+        id if id.starts_with("show:") => {
+            let mut episodes = entries
+                .iter()
+                .filter(|entry| parent_id(entry) == id)
+                .collect::<Vec<_>>();
+            if episodes.is_empty() {
+                return None;
+            }
+            episodes.sort_by_key(|entry| match entry.kind {
+                MediaKind::Episode { season, episode, .. } => (season, episode),
+                MediaKind::Movie => (0, 0),
+            });
+
+            Some(
+                episodes
+                    .into_iter()
+                    .map(|entry| LibraryObject::Item {
+                        parent_id: id.to_string(),
+                        entry: entry.clone(),
+                    })
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// The object itself, used by `BrowseMetadata`
+pub fn metadata(entries: &[MediaEntry], object_id: &str) -> Option<LibraryObject> {
+    match object_id {
+        ROOT_ID => Some(container(ROOT_ID.to_string(), "-1", "Hypertube".to_string(), 2)),
+        MOVIES_ID | SHOWS_ID => root_containers(entries).into_iter().find(|object| match object {
+            LibraryObject::Container { id, .. } => id == object_id,
+            LibraryObject::Item { .. } => false,
+        }),
+        id if id.starts_with("show:") => children(entries, SHOWS_ID)?.into_iter().find(|object| match object {
+            LibraryObject::Container { id, .. } => id == object_id,
+            LibraryObject::Item { .. } => false,
+        }),
+        id => entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| LibraryObject::Item {
+                parent_id: parent_id(entry),
+                entry: entry.clone(),
+            }),
+    }
+}
+
+/// Render objects as a DIDL-Lite document, items get one resource for direct play and one for the transcoder
+pub fn to_didl(objects: &[LibraryObject], base_url: &str) -> String {
+    let body = objects
+        .iter()
+        .map(|object| match object {
+            LibraryObject::Container {
+                id,
+                parent_id,
+                title,
+                child_count,
+            } => format!(
+                r#"<container id="{}" parentID="{}" restricted="1" childCount="{}"><dc:title>{}</dc:title><upnp:class>object.container.storageFolder</upnp:class></container>"#,
+                escape_xml(id),
+                escape_xml(parent_id),
+                child_count,
+                escape_xml(title)
+            ),
+            LibraryObject::Item { parent_id, entry } => {
+                let class = match entry.kind {
+                    MediaKind::Movie => "object.item.videoItem.movie",
+                    MediaKind::Episode { .. } => "object.item.videoItem",
+                };
+                // Tokens and transcode sessions are only handed out once a renderer requests a resource
+                let direct_url = format!("{}/api/dlna/media/{}/{}", base_url, entry.info_hash, entry.file_idx);
+                let transcode_url = format!(
+                    "{}/api/dlna/media/{}/{}/start.mpd",
+                    base_url, entry.info_hash, entry.file_idx
+                );
+
+                format!(
+                    r#"<item id="{}" parentID="{}" restricted="1"><dc:title>{}</dc:title><upnp:class>{}</upnp:class><res protocolInfo="http-get:*:{}:DLNA.ORG_OP=01;DLNA.ORG_CI=0" size="{}">{}</res><res protocolInfo="http-get:*:application/dash+xml:DLNA.ORG_OP=00;DLNA.ORG_CI=1">{}</res></item>"#,
+                    escape_xml(&entry.id),
+                    escape_xml(parent_id),
+                    escape_xml(&entry.title),
+                    class,
+                    entry.mime,
+                    entry.size,
+                    escape_xml(&direct_url),
+                    escape_xml(&transcode_url)
+                )
+            }
+        })
+        .collect::<String>();
+
+    format!(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">{}</DIDL-Lite>"#,
+        body
+    )
+}
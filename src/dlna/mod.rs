@@ -0,0 +1,4 @@
+mod library;
+mod routes;
+
+pub use routes::config_dlna;
@@ -0,0 +1,298 @@
+use crate::dlna::library::{children, collect_library, metadata, to_didl, MediaEntry};
+use crate::infrastructure::authorization::stream::StreamSigner;
+use crate::infrastructure::dlna::soap::{action_response, fault, get_element, parse_soap_action};
+use crate::infrastructure::dlna::{
+    CONNECTION_MANAGER_SCPD, CONNECTION_MANAGER_TYPE, CONTENT_DIRECTORY_SCPD, CONTENT_DIRECTORY_TYPE,
+};
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::torrent::{get_torrent_file, get_torrent_handle};
+use crate::state::ApplicationState;
+use crate::torrents::resume_for_playback;
+use actix_web::http::header::{CONTENT_TYPE, LOCATION};
+use actix_web::{web, HttpRequest, HttpResponse};
+use apistos::api_operation;
+use apistos::web::{get, post, resource, scope, ServiceConfig};
+use sqlx::SqlitePool;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use tracing::instrument;
+
+const XML_CONTENT_TYPE: &str = r#"text/xml; charset="utf-8""#;
+
+// UPnP error codes, see UPnP Device Architecture 1.0 section 3.2.2
+const INVALID_ACTION: u16 = 401;
+const NO_SUCH_OBJECT: u16 = 701;
+
+pub fn config_dlna(cfg: &mut ServiceConfig) {
+    cfg.service(
+        scope("/dlna")
+            .service(resource("/description.xml").route(get().to(get_description)))
+            .service(resource("/content_directory.xml").route(get().to(get_content_directory_scpd)))
+            .service(resource("/connection_manager.xml").route(get().to(get_connection_manager_scpd)))
+            .service(resource("/content_directory/control").route(post().to(content_directory_control)))
+            .service(resource("/connection_manager/control").route(post().to(connection_manager_control)))
+            .service(resource("/media/{hash}/{file_idx}").route(get().to(get_media_stream)))
+            .service(resource("/media/{hash}/{file_idx}/start.mpd").route(get().to(get_media_manifest))),
+    );
+}
+
+fn xml_response(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, XML_CONTENT_TYPE))
+        .body(body)
+}
+
+fn fault_response(error_code: u16, description: &str) -> HttpResponse {
+    HttpResponse::InternalServerError()
+        .insert_header((CONTENT_TYPE, XML_CONTENT_TYPE))
+        .body(fault(error_code, description))
+}
+
+/// Renderers cannot log in, the media server is only exposed to DLNA_ALLOWED_NETWORKS instead
+fn is_allowed_peer(req: &HttpRequest, state: &ApplicationState) -> bool {
+    req.peer_addr()
+        .map(|addr| state.dlna_device().allows(addr.ip()))
+        .unwrap_or(false)
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().finish()
+}
+
+fn redirect(location: String) -> HttpResponse {
+    HttpResponse::TemporaryRedirect()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+/// Changes whenever an item enters or leaves the library, so renderers know to drop their cached listings
+fn system_update_id(entries: &[MediaEntry]) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    for entry in entries {
+        entry.id.hash(&mut hasher);
+    }
+
+    hasher.finish() as u32
+}
+
+fn soap_action(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("SOAPACTION")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_soap_action)
+        .map(|(_, action)| action)
+}
+
+#[api_operation(skip)]
+pub async fn get_description(req: HttpRequest, state: web::Data<Arc<ApplicationState>>) -> HttpResponse {
+    if !is_allowed_peer(&req, &state) {
+        return forbidden();
+    }
+
+    xml_response(state.dlna_device().description_xml())
+}
+
+#[api_operation(skip)]
+pub async fn get_content_directory_scpd(req: HttpRequest, state: web::Data<Arc<ApplicationState>>) -> HttpResponse {
+    if !is_allowed_peer(&req, &state) {
+        return forbidden();
+    }
+
+    xml_response(CONTENT_DIRECTORY_SCPD.to_string())
+}
+
+#[api_operation(skip)]
+pub async fn get_connection_manager_scpd(req: HttpRequest, state: web::Data<Arc<ApplicationState>>) -> HttpResponse {
+    if !is_allowed_peer(&req, &state) {
+        return forbidden();
+    }
+
+    xml_response(CONNECTION_MANAGER_SCPD.to_string())
+}
+
+#[api_operation(skip)]
+#[instrument(skip(req, body, state))]
+pub async fn content_directory_control(
+    req: HttpRequest,
+    body: String,
+    state: web::Data<Arc<ApplicationState>>,
+) -> HttpResponse {
+    if !is_allowed_peer(&req, &state) {
+        return forbidden();
+    }
+
+    let Some(action) = soap_action(&req) else {
+        return fault_response(INVALID_ACTION, "Invalid Action");
+    };
+
+    tracing::debug!("ContentDirectory action {}", action);
+
+    let entries = collect_library(&state);
+    let update_id = system_update_id(&entries).to_string();
+
+    match action.as_str() {
+        "Browse" => {
+            let object_id = get_element(&body, "ObjectID").unwrap_or_default();
+            let browse_flag = get_element(&body, "BrowseFlag").unwrap_or_default();
+            let starting_index = get_element(&body, "StartingIndex")
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(0);
+            let requested_count = get_element(&body, "RequestedCount")
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|count| *count > 0)
+                .unwrap_or(usize::MAX);
+
+            let objects = match browse_flag.as_str() {
+                "BrowseMetadata" => metadata(&entries, &object_id).map(|object| vec![object]),
+                _ => children(&entries, &object_id),
+            };
+            let Some(objects) = objects else {
+                return fault_response(NO_SUCH_OBJECT, "No such object");
+            };
+
+            let total = objects.len();
+            let page = objects
+                .into_iter()
+                .skip(starting_index)
+                .take(requested_count)
+                .collect::<Vec<_>>();
+
+            xml_response(action_response(
+                CONTENT_DIRECTORY_TYPE,
+                &action,
+                &[
+                    ("Result", to_didl(&page, &state.dlna_device().base_url)),
+                    ("NumberReturned", page.len().to_string()),
+                    ("TotalMatches", total.to_string()),
+                    ("UpdateID", update_id),
+                ],
+            ))
+        }
+        "GetSystemUpdateID" => xml_response(action_response(CONTENT_DIRECTORY_TYPE, &action, &[("Id", update_id)])),
+        "GetSearchCapabilities" => xml_response(action_response(
+            CONTENT_DIRECTORY_TYPE,
+            &action,
+            &[("SearchCaps", String::new())],
+        )),
+        "GetSortCapabilities" => xml_response(action_response(
+            CONTENT_DIRECTORY_TYPE,
+            &action,
+            &[("SortCaps", String::new())],
+        )),
+        _ => fault_response(INVALID_ACTION, "Invalid Action"),
+    }
+}
+
+#[api_operation(skip)]
+#[instrument(skip(req, state))]
+pub async fn connection_manager_control(req: HttpRequest, state: web::Data<Arc<ApplicationState>>) -> HttpResponse {
+    if !is_allowed_peer(&req, &state) {
+        return forbidden();
+    }
+
+    let Some(action) = soap_action(&req) else {
+        return fault_response(INVALID_ACTION, "Invalid Action");
+    };
+
+    match action.as_str() {
+        "GetProtocolInfo" => xml_response(action_response(
+            CONNECTION_MANAGER_TYPE,
+            &action,
+            &[
+                (
+                    "Source",
+                    "http-get:*:video/mp4:*,http-get:*:video/x-matroska:*,http-get:*:video/x-msvideo:*,http-get:*:application/dash+xml:*"
+                        .to_string(),
+                ),
+                ("Sink", String::new()),
+            ],
+        )),
+        "GetCurrentConnectionIDs" => xml_response(action_response(
+            CONNECTION_MANAGER_TYPE,
+            &action,
+            &[("ConnectionIDs", "0".to_string())],
+        )),
+        _ => fault_response(INVALID_ACTION, "Invalid Action"),
+    }
+}
+
+/// Direct play resource of a library item, redirecting to the file stream with an anonymous token
+#[api_operation(skip)]
+#[instrument(skip(req, state))]
+pub async fn get_media_stream(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> HttpResponse {
+    if !is_allowed_peer(&req, &state) {
+        return forbidden();
+    }
+
+    let (hash, file_idx) = path.into_inner();
+
+    let Ok(handle) = get_torrent_handle(state.manager(), &hash) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if get_torrent_file(state.download_dir(), &handle, file_idx).is_err() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let info_hash = handle.info_hash().as_string();
+    let token = state
+        .stream_signer()
+        .sign(&StreamSigner::file_resource(&info_hash, file_idx), None);
+
+    redirect(format!(
+        "{}/api/torrents/{}/files/{}/stream?token={}",
+        state.dlna_device().base_url,
+        info_hash,
+        file_idx,
+        urlencoding::encode(&token)
+    ))
+}
+
+/// Transcode resource of a library item, redirecting to the manifest of a shared session created on first play
+#[api_operation(skip)]
+#[instrument(skip(req, pool, state))]
+pub async fn get_media_manifest(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> HttpResponse {
+    if !is_allowed_peer(&req, &state) {
+        return forbidden();
+    }
+
+    let (hash, file_idx) = path.into_inner();
+
+    let Ok(handle) = get_torrent_handle(state.manager(), &hash) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let Ok(file) = get_torrent_file(state.download_dir(), &handle, file_idx) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let info_hash = handle.info_hash().as_string();
+    if let Err(err) = resume_for_playback(&state, &handle).await {
+        tracing::warn!("Could not resume torrent {} for playback: {:?}", info_hash, err);
+    }
+    let session = state
+        .transcode_sessions()
+        .get_or_create_shared(&info_hash, file_idx, file.path);
+
+    // Direct play goes through the file stream, which records the playback itself
+    if let Err(err) = Torrent::mark_watched(&pool, &info_hash).await {
+        tracing::warn!("Could not mark torrent {} as watched: {}", info_hash, err);
+    }
+    let token = state
+        .stream_signer()
+        .sign(&StreamSigner::session_resource(&session.id), None);
+
+    redirect(format!(
+        "{}/api/transcode/start.mpd?session_id={}&token={}",
+        state.dlna_device().base_url,
+        session.id,
+        urlencoding::encode(&token)
+    ))
+}
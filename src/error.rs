@@ -1,8 +1,13 @@
 use crate::auth::error::AuthError;
+use crate::infrastructure::dlna::error::DlnaError;
+use crate::infrastructure::export::error::ExportError;
 use crate::infrastructure::indexers::error::IndexerError;
 use crate::infrastructure::metadata::error::MetadataError;
 use crate::infrastructure::torrent::error::TorrentError as LibTorrentError;
+use crate::notifications::error::NotificationError;
+use crate::playback::error::PlaybackError;
 use crate::torrents::error::TorrentError;
+use crate::transcode::error::TranscodeError;
 use crate::users::error::UserError;
 use crate::{ApiErrorImpl, ErrorResponse};
 use actix_web::{http::StatusCode, HttpResponse, ResponseError};
@@ -48,6 +53,16 @@ pub enum ApiError {
     AuthError(#[from] AuthError),
     #[error(transparent)]
     UserError(#[from] UserError),
+    #[error(transparent)]
+    ExportError(#[from] ExportError),
+    #[error(transparent)]
+    TranscodeError(#[from] TranscodeError),
+    #[error(transparent)]
+    DlnaError(#[from] DlnaError),
+    #[error(transparent)]
+    PlaybackError(#[from] PlaybackError),
+    #[error(transparent)]
+    NotificationError(#[from] NotificationError),
 }
 
 impl ApiErrorImpl for ApiError {
@@ -67,6 +82,11 @@ impl ApiErrorImpl for ApiError {
             ApiError::TorrentError(err) => err.get_codes(),
             ApiError::AuthError(err) => err.get_codes(),
             ApiError::UserError(err) => err.get_codes(),
+            ApiError::ExportError(err) => err.get_codes(),
+            ApiError::TranscodeError(err) => err.get_codes(),
+            ApiError::DlnaError(err) => err.get_codes(),
+            ApiError::PlaybackError(err) => err.get_codes(),
+            ApiError::NotificationError(err) => err.get_codes(),
         }
     }
 }
@@ -0,0 +1,4 @@
+mod requests;
+mod routes;
+
+pub use routes::config_exports;
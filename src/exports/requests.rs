@@ -0,0 +1,16 @@
+use apistos::ApiComponent;
+use garde::Validate;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct CreateExport {
+    #[garde(length(min = 40, max = 40))]
+    pub info_hash: String,
+    #[garde(skip)]
+    pub file_idx: usize,
+    #[garde(skip)]
+    pub audio_track: Option<usize>,
+    #[garde(skip)]
+    pub subtitle_track: Option<usize>,
+}
@@ -0,0 +1,123 @@
+use crate::error::ApiError;
+use crate::exports::requests::CreateExport;
+use crate::infrastructure::authorization::security::{GetUserFromSession, Security};
+use crate::infrastructure::export::error::ExportError;
+use crate::infrastructure::export::{ExportJob, ExportOptions, ExportState};
+use crate::infrastructure::torrent::{get_torrent_file, get_torrent_handle};
+use crate::state::ApplicationState;
+use crate::torrents::ensure_can_manage;
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use apistos::api_operation;
+use apistos::web::{get, post, resource, scope, ServiceConfig};
+use garde::Validate;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tracing::instrument;
+use uuid::Uuid;
+
+pub fn config_exports(cfg: &mut ServiceConfig) {
+    cfg.service(
+        scope("/exports")
+            .service(resource("").route(post().to(create_export)))
+            .service(
+                scope("/{export_id}")
+                    .service(resource("").route(get().to(get_export)))
+                    .service(resource("/download").route(get().to(download_export))),
+            ),
+    );
+}
+
+#[api_operation(
+    tag = "exports",
+    operation_id = "create_export",
+    summary = "Export a downloaded file to a single mp4"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn create_export(
+    body: web::Json<CreateExport>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<ExportJob>, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    let user = security.get_user(&pool).await?;
+    ensure_can_manage(&pool, &user, &body.info_hash).await?;
+
+    let handle = get_torrent_handle(state.manager(), &body.info_hash)?;
+    let file = get_torrent_file(state.download_dir(), &handle, body.file_idx)?;
+
+    let job = state
+        .export_manager()
+        .start(
+            user.id,
+            body.info_hash,
+            body.file_idx,
+            file,
+            ExportOptions {
+                audio_track: body.audio_track.unwrap_or_default(),
+                subtitle_track: body.subtitle_track,
+            },
+        )
+        .await?;
+
+    Ok(web::Json(job))
+}
+
+#[api_operation(tag = "exports", operation_id = "get_export", summary = "Get an export progress")]
+#[instrument(skip(security, pool, state))]
+pub async fn get_export(
+    path: web::Path<Uuid>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<ExportJob>, ApiError> {
+    let export_id = path.into_inner();
+    let user = security.get_user(&pool).await?;
+
+    let job = state.export_manager().get(export_id)?;
+    if job.user_id != user.id {
+        return Err(ExportError::ExportNotFound.into());
+    }
+
+    Ok(web::Json(job))
+}
+
+#[api_operation(
+    tag = "exports",
+    operation_id = "download_export",
+    summary = "Download a finished export"
+)]
+#[instrument(skip(req, security, pool, state))]
+pub async fn download_export(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<HttpResponse, ApiError> {
+    let export_id = path.into_inner();
+    let user = security.get_user(&pool).await?;
+
+    let job = state.export_manager().get(export_id)?;
+    if job.user_id != user.id {
+        return Err(ExportError::ExportNotFound.into());
+    }
+    ensure_can_manage(&pool, &user, &job.info_hash).await?;
+    if job.state != ExportState::Finished {
+        return Err(ExportError::ExportNotFinished.into());
+    }
+
+    let file = NamedFile::open_async(&job.output)
+        .await
+        .map_err(ExportError::from)?
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(job.file_name)],
+        });
+
+    Ok(file.into_response(&req))
+}
@@ -1,4 +1,5 @@
 pub mod security;
+pub mod stream;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Permission {
@@ -1,3 +1,4 @@
+use crate::auth::error::AuthError;
 use crate::error::ApiError;
 use crate::infrastructure::models::user::User;
 use actix_session::SessionExt;
@@ -28,6 +29,7 @@ impl FromRequest for Security {
 
 pub trait GetUserFromSession {
     fn get_user(&self, pool: &SqlitePool) -> impl std::future::Future<Output = Result<User, ApiError>>;
+    fn get_admin(&self, pool: &SqlitePool) -> impl std::future::Future<Output = Result<User, ApiError>>;
     fn is_authenticated(&self) -> bool;
 }
 
@@ -43,6 +45,15 @@ impl GetUserFromSession for Security {
         Err(ApiError::Unauthorized)
     }
 
+    async fn get_admin(&self, pool: &SqlitePool) -> Result<User, ApiError> {
+        let user = self.get_user(pool).await?;
+        if !user.is_admin() {
+            return Err(AuthError::Forbidden.into());
+        }
+
+        Ok(user)
+    }
+
     fn is_authenticated(&self) -> bool {
         self.session.get::<Uuid>("user_id").is_ok()
     }
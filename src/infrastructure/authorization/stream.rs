@@ -0,0 +1,155 @@
+use crate::auth::error::AuthError;
+use crate::error::ApiError;
+use crate::state::ApplicationState;
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use apistos::ApiSecurity;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use futures::future::{ready, Ready};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::sync::Arc;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const ANONYMOUS: &str = "-";
+
+/// Signs the resources served to players that cannot send the session cookie.
+///
+/// A token is `<user id>.<expiration timestamp>.<signature>`, the signature covers the resource
+/// (`session:<id>` or `file:<hash>:<index>`), the user and the expiration.
+pub struct StreamSigner {
+    secret: Vec<u8>,
+    ttl: i64,
+}
+
+impl StreamSigner {
+    pub fn new(secret: Option<String>, ttl: u64) -> Self {
+        let secret = secret.unwrap_or_else(|| {
+            tracing::warn!("No stream token secret configured, stream urls will not survive a restart");
+            format!("{}{}", Uuid::new_v4(), Uuid::new_v4())
+        });
+
+        Self {
+            secret: secret.into_bytes(),
+            ttl: ttl as i64,
+        }
+    }
+
+    pub fn session_resource(session_id: &str) -> String {
+        format!("session:{}", session_id)
+    }
+
+    pub fn file_resource(info_hash: &str, file_idx: usize) -> String {
+        format!("file:{}:{}", info_hash.to_lowercase(), file_idx)
+    }
+
+    fn mac(&self, resource: &str, user: &str, expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(format!("{}\n{}\n{}", resource, user, expires).as_bytes());
+
+        mac
+    }
+
+    pub fn sign(&self, resource: &str, user_id: Option<Uuid>) -> String {
+        let user = user_id.map(|id| id.to_string()).unwrap_or(ANONYMOUS.to_string());
+        let expires = Utc::now().timestamp() + self.ttl;
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(resource, &user, expires).finalize().into_bytes());
+
+        format!("{}.{}.{}", user, expires, signature)
+    }
+
+    /// Check a token against a resource and return the user it was minted for
+    pub fn verify(&self, resource: &str, token: &str) -> Result<Option<Uuid>, AuthError> {
+        let mut parts = token.splitn(3, '.');
+        let (Some(user), Some(expires), Some(signature)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(AuthError::InvalidStreamToken);
+        };
+
+        let expires = expires.parse::<i64>().map_err(|_| AuthError::InvalidStreamToken)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| AuthError::InvalidStreamToken)?;
+
+        self.mac(resource, user, expires)
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidStreamToken)?;
+
+        if expires < Utc::now().timestamp() {
+            return Err(AuthError::StreamTokenExpired);
+        }
+
+        match user {
+            ANONYMOUS => Ok(None),
+            user => Uuid::parse_str(user)
+                .map(Some)
+                .map_err(|_| AuthError::InvalidStreamToken),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct StreamTokenParams {
+    token: Option<String>,
+    session_id: Option<String>,
+}
+
+/// Guard for the streaming routes, the token is read from the `token` query parameter
+#[derive(ApiSecurity)]
+#[openapi_security(scheme(security_type(api_key(name = "token", api_key_in = "query"))))]
+pub struct StreamAuth {
+    pub user_id: Option<Uuid>,
+}
+
+impl StreamAuth {
+    fn authorize(req: &HttpRequest) -> Result<Self, ApiError> {
+        let state = req
+            .app_data::<web::Data<Arc<ApplicationState>>>()
+            .ok_or(ApiError::InternalServerError)?;
+
+        let params = web::Query::<StreamTokenParams>::from_query(req.query_string())
+            .map_err(|_| AuthError::InvalidStreamToken)?
+            .into_inner();
+        let token = params.token.ok_or(AuthError::InvalidStreamToken)?;
+
+        // The resource comes from the matched route, a `session_id` in the query string must not open a file
+        let match_info = req.match_info();
+        if let (Some(info_hash), Some(file_idx)) = (match_info.get("hash"), match_info.get("file_idx")) {
+            let file_idx = file_idx.parse::<usize>().map_err(|_| AuthError::InvalidStreamToken)?;
+            let user_id = state
+                .stream_signer()
+                .verify(&StreamSigner::file_resource(info_hash, file_idx), &token)?;
+
+            return Ok(Self { user_id });
+        }
+
+        // Transcode routes, the manifest takes the session in the query string
+        let session_id = match_info
+            .get("session_id")
+            .map(str::to_string)
+            .or(params.session_id)
+            .ok_or(AuthError::InvalidStreamToken)?;
+        let user_id = state
+            .stream_signer()
+            .verify(&StreamSigner::session_resource(&session_id), &token)?;
+
+        // A token only opens the session of the user it was minted for
+        match state.transcode_sessions().get(&session_id) {
+            Some(session) if session.user_id == user_id => Ok(Self { user_id }),
+            _ => Err(AuthError::InvalidStreamToken.into()),
+        }
+    }
+}
+
+impl FromRequest for StreamAuth {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Self::authorize(req))
+    }
+}
@@ -0,0 +1,190 @@
+use super::soap::escape_xml;
+use super::{CONNECTION_MANAGER_TYPE, CONTENT_DIRECTORY_TYPE, MEDIA_SERVER_TYPE};
+use crate::utils::net::IpNetwork;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use uuid::Uuid;
+
+pub const DESCRIPTION_PATH: &str = "/api/dlna/description.xml";
+
+#[derive(Debug, Clone)]
+pub struct DlnaDevice {
+    pub uuid: Uuid,
+    pub friendly_name: String,
+    pub base_url: String,
+    allowed_networks: Vec<IpNetwork>,
+}
+
+impl DlnaDevice {
+    pub fn new(friendly_name: String, base_url: String, allowed_networks: Vec<IpNetwork>) -> Self {
+        // Renderers cache devices by UDN, keep it stable across restarts and toolchains
+        let uuid = Uuid::new_v5(
+            &Uuid::NAMESPACE_URL,
+            format!("{}#{}", base_url, friendly_name).as_bytes(),
+        );
+
+        Self {
+            uuid,
+            friendly_name,
+            base_url,
+            allowed_networks,
+        }
+    }
+
+    /// Renderers cannot log in, the media server trusts the address clients connect from instead. It is the
+    /// TCP peer address, forwarding headers are ignored so clients cannot claim to be on the LAN
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.allowed_networks.iter().any(|network| network.contains(ip))
+    }
+
+    pub fn udn(&self) -> String {
+        format!("uuid:{}", self.uuid)
+    }
+
+    pub fn location(&self) -> String {
+        format!("{}{}", self.base_url, DESCRIPTION_PATH)
+    }
+
+    pub fn description_xml(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0" xmlns:dlna="urn:schemas-dlna-org:device-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <device>
+    <deviceType>{device_type}</deviceType>
+    <friendlyName>{friendly_name}</friendlyName>
+    <manufacturer>hypertube</manufacturer>
+    <modelName>hypertube</modelName>
+    <modelNumber>{version}</modelNumber>
+    <UDN>{udn}</UDN>
+    <dlna:X_DLNADOC>DMS-1.50</dlna:X_DLNADOC>
+    <serviceList>
+      <service>
+        <serviceType>{content_directory}</serviceType>
+        <serviceId>urn:upnp-org:serviceId:ContentDirectory</serviceId>
+        <SCPDURL>/api/dlna/content_directory.xml</SCPDURL>
+        <controlURL>/api/dlna/content_directory/control</controlURL>
+        <eventSubURL></eventSubURL>
+      </service>
+      <service>
+        <serviceType>{connection_manager}</serviceType>
+        <serviceId>urn:upnp-org:serviceId:ConnectionManager</serviceId>
+        <SCPDURL>/api/dlna/connection_manager.xml</SCPDURL>
+        <controlURL>/api/dlna/connection_manager/control</controlURL>
+        <eventSubURL></eventSubURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#,
+            device_type = MEDIA_SERVER_TYPE,
+            friendly_name = escape_xml(&self.friendly_name),
+            version = env!("CARGO_PKG_VERSION"),
+            udn = self.udn(),
+            content_directory = CONTENT_DIRECTORY_TYPE,
+            connection_manager = CONNECTION_MANAGER_TYPE,
+        )
+    }
+}
+
+/// Address of the interface used to reach the LAN, no packet is actually sent
+/// Url LAN devices reach the server at, the configured one or the address of the interface facing the LAN
+pub fn lan_base_url(base_url: Option<String>, port: u16) -> String {
+    let base_url = base_url.unwrap_or_else(|| format!("http://{}:{}", local_ipv4(), port));
+
+    base_url.trim_end_matches('/').to_string()
+}
+
+fn local_ipv4() -> Ipv4Addr {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((Ipv4Addr::new(239, 255, 255, 250), 1900))?;
+            socket.local_addr()
+        })
+        .ok()
+        .and_then(|addr| match addr.ip() {
+            std::net::IpAddr::V4(ip) => Some(ip),
+            std::net::IpAddr::V6(_) => None,
+        })
+        .unwrap_or(Ipv4Addr::LOCALHOST)
+}
+
+pub const CONTENT_DIRECTORY_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action>
+      <name>Browse</name>
+      <argumentList>
+        <argument><name>ObjectID</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_ObjectID</relatedStateVariable></argument>
+        <argument><name>BrowseFlag</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_BrowseFlag</relatedStateVariable></argument>
+        <argument><name>Filter</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Filter</relatedStateVariable></argument>
+        <argument><name>StartingIndex</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Index</relatedStateVariable></argument>
+        <argument><name>RequestedCount</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+        <argument><name>SortCriteria</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_SortCriteria</relatedStateVariable></argument>
+        <argument><name>Result</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Result</relatedStateVariable></argument>
+        <argument><name>NumberReturned</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+        <argument><name>TotalMatches</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+        <argument><name>UpdateID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_UpdateID</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetSystemUpdateID</name>
+      <argumentList>
+        <argument><name>Id</name><direction>out</direction><relatedStateVariable>SystemUpdateID</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetSearchCapabilities</name>
+      <argumentList>
+        <argument><name>SearchCaps</name><direction>out</direction><relatedStateVariable>SearchCapabilities</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetSortCapabilities</name>
+      <argumentList>
+        <argument><name>SortCaps</name><direction>out</direction><relatedStateVariable>SortCapabilities</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ObjectID</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Result</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no">
+      <name>A_ARG_TYPE_BrowseFlag</name>
+      <dataType>string</dataType>
+      <allowedValueList><allowedValue>BrowseMetadata</allowedValue><allowedValue>BrowseDirectChildren</allowedValue></allowedValueList>
+    </stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Filter</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_SortCriteria</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Index</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Count</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_UpdateID</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SystemUpdateID</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SearchCapabilities</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SortCapabilities</name><dataType>string</dataType></stateVariable>
+  </serviceStateTable>
+</scpd>"#;
+
+pub const CONNECTION_MANAGER_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action>
+      <name>GetProtocolInfo</name>
+      <argumentList>
+        <argument><name>Source</name><direction>out</direction><relatedStateVariable>SourceProtocolInfo</relatedStateVariable></argument>
+        <argument><name>Sink</name><direction>out</direction><relatedStateVariable>SinkProtocolInfo</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetCurrentConnectionIDs</name>
+      <argumentList>
+        <argument><name>ConnectionIDs</name><direction>out</direction><relatedStateVariable>CurrentConnectionIDs</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="no"><name>SourceProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SinkProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>CurrentConnectionIDs</name><dataType>string</dataType></stateVariable>
+  </serviceStateTable>
+</scpd>"#;
@@ -0,0 +1,36 @@
+use crate::ApiErrorImpl;
+use actix_web::http::StatusCode;
+
+pub(super) type Result<T> = std::result::Result<T, DlnaError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DlnaError {
+    #[error("Renderer not found")]
+    RendererNotFound,
+    #[error("Renderer does not support AVTransport")]
+    MissingAvTransport,
+    #[error("Invalid device description")]
+    InvalidDescription,
+    #[error("Device description is not served by the device that answered the search")]
+    ForeignLocation,
+    #[error("Renderer rejected the action: {0}")]
+    ActionFailed(String),
+    #[error("Discovery failed: {0}")]
+    DiscoveryFailed(#[from] std::io::Error),
+    #[error("Http error: {0}")]
+    HttpError(#[from] reqwest::Error),
+}
+
+impl ApiErrorImpl for DlnaError {
+    fn get_codes(&self) -> (StatusCode, &str) {
+        match self {
+            DlnaError::RendererNotFound => (StatusCode::NOT_FOUND, "renderer_not_found"),
+            DlnaError::MissingAvTransport => (StatusCode::BAD_REQUEST, "missing_av_transport"),
+            DlnaError::InvalidDescription => (StatusCode::BAD_GATEWAY, "invalid_description"),
+            DlnaError::ForeignLocation => (StatusCode::BAD_GATEWAY, "foreign_location"),
+            DlnaError::ActionFailed(_) => (StatusCode::BAD_GATEWAY, "action_failed"),
+            DlnaError::DiscoveryFailed(_) => (StatusCode::INTERNAL_SERVER_ERROR, "discovery_failed"),
+            DlnaError::HttpError(_) => (StatusCode::BAD_GATEWAY, "http_error"),
+        }
+    }
+}
@@ -0,0 +1,13 @@
+mod device;
+pub mod error;
+mod renderer;
+pub mod soap;
+pub mod ssdp;
+pub use device::*;
+pub use renderer::*;
+
+pub const MEDIA_SERVER_TYPE: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+pub const CONTENT_DIRECTORY_TYPE: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+pub const CONNECTION_MANAGER_TYPE: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+
+pub const SERVER_HEADER: &str = concat!("Linux/1.0 UPnP/1.0 hypertube/", env!("CARGO_PKG_VERSION"));
@@ -0,0 +1,412 @@
+use super::error::{DlnaError, Result};
+use super::soap::{action_request, escape_xml, get_element};
+use super::ssdp;
+use apistos::ApiComponent;
+use regex::Regex;
+use reqwest::{Client, Url};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const MEDIA_RENDERER_TYPE: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+pub const AV_TRANSPORT_TYPE: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const INSTANCE_ID: &str = "0";
+
+static SERVICE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<service>(.*?)</service>").unwrap());
+
+#[derive(Debug, Clone, Serialize, ApiComponent, JsonSchema)]
+pub struct Renderer {
+    pub id: String,
+    pub friendly_name: String,
+    pub location: String,
+    #[serde(skip_serializing)]
+    pub av_transport_url: String,
+}
+
+pub struct RendererControl {
+    client: Client,
+    renderers: RwLock<HashMap<String, Renderer>>,
+    /// User who loaded the current media of each renderer
+    casters: RwLock<HashMap<String, Uuid>>,
+}
+
+/// Format a position as the `H+:MM:SS` time expected by AVTransport
+fn format_position(seconds: u64) -> String {
+    format!("{}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+}
+
+/// Whether `location` points at `address`, the host a search answer came from
+fn is_served_by(location: &str, address: IpAddr) -> bool {
+    Url::parse(location)
+        .ok()
+        .and_then(|url| url.host_str()?.trim_matches(['[', ']']).parse::<IpAddr>().ok())
+        .is_some_and(|host| host == address)
+}
+
+impl Default for RendererControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RendererControl {
+    pub fn new() -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .expect("Failed to build renderer http client"),
+            renderers: RwLock::new(HashMap::new()),
+            casters: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn renderers(&self) -> Vec<Renderer> {
+        let mut renderers = self.renderers.read().unwrap().values().cloned().collect::<Vec<_>>();
+        renderers.sort_by(|left, right| left.friendly_name.cmp(&right.friendly_name));
+
+        renderers
+    }
+
+    pub fn get(&self, id: &str) -> Result<Renderer> {
+        self.renderers
+            .read()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or(DlnaError::RendererNotFound)
+    }
+
+    pub fn caster(&self, renderer: &Renderer) -> Option<Uuid> {
+        self.casters.read().unwrap().get(&renderer.id).copied()
+    }
+
+    pub fn set_caster(&self, renderer: &Renderer, user_id: Option<Uuid>) {
+        let mut casters = self.casters.write().unwrap();
+        match user_id {
+            Some(user_id) => casters.insert(renderer.id.clone(), user_id),
+            None => casters.remove(&renderer.id),
+        };
+    }
+
+    /// Search the LAN for media renderers and replace the known renderers with the ones that answered
+    pub async fn discover(&self) -> Result<Vec<Renderer>> {
+        let responses = ssdp::search(MEDIA_RENDERER_TYPE, DISCOVERY_TIMEOUT).await?;
+
+        let mut renderers = HashMap::new();
+        for response in responses {
+            match self.describe(&response.location, response.address).await {
+                Ok(renderer) => {
+                    renderers.insert(renderer.id.clone(), renderer);
+                }
+                Err(err) => tracing::warn!("Ignoring renderer at {}: {}", response.location, err),
+            }
+        }
+
+        tracing::info!("Discovered {} media renderers", renderers.len());
+        *self.renderers.write().unwrap() = renderers;
+
+        Ok(self.renderers())
+    }
+
+    /// Fetch the description of a renderer. Any host can answer a search, the location must be on that host so
+    /// that a forged answer cannot make the server request arbitrary urls
+    async fn describe(&self, location: &str, address: IpAddr) -> Result<Renderer> {
+        if !is_served_by(location, address) {
+            return Err(DlnaError::ForeignLocation);
+        }
+
+        let description = self
+            .client
+            .get(location)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let udn = get_element(&description, "UDN").ok_or(DlnaError::InvalidDescription)?;
+        let friendly_name = get_element(&description, "friendlyName").unwrap_or_else(|| udn.clone());
+
+        let control_url = SERVICE_RE
+            .captures_iter(&description)
+            .map(|cap| cap[1].to_string())
+            .find(|service| {
+                get_element(service, "serviceType")
+                    .map(|service_type| service_type.starts_with("urn:schemas-upnp-org:service:AVTransport:"))
+                    .unwrap_or(false)
+            })
+            .and_then(|service| get_element(&service, "controlURL"))
+            .ok_or(DlnaError::MissingAvTransport)?;
+
+        let av_transport_url = Url::parse(location)
+            .and_then(|base| base.join(&control_url))
+            .map_err(|_| DlnaError::InvalidDescription)?;
+
+        Ok(Renderer {
+            id: udn.trim_start_matches("uuid:").to_string(),
+            friendly_name,
+            location: location.to_string(),
+            av_transport_url: av_transport_url.to_string(),
+        })
+    }
+
+    async fn call(&self, renderer: &Renderer, action: &str, arguments: &[(&str, String)]) -> Result<()> {
+        let mut arguments = arguments.to_vec();
+        arguments.insert(0, ("InstanceID", INSTANCE_ID.to_string()));
+
+        let response = self
+            .client
+            .post(&renderer.av_transport_url)
+            .header("Content-Type", r#"text/xml; charset="utf-8""#)
+            .header("SOAPACTION", format!(r#""{}#{}""#, AV_TRANSPORT_TYPE, action))
+            .body(action_request(AV_TRANSPORT_TYPE, action, &arguments))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let reason = get_element(&body, "errorDescription").unwrap_or_else(|| action.to_string());
+            tracing::error!("{} failed on renderer {}: {}", action, renderer.friendly_name, reason);
+            return Err(DlnaError::ActionFailed(reason));
+        }
+
+        Ok(())
+    }
+
+    pub async fn load(&self, renderer: &Renderer, url: &str, title: &str, mime: &str) -> Result<()> {
+        // Several TVs refuse an empty CurrentURIMetaData
+        let metadata = format!(
+            r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"><item id="0" parentID="-1" restricted="1"><dc:title>{}</dc:title><upnp:class>object.item.videoItem</upnp:class><res protocolInfo="http-get:*:{}:*">{}</res></item></DIDL-Lite>"#,
+            escape_xml(title),
+            mime,
+            escape_xml(url)
+        );
+
+        self.call(
+            renderer,
+            "SetAVTransportURI",
+            &[("CurrentURI", url.to_string()), ("CurrentURIMetaData", metadata)],
+        )
+        .await
+    }
+
+    pub async fn play(&self, renderer: &Renderer) -> Result<()> {
+        self.call(renderer, "Play", &[("Speed", "1".to_string())]).await
+    }
+
+    pub async fn pause(&self, renderer: &Renderer) -> Result<()> {
+        self.call(renderer, "Pause", &[]).await
+    }
+
+    pub async fn stop(&self, renderer: &Renderer) -> Result<()> {
+        self.call(renderer, "Stop", &[]).await
+    }
+
+    pub async fn seek(&self, renderer: &Renderer, position: u64) -> Result<()> {
+        self.call(
+            renderer,
+            "Seek",
+            &[("Unit", "REL_TIME".to_string()), ("Target", format_position(position))],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::dlna::soap::{envelope, fault};
+    use std::net::Ipv4Addr;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <friendlyName>Living Room TV</friendlyName>
+    <UDN>uuid:5f1c8a52-0c4e-4b8a-9d4e-1b2c3d4e5f60</UDN>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:RenderingControl:1</serviceType>
+        <controlURL>/upnp/rendering</controlURL>
+      </service>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType>
+        <controlURL>upnp/av_transport</controlURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[derive(Debug, Clone)]
+    struct Request {
+        path: String,
+        soap_action: Option<String>,
+        body: String,
+    }
+
+    async fn read_request(socket: &mut TcpStream) -> Request {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+
+        let head_end = loop {
+            let read = socket.read(&mut chunk).await.unwrap();
+            buffer.extend_from_slice(&chunk[..read]);
+            if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break position + 4;
+            }
+            assert!(read > 0, "connection closed before the end of the headers");
+        };
+
+        let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+        let mut lines = head.lines();
+        let path = lines.next().unwrap().split(' ').nth(1).unwrap().to_string();
+        let header = |name: &str| {
+            head.lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim().to_string())
+        };
+
+        let length = header("content-length")
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(0);
+        while buffer.len() < head_end + length {
+            let read = socket.read(&mut chunk).await.unwrap();
+            assert!(read > 0, "connection closed before the end of the body");
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+
+        Request {
+            path,
+            soap_action: header("soapaction"),
+            body: String::from_utf8_lossy(&buffer[head_end..head_end + length]).into_owned(),
+        }
+    }
+
+    /// Stand-in renderer serving its description and recording the AVTransport calls, `failing` gets a UPnP fault
+    async fn spawn_renderer(failing: &'static str) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let request = read_request(&mut socket).await;
+                recorded.lock().unwrap().push(request.clone());
+
+                let failed = request
+                    .soap_action
+                    .as_deref()
+                    .is_some_and(|action| action.trim_matches('"').ends_with(&format!("#{}", failing)));
+                let (status, body) = match request.path.as_str() {
+                    "/description.xml" => ("200 OK", DESCRIPTION.to_string()),
+                    "/upnp/av_transport" if failed => {
+                        ("500 Internal Server Error", fault(701, "Transition not available"))
+                    }
+                    "/upnp/av_transport" => ("200 OK", envelope("")),
+                    _ => ("404 Not Found", String::new()),
+                };
+
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        (format!("http://{}/description.xml", address), requests)
+    }
+
+    #[tokio::test]
+    async fn describe_resolves_the_av_transport_control_url() {
+        let (location, _) = spawn_renderer("").await;
+
+        let renderer = RendererControl::new().describe(&location, LOCALHOST).await.unwrap();
+
+        assert_eq!(renderer.id, "5f1c8a52-0c4e-4b8a-9d4e-1b2c3d4e5f60");
+        assert_eq!(renderer.friendly_name, "Living Room TV");
+        assert_eq!(renderer.location, location);
+        assert_eq!(
+            renderer.av_transport_url,
+            location.replace("description.xml", "upnp/av_transport")
+        );
+    }
+
+    #[tokio::test]
+    async fn describe_refuses_a_location_on_another_host() {
+        let (location, requests) = spawn_renderer("").await;
+
+        let result = RendererControl::new()
+            .describe(&location, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20)))
+            .await;
+
+        assert!(matches!(result, Err(DlnaError::ForeignLocation)));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn load_and_play_send_av_transport_actions() {
+        let (location, requests) = spawn_renderer("").await;
+        let control = RendererControl::new();
+        let renderer = control.describe(&location, LOCALHOST).await.unwrap();
+
+        let url = "http://192.168.1.10:8080/api/torrents/abc/files/0/stream?token=a.1.b&x=<y>";
+        control.load(&renderer, url, "Movie & Co", "video/mp4").await.unwrap();
+        control.play(&renderer).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let actions = requests
+            .iter()
+            .filter(|request| request.path == "/upnp/av_transport")
+            .collect::<Vec<_>>();
+        assert_eq!(actions.len(), 2);
+
+        let load = actions[0];
+        assert_eq!(
+            load.soap_action.as_deref(),
+            Some(r#""urn:schemas-upnp-org:service:AVTransport:1#SetAVTransportURI""#)
+        );
+        assert_eq!(get_element(&load.body, "InstanceID").as_deref(), Some("0"));
+        assert_eq!(get_element(&load.body, "CurrentURI").as_deref(), Some(url));
+        let metadata = get_element(&load.body, "CurrentURIMetaData").unwrap();
+        assert_eq!(get_element(&metadata, "title").as_deref(), Some("Movie & Co"));
+        assert!(metadata.contains(r#"protocolInfo="http-get:*:video/mp4:*""#));
+
+        let play = actions[1];
+        assert_eq!(
+            play.soap_action.as_deref(),
+            Some(r#""urn:schemas-upnp-org:service:AVTransport:1#Play""#)
+        );
+        assert_eq!(get_element(&play.body, "Speed").as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn rejected_actions_report_the_renderer_error() {
+        let (location, _) = spawn_renderer("Seek").await;
+        let control = RendererControl::new();
+        let renderer = control.describe(&location, LOCALHOST).await.unwrap();
+
+        let result = control.seek(&renderer, 3725).await;
+
+        match result {
+            Err(DlnaError::ActionFailed(reason)) => assert_eq!(reason, "Transition not available"),
+            other => panic!("expected ActionFailed, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use regex::Regex;
+use std::fmt::Write;
+
+pub fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Value of the first `name` element, namespace prefixes are ignored
+pub fn get_element(body: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(
+        r"(?s)<(?:\w+:)?{name}(?:\s[^>]*)?>(.*?)</(?:\w+:)?{name}>",
+        name = regex::escape(name)
+    ))
+    .ok()?;
+
+    re.captures(body).map(|cap| unescape_xml(cap[1].trim()))
+}
+
+/// Split a `SOAPACTION` header (`"urn:schemas-upnp-org:service:ContentDirectory:1#Browse"`) into service type and action
+pub fn parse_soap_action(header: &str) -> Option<(String, String)> {
+    let (service, action) = header.trim().trim_matches('"').split_once('#')?;
+
+    Some((service.to_string(), action.to_string()))
+}
+
+pub fn envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body>{}</s:Body></s:Envelope>"#,
+        body
+    )
+}
+
+fn action_body(service_type: &str, action: &str, arguments: &[(&str, String)]) -> String {
+    let arguments = arguments.iter().fold(String::new(), |mut output, (name, value)| {
+        let _ = write!(output, "<{name}>{}</{name}>", escape_xml(value));
+        output
+    });
+
+    format!(r#"<u:{action} xmlns:u="{service_type}">{arguments}</u:{action}>"#)
+}
+
+pub fn action_request(service_type: &str, action: &str, arguments: &[(&str, String)]) -> String {
+    envelope(&action_body(service_type, action, arguments))
+}
+
+pub fn action_response(service_type: &str, action: &str, arguments: &[(&str, String)]) -> String {
+    envelope(&action_body(service_type, &format!("{}Response", action), arguments))
+}
+
+pub fn fault(error_code: u16, description: &str) -> String {
+    envelope(&format!(
+        r#"<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault>"#,
+        error_code,
+        escape_xml(description)
+    ))
+}
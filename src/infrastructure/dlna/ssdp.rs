@@ -0,0 +1,259 @@
+use super::{DlnaDevice, CONNECTION_MANAGER_TYPE, CONTENT_DIRECTORY_TYPE, MEDIA_SERVER_TYPE, SERVER_HEADER};
+use chrono::Utc;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use uuid::Uuid;
+
+pub const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+pub const SSDP_PORT: u16 = 1900;
+
+const MAX_AGE: u32 = 1800;
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+// Upper bound of the MX answer delay, UPnP Device Architecture 1.1 section 1.3.3
+const MAX_SEARCH_DELAY_SECS: u64 = 5;
+
+#[derive(Debug, Clone)]
+pub struct SearchResponse {
+    pub location: String,
+    /// Host the answer came from
+    pub address: IpAddr,
+}
+
+fn bind_multicast() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Other UPnP stacks on the same host commonly hold the SSDP port as well
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, SSDP_PORT).into())?;
+    socket.join_multicast_v4(&SSDP_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+/// Parse the headers of an SSDP message, header names are lowercased
+pub fn parse_headers(message: &str) -> Vec<(String, String)> {
+    message
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect()
+}
+
+pub fn get_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+}
+
+pub struct SsdpServer {
+    device: Arc<DlnaDevice>,
+}
+
+impl SsdpServer {
+    pub fn new(device: Arc<DlnaDevice>) -> Self {
+        Self { device }
+    }
+
+    /// Pairs of notification type and unique service name advertised for the media server
+    fn notification_types(&self) -> Vec<(String, String)> {
+        let udn = self.device.udn();
+
+        let mut types = vec![
+            ("upnp:rootdevice".to_string(), format!("{}::upnp:rootdevice", udn)),
+            (udn.clone(), udn.clone()),
+        ];
+        types.extend(
+            [MEDIA_SERVER_TYPE, CONTENT_DIRECTORY_TYPE, CONNECTION_MANAGER_TYPE]
+                .iter()
+                .map(|nt| (nt.to_string(), format!("{}::{}", udn, nt))),
+        );
+
+        types
+    }
+
+    /// Advertise the media server until the process is asked to stop, then announce it is leaving
+    pub async fn run(self) -> std::io::Result<()> {
+        let socket = Arc::new(bind_multicast()?);
+        let mut interval = tokio::time::interval(NOTIFY_INTERVAL);
+        let mut buffer = [0u8; 2048];
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+
+        tracing::info!(
+            "Advertising DLNA media server {} at {}",
+            self.device.friendly_name,
+            self.device.location()
+        );
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = interval.tick() => self.notify_alive(&socket).await,
+                result = socket.recv_from(&mut buffer) => {
+                    // Errors such as ICMP port unreachable from a previous answer only concern one peer
+                    let (len, addr) = match result {
+                        Ok(received) => received,
+                        Err(err) => {
+                            tracing::warn!("Failed to receive SSDP message: {}", err);
+                            continue;
+                        }
+                    };
+                    if !self.device.allows(addr.ip()) {
+                        continue;
+                    }
+                    let message = String::from_utf8_lossy(&buffer[..len]);
+                    if message.starts_with("M-SEARCH") {
+                        self.answer_search(&socket, &message, addr);
+                    }
+                }
+            }
+        }
+
+        tracing::info!("Withdrawing DLNA media server {}", self.device.friendly_name);
+        self.notify_byebye(&socket).await;
+
+        Ok(())
+    }
+
+    async fn notify_alive(&self, socket: &UdpSocket) {
+        for (nt, usn) in self.notification_types() {
+            let message = format!(
+                "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nCACHE-CONTROL: max-age={}\r\nLOCATION: {}\r\nNT: {}\r\nNTS: ssdp:alive\r\nSERVER: {}\r\nUSN: {}\r\n\r\n",
+                SSDP_ADDR,
+                SSDP_PORT,
+                MAX_AGE,
+                self.device.location(),
+                nt,
+                SERVER_HEADER,
+                usn
+            );
+
+            if let Err(err) = socket.send_to(message.as_bytes(), (SSDP_ADDR, SSDP_PORT)).await {
+                tracing::warn!("Failed to send SSDP notify: {}", err);
+            }
+        }
+    }
+
+    /// Tell control points to drop the device now instead of waiting for the advertisement to expire
+    async fn notify_byebye(&self, socket: &UdpSocket) {
+        for (nt, usn) in self.notification_types() {
+            let message = format!(
+                "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nNT: {}\r\nNTS: ssdp:byebye\r\nUSN: {}\r\n\r\n",
+                SSDP_ADDR, SSDP_PORT, nt, usn
+            );
+
+            if let Err(err) = socket.send_to(message.as_bytes(), (SSDP_ADDR, SSDP_PORT)).await {
+                tracing::warn!("Failed to send SSDP byebye: {}", err);
+            }
+        }
+    }
+
+    /// Answer after a random delay of up to MX seconds so that control points are not flooded by every device at once
+    fn answer_search(&self, socket: &Arc<UdpSocket>, message: &str, addr: SocketAddr) {
+        let headers = parse_headers(message);
+        let Some(search_target) = get_header(&headers, "st") else {
+            return;
+        };
+        let max_delay_ms = get_header(&headers, "mx")
+            .and_then(|mx| mx.parse::<u64>().ok())
+            .unwrap_or(1)
+            .clamp(1, MAX_SEARCH_DELAY_SECS)
+            * 1000;
+        let delay = Duration::from_millis((Uuid::new_v4().as_u128() % max_delay_ms as u128) as u64);
+
+        let responses = self
+            .notification_types()
+            .into_iter()
+            .filter(|(nt, _)| search_target == "ssdp:all" || search_target == nt)
+            .map(|(nt, usn)| {
+                format!(
+                    "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={}\r\nDATE: {}\r\nEXT:\r\nLOCATION: {}\r\nSERVER: {}\r\nST: {}\r\nUSN: {}\r\n\r\n",
+                    MAX_AGE,
+                    Utc::now().format("%a, %d %b %Y %H:%M:%S GMT"),
+                    self.device.location(),
+                    SERVER_HEADER,
+                    nt,
+                    usn
+                )
+            })
+            .collect::<Vec<_>>();
+        if responses.is_empty() {
+            return;
+        }
+
+        let socket = socket.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            for response in responses {
+                if let Err(err) = socket.send_to(response.as_bytes(), addr).await {
+                    tracing::warn!("Failed to answer SSDP search from {}: {}", addr, err);
+                }
+            }
+        });
+    }
+}
+
+/// Resolves on the signals actix stops the server on
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let (Ok(mut terminate), Ok(mut quit)) = (signal(SignalKind::terminate()), signal(SignalKind::quit())) else {
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+            _ = quit.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Send an M-SEARCH for `search_target` and collect the answers received before `timeout`
+pub async fn search(search_target: &str, timeout: Duration) -> std::io::Result<Vec<SearchResponse>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}:{}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+        SSDP_ADDR,
+        SSDP_PORT,
+        timeout.as_secs().max(1),
+        search_target
+    );
+    socket.send_to(request.as_bytes(), (SSDP_ADDR, SSDP_PORT)).await?;
+
+    let mut responses: Vec<SearchResponse> = Vec::new();
+    let mut buffer = [0u8; 2048];
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    while let Ok(result) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        let (len, addr) = result?;
+        let message = String::from_utf8_lossy(&buffer[..len]);
+        if !message.starts_with("HTTP/1.1 200") {
+            continue;
+        }
+
+        let headers = parse_headers(&message);
+        if let Some(location) = get_header(&headers, "location") {
+            if responses.iter().any(|response| response.location == location) {
+                continue;
+            }
+            responses.push(SearchResponse {
+                location: location.to_string(),
+                address: addr.ip(),
+            });
+        }
+    }
+
+    Ok(responses)
+}
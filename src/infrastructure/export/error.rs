@@ -0,0 +1,33 @@
+use crate::ApiErrorImpl;
+use actix_web::http::StatusCode;
+
+pub(super) type Result<T> = std::result::Result<T, ExportError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("Export not found")]
+    ExportNotFound,
+    #[error("Export is not finished")]
+    ExportNotFinished,
+    #[error("File is not fully downloaded")]
+    FileNotDownloaded,
+    #[error("Failed to probe media file")]
+    ProbeFailed,
+    #[error("Ffmpeg failed: {0}")]
+    FfmpegFailed(String),
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl ApiErrorImpl for ExportError {
+    fn get_codes(&self) -> (StatusCode, &str) {
+        match self {
+            ExportError::ExportNotFound => (StatusCode::NOT_FOUND, "export_not_found"),
+            ExportError::ExportNotFinished => (StatusCode::CONFLICT, "export_not_finished"),
+            ExportError::FileNotDownloaded => (StatusCode::CONFLICT, "file_not_downloaded"),
+            ExportError::ProbeFailed => (StatusCode::INTERNAL_SERVER_ERROR, "probe_failed"),
+            ExportError::FfmpegFailed(_) => (StatusCode::INTERNAL_SERVER_ERROR, "ffmpeg_failed"),
+            ExportError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "io_error"),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use super::error::{ExportError, Result};
+use crate::infrastructure::torrent::TorrentFile;
+use apistos::ApiComponent;
+use chrono::{Duration, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+const EXPORT_FOLDER: &str = "./exports";
+/// How long a finished export and its file are kept for the user to download
+const EXPORT_TTL_SECONDS: i64 = 24 * 3600;
+const CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
+/// Burning subtitles re-encodes the video, so the other jobs stay queued while these run
+const MAX_RUNNING_EXPORTS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ApiComponent, JsonSchema)]
+pub enum ExportState {
+    #[serde(rename = "queued")]
+    Queued,
+    #[serde(rename = "running")]
+    Running,
+    #[serde(rename = "finished")]
+    Finished,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, ApiComponent, JsonSchema)]
+pub struct ExportJob {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub info_hash: String,
+    pub file_idx: usize,
+    pub file_name: String,
+    pub state: ExportState,
+    pub progress: f64,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExportOptions {
+    pub audio_track: usize,
+    pub subtitle_track: Option<usize>,
+}
+
+pub struct ExportManager {
+    jobs: Arc<RwLock<HashMap<Uuid, ExportJob>>>,
+    workers: Arc<Semaphore>,
+    output_folder: PathBuf,
+}
+
+impl Default for ExportManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExportManager {
+    pub fn new() -> Self {
+        Self {
+            jobs: Arc::new(RwLock::new(HashMap::new())),
+            workers: Arc::new(Semaphore::new(MAX_RUNNING_EXPORTS)),
+            output_folder: PathBuf::from(EXPORT_FOLDER),
+        }
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<ExportJob> {
+        self.jobs
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(ExportError::ExportNotFound)
+    }
+
+    pub async fn start(
+        &self,
+        user_id: Uuid,
+        info_hash: String,
+        file_idx: usize,
+        file: TorrentFile,
+        options: ExportOptions,
+    ) -> Result<ExportJob> {
+        if !file.is_finished() {
+            return Err(ExportError::FileNotDownloaded);
+        }
+
+        fs::create_dir_all(&self.output_folder).await?;
+
+        let id = Uuid::new_v4();
+        let file_name = Path::new(&file.name)
+            .with_extension("mp4")
+            .to_string_lossy()
+            .into_owned();
+
+        let job = ExportJob {
+            id,
+            user_id,
+            info_hash,
+            file_idx,
+            file_name,
+            state: ExportState::Queued,
+            progress: 0.0,
+            error: None,
+            created_at: Utc::now().naive_utc(),
+            finished_at: None,
+            output: self.output_folder.join(format!("{}.mp4", id)),
+        };
+
+        self.jobs.write().unwrap().insert(id, job.clone());
+
+        let jobs = self.jobs.clone();
+        let workers = self.workers.clone();
+        let output = job.output.clone();
+        tokio::spawn(async move {
+            let update = |f: &dyn Fn(&mut ExportJob)| {
+                if let Some(job) = jobs.write().unwrap().get_mut(&id) {
+                    f(job);
+                }
+            };
+
+            // The semaphore is never closed
+            let Ok(_worker) = workers.acquire_owned().await else {
+                return;
+            };
+            update(&|job| job.state = ExportState::Running);
+
+            let result = run_export(&file.path, &output, options, |progress| {
+                update(&|job| job.progress = progress);
+            })
+            .await;
+
+            match result {
+                Ok(()) => {
+                    tracing::info!("Export {} finished", id);
+                    update(&|job| {
+                        job.state = ExportState::Finished;
+                        job.progress = 1.0;
+                        job.finished_at = Some(Utc::now().naive_utc());
+                    });
+                }
+                Err(err) => {
+                    tracing::error!("Export {} failed: {}", id, err);
+                    let _ = fs::remove_file(&output).await;
+                    update(&|job| {
+                        job.state = ExportState::Failed;
+                        job.error = Some(err.to_string());
+                        job.finished_at = Some(Utc::now().naive_utc());
+                    });
+                }
+            }
+        });
+
+        Ok(job)
+    }
+
+    /// Periodically drop the jobs finished for longer than the ttl along with their file. Jobs do not survive
+    /// a restart, so the exports left over by a previous run are removed right away
+    pub async fn run(self: Arc<Self>) {
+        if let Err(err) = fs::remove_dir_all(&self.output_folder).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::error!("Failed to remove previous exports: {}", err);
+            }
+        }
+
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let now = Utc::now().naive_utc();
+            let expired = {
+                let mut jobs = self.jobs.write().unwrap();
+                let expired_ids = jobs
+                    .values()
+                    .filter(|job| match job.finished_at {
+                        Some(finished_at) => now - finished_at > Duration::seconds(EXPORT_TTL_SECONDS),
+                        None => false,
+                    })
+                    .map(|job| job.id)
+                    .collect::<Vec<_>>();
+
+                expired_ids.iter().filter_map(|id| jobs.remove(id)).collect::<Vec<_>>()
+            };
+
+            for job in expired {
+                tracing::info!("Removing expired export {}", job.id);
+                if let Err(err) = fs::remove_file(&job.output).await {
+                    if err.kind() != std::io::ErrorKind::NotFound {
+                        tracing::error!("Failed to remove export {}: {}", job.id, err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn probe_duration(input: &Path) -> Result<f64> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0"])
+        .arg(input)
+        .output()
+        .await?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ExportError::ProbeFailed)
+}
+
+/// Escape a path so it can be used as a filter option inside a `-vf` filtergraph. `\\ : '` are escaped
+/// for the option value then once more for the filtergraph, `[ ] , ;` only mean something to the filtergraph
+fn escape_filter_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('\\', "\\\\\\\\")
+        .replace(':', "\\\\:")
+        .replace('\'', "\\\\\\'")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+}
+
+async fn run_export<F>(input: &Path, output: &Path, options: ExportOptions, on_progress: F) -> Result<()>
+where
+    F: Fn(f64),
+{
+    let duration = probe_duration(input).await?;
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-y", "-loglevel", "error", "-nostats", "-progress", "pipe:1", "-i"])
+        .arg(input)
+        .args(["-map", "0:v:0", "-map", &format!("0:a:{}", options.audio_track)]);
+
+    match options.subtitle_track {
+        Some(subtitle_track) => {
+            command.args([
+                "-vf",
+                &format!("subtitles={}:si={}", escape_filter_path(input), subtitle_track),
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "20",
+            ]);
+        }
+        None => {
+            command.args(["-c:v", "copy"]);
+        }
+    };
+
+    command
+        .args([
+            "-c:a",
+            "aac",
+            "-b:a",
+            "192k",
+            "-ac",
+            "2",
+            "-sn",
+            "-movflags",
+            "+faststart",
+            "-f",
+            "mp4",
+        ])
+        .arg(output)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut ffmpeg = command.spawn()?;
+
+    if let Some(stdout) = ffmpeg.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            // out_time_ms is reported in microseconds as well, ffmpeg kept the name for compatibility
+            let out_time = line
+                .strip_prefix("out_time_us=")
+                .or_else(|| line.strip_prefix("out_time_ms="))
+                .and_then(|value| value.parse::<f64>().ok());
+
+            if let Some(out_time) = out_time.filter(|_| duration > 0.0) {
+                on_progress((out_time / 1_000_000.0 / duration).clamp(0.0, 0.99));
+            }
+        }
+    }
+
+    let result = ffmpeg.wait_with_output().await?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        let reason = stderr.lines().last().unwrap_or("unknown error").to_string();
+        return Err(ExportError::FfmpegFailed(reason));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_release_paths_for_the_filtergraph() {
+        let path = Path::new("/downloads/Movie (2020) [1080p] [YTS.MX]/Movie, Part 1; Director's Cut.mkv");
+
+        assert_eq!(
+            escape_filter_path(path),
+            r"/downloads/Movie (2020) \[1080p\] \[YTS.MX\]/Movie\, Part 1\; Director\\\'s Cut.mkv"
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod error;
+mod export;
+pub use export::*;
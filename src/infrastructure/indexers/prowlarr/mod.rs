@@ -28,6 +28,10 @@ impl ProwlarrIndexer {
             api_key,
         }
     }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
 }
 
 #[async_trait::async_trait]
@@ -0,0 +1,79 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Organized view of the finished downloads: `Movies/Title (Year)/` and `Shows/Name/Season 01/`.
+/// Files are hardlinked so librqbit keeps seeding from the download directory
+pub struct Library {
+    root: PathBuf,
+}
+
+/// Characters that are not allowed in file names on at least one of the usual filesystems
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => ' ',
+            c => c,
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn with_year(title: &str, year: Option<&str>) -> String {
+    match year.filter(|year| !year.is_empty()) {
+        Some(year) => format!("{} ({})", sanitize(title), year),
+        None => sanitize(title),
+    }
+}
+
+impl Library {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn movie_path(&self, title: &str, year: Option<&str>, extension: &str) -> PathBuf {
+        let name = with_year(title, year);
+
+        self.root
+            .join("Movies")
+            .join(&name)
+            .join(format!("{}.{}", name, extension))
+    }
+
+    pub fn episode_path(&self, show: &str, season: u32, episode: u32, extension: &str) -> PathBuf {
+        let show = sanitize(show);
+
+        self.root
+            .join("Shows")
+            .join(&show)
+            .join(format!("Season {:02}", season))
+            .join(format!("{} S{:02}E{:02}.{}", show, season, episode, extension))
+    }
+
+    /// Hardlink a downloaded file into the library. A copy would take the space of the file a second time
+    /// without being counted in the disk quota, so a library on another filesystem is refused
+    pub async fn link(&self, source: &Path, destination: &Path) -> io::Result<()> {
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        if fs::try_exists(destination).await? {
+            return Ok(());
+        }
+
+        fs::hard_link(source, destination).await.map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "could not hardlink {} into the library, it must be on the filesystem of the downloads: {}",
+                    source.display(),
+                    err
+                ),
+            )
+        })?;
+
+        Ok(())
+    }
+}
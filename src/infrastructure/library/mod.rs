@@ -0,0 +1,2 @@
+mod library;
+pub use library::*;
@@ -1,6 +1,11 @@
 pub mod authorization;
+pub mod dlna;
+pub mod export;
 pub mod indexers;
+pub mod library;
 pub mod metadata;
 pub mod models;
 pub mod oauth;
 pub mod torrent;
+pub mod transcode;
+pub mod webhook;
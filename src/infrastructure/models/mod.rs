@@ -1 +1,4 @@
+pub mod notification;
+pub mod playback_event;
+pub mod torrent;
 pub mod user;
@@ -0,0 +1,139 @@
+use crate::notifications::error::NotificationError;
+use apistos::ApiComponent;
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ApiComponent, JsonSchema)]
+pub enum NotificationKind {
+    #[serde(rename = "download_finished")]
+    DownloadFinished,
+    #[serde(rename = "download_failed")]
+    DownloadFailed,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::DownloadFinished => "download_finished",
+            NotificationKind::DownloadFailed => "download_failed",
+        }
+    }
+
+    fn from_str(kind: &str) -> Option<Self> {
+        match kind {
+            "download_finished" => Some(NotificationKind::DownloadFinished),
+            "download_failed" => Some(NotificationKind::DownloadFailed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct DbNotification {
+    id: i64,
+    info_hash: Option<String>,
+    kind: String,
+    message: String,
+    read_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, ApiComponent, JsonSchema)]
+pub struct Notification {
+    pub id: i64,
+    pub info_hash: Option<String>,
+    pub kind: Option<NotificationKind>,
+    pub message: String,
+    pub read_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<DbNotification> for Notification {
+    fn from(notification: DbNotification) -> Self {
+        Self {
+            id: notification.id,
+            info_hash: notification.info_hash,
+            kind: NotificationKind::from_str(&notification.kind),
+            message: notification.message,
+            read_at: notification.read_at,
+            created_at: notification.created_at,
+        }
+    }
+}
+
+impl Notification {
+    pub async fn create(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        info_hash: &str,
+        kind: NotificationKind,
+        message: &str,
+    ) -> Result<(), NotificationError> {
+        let user_id = user_id.to_string();
+        let info_hash = info_hash.to_lowercase();
+        let kind = kind.as_str();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO notification (user_id, info_hash, kind, message)
+            VALUES (?1, ?2, ?3, ?4)
+            "#,
+            user_id,
+            info_hash,
+            kind,
+            message
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_by_user(
+        pool: &SqlitePool,
+        user_id: Uuid,
+        unread_only: bool,
+    ) -> Result<Vec<Notification>, NotificationError> {
+        let user_id = user_id.to_string();
+
+        let result = sqlx::query_as!(
+            DbNotification,
+            r#"
+            SELECT id as "id!", info_hash, kind, message, read_at, created_at
+            FROM notification
+            WHERE user_id = ?1 AND (?2 = 0 OR read_at IS NULL)
+            ORDER BY created_at DESC, id DESC
+            LIMIT 100
+            "#,
+            user_id,
+            unread_only
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(result.into_iter().map(Into::into).collect())
+    }
+
+    /// Mark one notification of the user as read, or all of them when `id` is `None`. Notifications that
+    /// were already read keep their time. Returns the number of notifications of the user that matched
+    pub async fn mark_read(pool: &SqlitePool, user_id: Uuid, id: Option<i64>) -> Result<u64, NotificationError> {
+        let user_id = user_id.to_string();
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE notification
+            SET read_at = COALESCE(read_at, CURRENT_TIMESTAMP)
+            WHERE user_id = ?1 AND (?2 IS NULL OR id = ?2)
+            "#,
+            user_id,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
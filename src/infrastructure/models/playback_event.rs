@@ -0,0 +1,214 @@
+use crate::playback::error::PlaybackError;
+use apistos::ApiComponent;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ApiComponent, JsonSchema)]
+pub enum PlaybackEventType {
+    #[serde(rename = "start")]
+    Start,
+    #[serde(rename = "pause")]
+    Pause,
+    #[serde(rename = "seek")]
+    Seek,
+    #[serde(rename = "stall")]
+    Stall,
+    #[serde(rename = "bitrate_switch")]
+    BitrateSwitch,
+    #[serde(rename = "error")]
+    Error,
+}
+
+impl PlaybackEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlaybackEventType::Start => "start",
+            PlaybackEventType::Pause => "pause",
+            PlaybackEventType::Seek => "seek",
+            PlaybackEventType::Stall => "stall",
+            PlaybackEventType::BitrateSwitch => "bitrate_switch",
+            PlaybackEventType::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PlaybackEventInsert {
+    pub session_id: String,
+    pub user_id: Option<Uuid>,
+    pub info_hash: String,
+    pub file_idx: usize,
+    pub stream_kind: &'static str,
+    pub quality: String,
+    pub event_type: PlaybackEventType,
+    /// Client clock, the server receives events in batches
+    pub timestamp_ms: i64,
+    /// Playback position in seconds
+    pub position: Option<f64>,
+    /// Startup time for `start` events, rebuffering time for `stall` events
+    pub duration_ms: Option<i64>,
+    pub bitrate: Option<i64>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, ApiComponent, JsonSchema)]
+pub struct FileQoe {
+    pub info_hash: String,
+    pub file_idx: i64,
+    pub quality: String,
+    #[serde(flatten)]
+    pub metrics: QoeMetrics,
+}
+
+#[derive(Debug, Serialize, ApiComponent, JsonSchema)]
+pub struct UserQoe {
+    pub user_id: Option<String>,
+    #[serde(flatten)]
+    pub metrics: QoeMetrics,
+}
+
+#[derive(Debug, Serialize, ApiComponent, JsonSchema)]
+pub struct QoeMetrics {
+    pub sessions: i64,
+    pub avg_startup_ms: Option<f64>,
+    pub stalls: i64,
+    pub errors: i64,
+    /// Time spent rebuffering over the time spent in the player
+    pub rebuffer_ratio: Option<f64>,
+}
+
+pub struct PlaybackEvent;
+
+impl PlaybackEvent {
+    pub async fn create_many(pool: &SqlitePool, events: &[PlaybackEventInsert]) -> Result<(), PlaybackError> {
+        let mut transaction = pool.begin().await?;
+
+        for event in events {
+            let user_id = event.user_id.map(|id| id.to_string());
+            let file_idx = event.file_idx as i64;
+            let event_type = event.event_type.as_str();
+
+            sqlx::query!(
+                r#"
+                INSERT INTO playback_event
+                    (session_id, user_id, info_hash, file_idx, stream_kind, quality, event_type, timestamp_ms, position, duration_ms, bitrate, message)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                "#,
+                event.session_id,
+                user_id,
+                event.info_hash,
+                file_idx,
+                event.stream_kind,
+                event.quality,
+                event_type,
+                event.timestamp_ms,
+                event.position,
+                event.duration_ms,
+                event.bitrate,
+                event.message
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Metrics per file and quality, computed from one row per playback session
+    pub async fn stats_per_file(pool: &SqlitePool) -> Result<Vec<FileQoe>, PlaybackError> {
+        let rows = sqlx::query!(
+            r#"
+            WITH sessions AS (
+                SELECT session_id,
+                       MAX(info_hash)                                                        AS info_hash,
+                       MAX(file_idx)                                                         AS file_idx,
+                       MAX(quality)                                                          AS quality,
+                       MAX(CASE WHEN event_type = 'start' THEN duration_ms END)              AS startup_ms,
+                       SUM(CASE WHEN event_type = 'stall' THEN COALESCE(duration_ms, 0) END) AS stall_ms,
+                       SUM(event_type = 'stall')                                             AS stalls,
+                       SUM(event_type = 'error')                                             AS errors,
+                       MAX(timestamp_ms) - MIN(timestamp_ms)                                 AS wall_ms
+                FROM playback_event
+                GROUP BY session_id
+            )
+            SELECT info_hash                                                          AS "info_hash!: String",
+                   file_idx                                                           AS "file_idx!: i64",
+                   quality                                                            AS "quality!: String",
+                   COUNT(*)                                                           AS "sessions!: i64",
+                   AVG(startup_ms)                                                    AS "avg_startup_ms: f64",
+                   COALESCE(SUM(stalls), 0)                                           AS "stalls!: i64",
+                   COALESCE(SUM(errors), 0)                                           AS "errors!: i64",
+                   CAST(SUM(COALESCE(stall_ms, 0)) AS REAL) / NULLIF(SUM(wall_ms), 0) AS "rebuffer_ratio: f64"
+            FROM sessions
+            GROUP BY info_hash, file_idx, quality
+            ORDER BY "rebuffer_ratio: f64" DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| FileQoe {
+                info_hash: row.info_hash,
+                file_idx: row.file_idx,
+                quality: row.quality,
+                metrics: QoeMetrics {
+                    sessions: row.sessions,
+                    avg_startup_ms: row.avg_startup_ms,
+                    stalls: row.stalls,
+                    errors: row.errors,
+                    rebuffer_ratio: row.rebuffer_ratio,
+                },
+            })
+            .collect())
+    }
+
+    /// Metrics per user, computed from one row per playback session
+    pub async fn stats_per_user(pool: &SqlitePool) -> Result<Vec<UserQoe>, PlaybackError> {
+        let rows = sqlx::query!(
+            r#"
+            WITH sessions AS (
+                SELECT session_id,
+                       MAX(user_id)                                                          AS user_id,
+                       MAX(CASE WHEN event_type = 'start' THEN duration_ms END)              AS startup_ms,
+                       SUM(CASE WHEN event_type = 'stall' THEN COALESCE(duration_ms, 0) END) AS stall_ms,
+                       SUM(event_type = 'stall')                                             AS stalls,
+                       SUM(event_type = 'error')                                             AS errors,
+                       MAX(timestamp_ms) - MIN(timestamp_ms)                                 AS wall_ms
+                FROM playback_event
+                GROUP BY session_id
+            )
+            SELECT user_id                                                            AS "user_id: String",
+                   COUNT(*)                                                           AS "sessions!: i64",
+                   AVG(startup_ms)                                                    AS "avg_startup_ms: f64",
+                   COALESCE(SUM(stalls), 0)                                           AS "stalls!: i64",
+                   COALESCE(SUM(errors), 0)                                           AS "errors!: i64",
+                   CAST(SUM(COALESCE(stall_ms, 0)) AS REAL) / NULLIF(SUM(wall_ms), 0) AS "rebuffer_ratio: f64"
+            FROM sessions
+            GROUP BY user_id
+            ORDER BY "rebuffer_ratio: f64" DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| UserQoe {
+                user_id: row.user_id,
+                metrics: QoeMetrics {
+                    sessions: row.sessions,
+                    avg_startup_ms: row.avg_startup_ms,
+                    stalls: row.stalls,
+                    errors: row.errors,
+                    rebuffer_ratio: row.rebuffer_ratio,
+                },
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,388 @@
+use crate::config::SeedAction;
+use crate::infrastructure::metadata::models::ShowType;
+use crate::torrents::error::TorrentError;
+use chrono::NaiveDateTime;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DbTorrent {
+    pub info_hash: String,
+    pub user_id: Option<String>,
+    pub added_at: NaiveDateTime,
+    pub tmdb_id: Option<i64>,
+    pub media_type: Option<String>,
+    pub season: Option<i64>,
+    pub episode: Option<i64>,
+    pub file_idx: Option<i64>,
+    pub library_path: Option<String>,
+    pub last_watched_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub seed_ratio_limit: Option<f64>,
+    pub seed_time_limit_hours: Option<i64>,
+    pub seed_limit_action: Option<String>,
+    pub kept_files: Option<String>,
+    pub paused_by_user: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Torrent {
+    pub info_hash: String,
+    pub user_id: Option<Uuid>,
+    pub added_at: NaiveDateTime,
+    pub tmdb_id: Option<u32>,
+    pub media_type: Option<ShowType>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub file_idx: Option<usize>,
+    pub library_path: Option<PathBuf>,
+    pub last_watched_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub seed_ratio_limit: Option<f64>,
+    pub seed_time_limit_hours: Option<u64>,
+    pub seed_limit_action: Option<SeedAction>,
+    /// Files left on disk after the torrent was removed from the session
+    pub kept_files: Vec<PathBuf>,
+    /// Paused through the API, the download queue leaves it alone across restarts
+    pub paused_by_user: bool,
+}
+
+fn media_type_to_str(media_type: &ShowType) -> &'static str {
+    match media_type {
+        ShowType::Movie => "movie",
+        ShowType::TV => "tv",
+    }
+}
+
+fn media_type_from_str(media_type: &str) -> Option<ShowType> {
+    match media_type {
+        "movie" => Some(ShowType::Movie),
+        "tv" => Some(ShowType::TV),
+        _ => None,
+    }
+}
+
+fn seed_action_to_str(action: &SeedAction) -> &'static str {
+    match action {
+        SeedAction::Pause => "pause",
+        SeedAction::Remove => "remove",
+    }
+}
+
+fn seed_action_from_str(action: &str) -> Option<SeedAction> {
+    match action {
+        "pause" => Some(SeedAction::Pause),
+        "remove" => Some(SeedAction::Remove),
+        _ => None,
+    }
+}
+
+impl From<DbTorrent> for Torrent {
+    fn from(torrent: DbTorrent) -> Self {
+        Self {
+            info_hash: torrent.info_hash,
+            user_id: torrent.user_id.and_then(|id| Uuid::parse_str(&id).ok()),
+            added_at: torrent.added_at,
+            tmdb_id: torrent.tmdb_id.map(|id| id as u32),
+            media_type: torrent.media_type.as_deref().and_then(media_type_from_str),
+            season: torrent.season.map(|season| season as u32),
+            episode: torrent.episode.map(|episode| episode as u32),
+            file_idx: torrent.file_idx.map(|file_idx| file_idx as usize),
+            library_path: torrent.library_path.map(PathBuf::from),
+            last_watched_at: torrent.last_watched_at,
+            finished_at: torrent.finished_at,
+            seed_ratio_limit: torrent.seed_ratio_limit,
+            seed_time_limit_hours: torrent.seed_time_limit_hours.map(|hours| hours as u64),
+            seed_limit_action: torrent.seed_limit_action.as_deref().and_then(seed_action_from_str),
+            kept_files: torrent
+                .kept_files
+                .and_then(|files| serde_json::from_str(&files).ok())
+                .unwrap_or_default(),
+            paused_by_user: torrent.paused_by_user,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TorrentInsert {
+    pub info_hash: String,
+    pub user_id: Option<Uuid>,
+    pub tmdb_id: Option<u32>,
+    pub media_type: Option<ShowType>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub file_idx: Option<usize>,
+}
+
+impl Torrent {
+    /// Insert a torrent, or update the row a torrent no longer in the session left behind.
+    ///
+    /// The user adding the torrent back becomes its owner, rows recorded without owner at startup keep theirs
+    pub async fn create(pool: &SqlitePool, torrent: &TorrentInsert) -> Result<Torrent, TorrentError> {
+        let info_hash = torrent.info_hash.to_lowercase();
+        let user_id = torrent.user_id.map(|id| id.to_string());
+        let media_type = torrent.media_type.as_ref().map(media_type_to_str);
+        let file_idx = torrent.file_idx.map(|file_idx| file_idx as i64);
+
+        let result = sqlx::query_as!(
+            DbTorrent,
+            r#"
+            INSERT INTO torrent (info_hash, user_id, tmdb_id, media_type, season, episode, file_idx)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (info_hash) DO UPDATE SET
+                user_id = COALESCE(excluded.user_id, user_id),
+                tmdb_id = COALESCE(excluded.tmdb_id, tmdb_id),
+                media_type = COALESCE(excluded.media_type, media_type),
+                season = COALESCE(excluded.season, season),
+                episode = COALESCE(excluded.episode, episode),
+                file_idx = COALESCE(excluded.file_idx, file_idx),
+                kept_files = NULL,
+                paused_by_user = FALSE
+            RETURNING info_hash, user_id, added_at, tmdb_id, media_type, season, episode, file_idx, library_path,
+                last_watched_at, finished_at, seed_ratio_limit, seed_time_limit_hours, seed_limit_action,
+                kept_files, paused_by_user
+            "#,
+            info_hash,
+            user_id,
+            torrent.tmdb_id,
+            media_type,
+            torrent.season,
+            torrent.episode,
+            file_idx
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(result.into())
+    }
+
+    pub async fn get_all(pool: &SqlitePool) -> Result<Vec<Torrent>, TorrentError> {
+        let result = sqlx::query_as!(
+            DbTorrent,
+            r#"
+            SELECT info_hash, user_id, added_at, tmdb_id, media_type, season, episode, file_idx, library_path,
+                last_watched_at, finished_at, seed_ratio_limit, seed_time_limit_hours, seed_limit_action,
+                kept_files, paused_by_user
+            FROM torrent
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(result.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn get_by_info_hash(pool: &SqlitePool, info_hash: &str) -> Result<Option<Torrent>, TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+
+        let result = sqlx::query_as!(
+            DbTorrent,
+            r#"
+            SELECT info_hash, user_id, added_at, tmdb_id, media_type, season, episode, file_idx, library_path,
+                last_watched_at, finished_at, seed_ratio_limit, seed_time_limit_hours, seed_limit_action,
+                kept_files, paused_by_user
+            FROM torrent
+            WHERE info_hash = ?1
+            "#,
+            info_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(result.map(Into::into))
+    }
+
+    /// Torrents linked to a movie or show that were not organized into the library yet
+    pub async fn get_unorganized(pool: &SqlitePool) -> Result<Vec<Torrent>, TorrentError> {
+        let result = sqlx::query_as!(
+            DbTorrent,
+            r#"
+            SELECT info_hash, user_id, added_at, tmdb_id, media_type, season, episode, file_idx, library_path,
+                last_watched_at, finished_at, seed_ratio_limit, seed_time_limit_hours, seed_limit_action,
+                kept_files, paused_by_user
+            FROM torrent
+            WHERE library_path IS NULL AND organized_at IS NULL AND tmdb_id IS NOT NULL AND media_type IS NOT NULL
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(result.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn set_library_path(pool: &SqlitePool, info_hash: &str, library_path: &Path) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+        let library_path = library_path.to_string_lossy().into_owned();
+
+        sqlx::query!(
+            r#"
+            UPDATE torrent
+            SET library_path = ?2, organized_at = CURRENT_TIMESTAMP
+            WHERE info_hash = ?1
+            "#,
+            info_hash,
+            library_path
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record that a torrent has nothing to link into the library, so it is not looked at again
+    pub async fn set_nothing_to_organize(pool: &SqlitePool, info_hash: &str) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+
+        sqlx::query!(
+            r#"
+            UPDATE torrent
+            SET organized_at = CURRENT_TIMESTAMP
+            WHERE info_hash = ?1
+            "#,
+            info_hash
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn mark_watched(pool: &SqlitePool, info_hash: &str) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+
+        sqlx::query!(
+            r#"
+            UPDATE torrent
+            SET last_watched_at = CURRENT_TIMESTAMP
+            WHERE info_hash = ?1
+            "#,
+            info_hash
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Torrents not watched since `cutoff`, the ones never watched count from when they were added
+    pub async fn get_unwatched_since(pool: &SqlitePool, cutoff: NaiveDateTime) -> Result<Vec<Torrent>, TorrentError> {
+        let result = sqlx::query_as!(
+            DbTorrent,
+            r#"
+            SELECT info_hash, user_id, added_at, tmdb_id, media_type, season, episode, file_idx, library_path,
+                last_watched_at, finished_at, seed_ratio_limit, seed_time_limit_hours, seed_limit_action,
+                kept_files, paused_by_user
+            FROM torrent
+            WHERE COALESCE(last_watched_at, added_at) < ?1
+            ORDER BY COALESCE(last_watched_at, added_at)
+            "#,
+            cutoff
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(result.into_iter().map(Into::into).collect())
+    }
+
+    /// Seeding time is counted from the first time the torrent was seen finished
+    pub async fn set_finished(pool: &SqlitePool, info_hash: &str) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+
+        sqlx::query!(
+            r#"
+            UPDATE torrent
+            SET finished_at = CURRENT_TIMESTAMP
+            WHERE info_hash = ?1 AND finished_at IS NULL
+            "#,
+            info_hash
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Per-torrent seeding rules, unset values fall back to the server defaults
+    pub async fn set_seeding_rules(
+        pool: &SqlitePool,
+        info_hash: &str,
+        ratio_limit: Option<f64>,
+        time_limit_hours: Option<u64>,
+        action: Option<SeedAction>,
+    ) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+        let time_limit_hours = time_limit_hours.map(|hours| hours as i64);
+        let action = action.as_ref().map(seed_action_to_str);
+
+        sqlx::query!(
+            r#"
+            UPDATE torrent
+            SET seed_ratio_limit = ?2, seed_time_limit_hours = ?3, seed_limit_action = ?4
+            WHERE info_hash = ?1
+            "#,
+            info_hash,
+            ratio_limit,
+            time_limit_hours,
+            action
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remember the files a torrent left on disk when it was removed from the session
+    pub async fn set_kept_files(pool: &SqlitePool, info_hash: &str, files: &[PathBuf]) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+        let files = serde_json::to_string(files).ok();
+
+        sqlx::query!(
+            r#"
+            UPDATE torrent
+            SET kept_files = ?2
+            WHERE info_hash = ?1
+            "#,
+            info_hash,
+            files
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_paused_by_user(pool: &SqlitePool, info_hash: &str, paused: bool) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+
+        sqlx::query!(
+            r#"
+            UPDATE torrent
+            SET paused_by_user = ?2
+            WHERE info_hash = ?1
+            "#,
+            info_hash,
+            paused
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(pool: &SqlitePool, info_hash: &str) -> Result<(), TorrentError> {
+        let info_hash = info_hash.to_lowercase();
+
+        sqlx::query!(
+            r#"
+            DELETE FROM torrent
+            WHERE info_hash = ?1
+            "#,
+            info_hash
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::ApiError;
+use crate::infrastructure::authorization::{AuthorizationService, Permission};
 use crate::users::error::UserError;
 use apistos::ApiComponent;
 use chrono::NaiveDateTime;
@@ -68,6 +69,10 @@ pub struct UserUpdate {
 }
 
 impl User {
+    pub fn is_admin(&self) -> bool {
+        AuthorizationService::has_permission(self.permissions as u32, Permission::ServerAdmin)
+    }
+
     pub async fn create(pool: &SqlitePool, user: &UserInsert) -> Result<User, UserError> {
         let uuid = Uuid::new_v4().to_string();
 
@@ -13,6 +13,26 @@ pub enum TorrentError {
     InvalidLengths,
     #[error("File not found")]
     FileNotFound,
+    #[error("Torrent is not live")]
+    TorrentNotLive,
+    #[error("Torrent is already paused")]
+    AlreadyPaused,
+    #[error("Torrent is not paused")]
+    NotPaused,
+    #[error("Failed to pause torrent")]
+    PauseFailed,
+    #[error("Failed to resume torrent")]
+    ResumeFailed,
+    #[error("Failed to delete torrent")]
+    DeleteFailed,
+    #[error("Failed to recheck torrent")]
+    RecheckFailed,
+    #[error("Invalid rate limit schedule window: {0}")]
+    InvalidSchedule(String),
+    #[error("Invalid magnet link: {0}")]
+    InvalidMagnet(&'static str),
+    #[error("BitTorrent v2 only magnets are not supported")]
+    UnsupportedMagnet,
 }
 
 impl ApiErrorImpl for TorrentError {
@@ -22,6 +42,16 @@ impl ApiErrorImpl for TorrentError {
             TorrentError::TorrentNotFound => (StatusCode::NOT_FOUND, "torrent_not_found"),
             TorrentError::InvalidLengths => (StatusCode::INTERNAL_SERVER_ERROR, "invalid_lengths"),
             TorrentError::FileNotFound => (StatusCode::NOT_FOUND, "file_not_found"),
+            TorrentError::TorrentNotLive => (StatusCode::CONFLICT, "torrent_not_live"),
+            TorrentError::AlreadyPaused => (StatusCode::CONFLICT, "already_paused"),
+            TorrentError::NotPaused => (StatusCode::CONFLICT, "not_paused"),
+            TorrentError::PauseFailed => (StatusCode::INTERNAL_SERVER_ERROR, "pause_failed"),
+            TorrentError::ResumeFailed => (StatusCode::INTERNAL_SERVER_ERROR, "resume_failed"),
+            TorrentError::DeleteFailed => (StatusCode::INTERNAL_SERVER_ERROR, "delete_failed"),
+            TorrentError::RecheckFailed => (StatusCode::INTERNAL_SERVER_ERROR, "recheck_failed"),
+            TorrentError::InvalidSchedule(..) => (StatusCode::BAD_REQUEST, "invalid_schedule"),
+            TorrentError::InvalidMagnet(..) => (StatusCode::BAD_REQUEST, "invalid_magnet"),
+            TorrentError::UnsupportedMagnet => (StatusCode::UNPROCESSABLE_ENTITY, "unsupported_magnet"),
         }
     }
 }
@@ -0,0 +1,243 @@
+use super::error::{Result, TorrentError};
+use std::fmt::Write;
+use std::str::FromStr;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// Multihash prefix of the sha2-256 digests used by BitTorrent v2
+const SHA2_256_MULTIHASH: &str = "1220";
+
+/// Magnet link of a BitTorrent v1, v2 or hybrid torrent
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Magnet {
+    /// Lowercase hex of the v1 info hash, from `xt=urn:btih:`
+    pub info_hash_v1: Option<String>,
+    /// Lowercase hex of the v2 sha2-256 multihash, from `xt=urn:btmh:`
+    pub info_hash_v2: Option<String>,
+    /// `dn`
+    pub display_name: Option<String>,
+    /// `tr`
+    pub trackers: Vec<String>,
+    /// `xl`
+    pub exact_length: Option<u64>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+fn decode_base32(value: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(value.len() * 5 / 8);
+    let mut buffer = 0u64;
+    let mut bits = 0;
+
+    for c in value.bytes() {
+        let index = BASE32_ALPHABET.iter().position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | index as u64;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+fn parse_btih(value: &str) -> Result<String> {
+    match value.len() {
+        40 if value.chars().all(|c| c.is_ascii_hexdigit()) => Ok(value.to_lowercase()),
+        32 => decode_base32(value)
+            .map(|bytes| to_hex(&bytes))
+            .ok_or(TorrentError::InvalidMagnet("btih is not valid base32")),
+        _ => Err(TorrentError::InvalidMagnet(
+            "btih must be 40 hex or 32 base32 characters",
+        )),
+    }
+}
+
+fn parse_btmh(value: &str) -> Result<String> {
+    let value = value.to_lowercase();
+    if value.len() != 68 || !value.starts_with(SHA2_256_MULTIHASH) || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(TorrentError::InvalidMagnet("btmh must be a hex sha2-256 multihash"));
+    }
+
+    Ok(value)
+}
+
+impl Magnet {
+    /// Info hash librqbit identifies the torrent with, v2-only torrents have none
+    pub fn info_hash(&self) -> Option<&str> {
+        self.info_hash_v1.as_deref()
+    }
+
+    /// Magnet link handed to librqbit, with the v1 info hash in hex whatever encoding the original used
+    pub fn to_v1_link(&self) -> Option<String> {
+        let mut link = format!("magnet:?xt=urn:btih:{}", self.info_hash()?);
+        if let Some(display_name) = &self.display_name {
+            let _ = write!(link, "&dn={}", urlencoding::encode(display_name));
+        }
+        for tracker in &self.trackers {
+            let _ = write!(link, "&tr={}", urlencoding::encode(tracker));
+        }
+
+        Some(link)
+    }
+}
+
+impl FromStr for Magnet {
+    type Err = TorrentError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let query = s
+            .trim()
+            .strip_prefix("magnet:?")
+            .ok_or(TorrentError::InvalidMagnet("not a magnet link"))?;
+
+        let mut magnet = Magnet::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = urlencoding::decode(&value.replace('+', " "))
+                .map_err(|_| TorrentError::InvalidMagnet("invalid percent-encoding"))?
+                .into_owned();
+
+            // Parameters may be numbered when repeated, like `tr.1`
+            match key.split('.').next().unwrap_or(key) {
+                "xt" => {
+                    if let Some(btih) = value.strip_prefix("urn:btih:") {
+                        magnet.info_hash_v1 = Some(parse_btih(btih)?);
+                    } else if let Some(btmh) = value.strip_prefix("urn:btmh:") {
+                        magnet.info_hash_v2 = Some(parse_btmh(btmh)?);
+                    }
+                }
+                "dn" if !value.is_empty() => magnet.display_name = Some(value),
+                "tr" if !value.is_empty() => magnet.trackers.push(value),
+                "xl" => {
+                    magnet.exact_length = Some(
+                        value
+                            .parse()
+                            .map_err(|_| TorrentError::InvalidMagnet("xl is not a number"))?,
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        if magnet.info_hash_v1.is_none() && magnet.info_hash_v2.is_none() {
+            return Err(TorrentError::InvalidMagnet("missing btih or btmh exact topic"));
+        }
+
+        Ok(magnet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+    const BASE32: &str = "YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK";
+    const BTMH: &str = "1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e";
+
+    #[test]
+    fn parses_hex_btih() {
+        let magnet = Magnet::from_str(&format!("magnet:?xt=urn:btih:{}", HEX.to_uppercase())).unwrap();
+
+        assert_eq!(magnet.info_hash(), Some(HEX));
+        assert_eq!(magnet.info_hash_v2, None);
+    }
+
+    #[test]
+    fn parses_base32_btih_in_either_case() {
+        for btih in [BASE32.to_string(), BASE32.to_lowercase()] {
+            let magnet = Magnet::from_str(&format!("magnet:?xt=urn:btih:{}", btih)).unwrap();
+
+            assert_eq!(magnet.info_hash(), Some(HEX));
+        }
+    }
+
+    #[test]
+    fn parses_hybrid_magnet() {
+        let magnet = Magnet::from_str(&format!("magnet:?xt=urn:btih:{}&xt=urn:btmh:{}", HEX, BTMH)).unwrap();
+
+        assert_eq!(magnet.info_hash(), Some(HEX));
+        assert_eq!(magnet.info_hash_v2.as_deref(), Some(BTMH));
+    }
+
+    #[test]
+    fn v2_only_magnet_has_no_librqbit_info_hash() {
+        let magnet = Magnet::from_str(&format!("magnet:?xt=urn:btmh:{}", BTMH)).unwrap();
+
+        assert_eq!(magnet.info_hash(), None);
+        assert_eq!(magnet.info_hash_v2.as_deref(), Some(BTMH));
+        assert_eq!(magnet.to_v1_link(), None);
+    }
+
+    #[test]
+    fn collects_numbered_trackers() {
+        let magnet = Magnet::from_str(&format!(
+            "magnet:?xt=urn:btih:{}&tr.1=udp%3A%2F%2Fone.example%3A80&tr.2=udp%3A%2F%2Ftwo.example%3A80",
+            HEX
+        ))
+        .unwrap();
+
+        assert_eq!(magnet.trackers, vec!["udp://one.example:80", "udp://two.example:80"]);
+    }
+
+    #[test]
+    fn decodes_display_name() {
+        let magnet = Magnet::from_str(&format!("magnet:?xt=urn:btih:{}&dn=Big+Buck%20Bunny%2B1080p", HEX)).unwrap();
+
+        assert_eq!(magnet.display_name.as_deref(), Some("Big Buck Bunny+1080p"));
+    }
+
+    #[test]
+    fn rejects_invalid_exact_length() {
+        let result = Magnet::from_str(&format!("magnet:?xt=urn:btih:{}&xl=big", HEX));
+
+        assert!(matches!(result, Err(TorrentError::InvalidMagnet(_))));
+    }
+
+    #[test]
+    fn rejects_junk() {
+        for input in [
+            "",
+            "https://example.com/file.torrent",
+            "magnet:?",
+            "magnet:?dn=nothing",
+            "magnet:?xt=urn:btih:abc",
+            "magnet:?xt=urn:btih:0123456789012345678901234567890!",
+            "magnet:?xt=urn:btmh:1234",
+        ] {
+            assert!(
+                matches!(Magnet::from_str(input), Err(TorrentError::InvalidMagnet(_))),
+                "{} was accepted",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn v1_link_uses_hex_info_hash() {
+        let magnet = Magnet::from_str(&format!(
+            "magnet:?xt=urn:btih:{}&xt=urn:btmh:{}&dn=Big+Buck&tr=udp%3A%2F%2Fone.example%3A80&xl=42",
+            BASE32, BTMH
+        ))
+        .unwrap();
+
+        assert_eq!(
+            magnet.to_v1_link().as_deref(),
+            Some(
+                format!(
+                    "magnet:?xt=urn:btih:{}&dn=Big%20Buck&tr=udp%3A%2F%2Fone.example%3A80",
+                    HEX
+                )
+                .as_str()
+            )
+        );
+    }
+}
@@ -1,3 +1,19 @@
 pub mod error;
+mod magnet;
+mod options;
+mod preview;
+mod queue;
+mod quota;
+mod ratelimit;
+mod streaming;
+mod throttle;
 mod torrent;
+pub use magnet::*;
+pub use options::*;
+pub use preview::*;
+pub use queue::*;
+pub use quota::*;
+pub use ratelimit::*;
+pub use streaming::*;
+pub use throttle::PeerProxy;
 pub use torrent::*;
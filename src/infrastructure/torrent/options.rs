@@ -0,0 +1,99 @@
+use librqbit::{AddTorrent, AddTorrentOptions};
+use std::borrow::Cow;
+use std::time::Duration;
+
+/// Options every torrent is added with, on top of the ones of the request
+#[derive(Debug, Clone, Default)]
+pub struct TorrentDefaults {
+    trackers: Vec<String>,
+    disable_trackers: bool,
+    force_tracker_interval: Option<Duration>,
+}
+
+impl TorrentDefaults {
+    pub fn new(trackers: Vec<String>, trackers_enabled: bool, tracker_interval_secs: u64) -> Self {
+        Self {
+            trackers,
+            disable_trackers: !trackers_enabled,
+            force_tracker_interval: (tracker_interval_secs > 0).then(|| Duration::from_secs(tracker_interval_secs)),
+        }
+    }
+
+    pub fn apply(&self, options: AddTorrentOptions) -> AddTorrentOptions {
+        AddTorrentOptions {
+            disable_trackers: options.disable_trackers || self.disable_trackers,
+            force_tracker_interval: options.force_tracker_interval.or(self.force_tracker_interval),
+            ..options
+        }
+    }
+
+    /// librqbit only announces to the trackers of the torrent itself, so the extra trackers are added to
+    /// magnet links as `tr` parameters. Torrent files keep their own announce list
+    pub fn add_trackers<'a>(&self, torrent: AddTorrent<'a>) -> AddTorrent<'a> {
+        let AddTorrent::Url(url) = &torrent else {
+            return torrent;
+        };
+        let Ok(mut magnet) = reqwest::Url::parse(url) else {
+            return torrent;
+        };
+        if magnet.scheme() != "magnet" || self.trackers.is_empty() {
+            return torrent;
+        }
+
+        let known: Vec<String> = magnet
+            .query_pairs()
+            .filter(|(key, _)| key == "tr")
+            .map(|(_, tracker)| tracker.into_owned())
+            .collect();
+        {
+            let mut query = magnet.query_pairs_mut();
+            for tracker in self.trackers.iter().filter(|tracker| !known.contains(tracker)) {
+                query.append_pair("tr", tracker);
+            }
+        }
+
+        AddTorrent::Url(Cow::Owned(magnet.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn magnet(torrent: AddTorrent) -> String {
+        match torrent {
+            AddTorrent::Url(url) => url.into_owned(),
+            AddTorrent::TorrentFileBytes(_) => panic!("not a magnet link"),
+        }
+    }
+
+    #[test]
+    fn trackers_are_appended_to_magnet_links() {
+        let defaults = TorrentDefaults::new(
+            vec![
+                "udp://tracker.example.org:1337/announce".to_string(),
+                "https://tracker.example.com/announce".to_string(),
+            ],
+            true,
+            0,
+        );
+
+        let torrent = defaults.add_trackers(AddTorrent::from_url(
+            "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&tr=https%3A%2F%2Ftracker.example.com%2Fannounce",
+        ));
+
+        assert_eq!(
+            magnet(torrent),
+            "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&tr=https%3A%2F%2Ftracker.example.com%2Fannounce&tr=udp%3A%2F%2Ftracker.example.org%3A1337%2Fannounce"
+        );
+    }
+
+    #[test]
+    fn urls_of_torrent_files_are_left_alone() {
+        let defaults = TorrentDefaults::new(vec!["udp://tracker.example.org:1337/announce".to_string()], true, 0);
+
+        let torrent = defaults.add_trackers(AddTorrent::from_url("https://example.com/file.torrent"));
+
+        assert_eq!(magnet(torrent), "https://example.com/file.torrent");
+    }
+}
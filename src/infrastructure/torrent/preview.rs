@@ -0,0 +1,67 @@
+use actix_web::web::Bytes;
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// How long the metadata of a previewed torrent is kept waiting for the user to pick files
+const PREVIEW_TTL_SECONDS: i64 = 3600;
+
+#[derive(Debug, Clone)]
+pub struct TorrentPreviewEntry {
+    pub torrent_bytes: Bytes,
+    pub file_count: usize,
+    pub created_at: NaiveDateTime,
+}
+
+/// Metadata resolved by a preview, so adding the torrent afterwards does not have to fetch it again.
+///
+/// Previews are kept per user, several users may preview the same torrent at once
+pub struct TorrentPreviews {
+    previews: RwLock<HashMap<(String, Uuid), TorrentPreviewEntry>>,
+}
+
+impl Default for TorrentPreviews {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TorrentPreviews {
+    pub fn new() -> Self {
+        Self {
+            previews: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn insert(&self, info_hash: &str, torrent_bytes: Bytes, file_count: usize, user_id: Uuid) {
+        let now = Utc::now().naive_utc();
+        let mut previews = self.previews.write().unwrap();
+
+        previews.retain(|_, preview| now - preview.created_at < Duration::seconds(PREVIEW_TTL_SECONDS));
+        previews.insert(
+            (info_hash.to_lowercase(), user_id),
+            TorrentPreviewEntry {
+                torrent_bytes,
+                file_count,
+                created_at: now,
+            },
+        );
+    }
+
+    pub fn get(&self, info_hash: &str, user_id: Uuid) -> Option<TorrentPreviewEntry> {
+        self.previews
+            .read()
+            .unwrap()
+            .get(&(info_hash.to_lowercase(), user_id))
+            .filter(|preview| Utc::now().naive_utc() - preview.created_at < Duration::seconds(PREVIEW_TTL_SECONDS))
+            .cloned()
+    }
+
+    pub fn remove(&self, info_hash: &str, user_id: Uuid) {
+        self.previews
+            .write()
+            .unwrap()
+            .remove(&(info_hash.to_lowercase(), user_id));
+    }
+}
@@ -0,0 +1,181 @@
+use super::error::Result;
+use super::streaming::StreamingPriorities;
+use super::torrent::{get_torrent_handle, get_torrent_handles, pause_torrent, resume_torrent};
+use librqbit::{ManagedTorrent, Session, TorrentStatsState};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Limits how many torrents download at once, the others wait paused in the queue.
+/// Torrents being streamed always download and push other downloads back into the queue
+pub struct DownloadQueue {
+    max_active: Option<usize>,
+    queued: Mutex<Vec<String>>,
+}
+
+fn is_downloading(handle: &ManagedTorrent) -> bool {
+    let stats = handle.stats();
+    !stats.finished && matches!(stats.state, TorrentStatsState::Live)
+}
+
+impl DownloadQueue {
+    pub fn new(max_active: usize) -> Self {
+        Self {
+            max_active: (max_active > 0).then_some(max_active),
+            queued: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 1-based position of a queued torrent
+    pub fn position(&self, info_hash: &str) -> Option<usize> {
+        let info_hash = info_hash.to_lowercase();
+        self.queued
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|queued| *queued == info_hash)
+            .map(|position| position + 1)
+    }
+
+    /// Forget a torrent, used when it is paused, resumed or deleted by hand
+    pub fn remove(&self, info_hash: &str) {
+        let info_hash = info_hash.to_lowercase();
+        self.queued.lock().unwrap().retain(|queued| *queued != info_hash);
+    }
+
+    fn active(&self, manager: &Arc<Session>) -> Vec<Arc<ManagedTorrent>> {
+        let queued = self.queued.lock().unwrap().clone();
+        get_torrent_handles(manager)
+            .into_iter()
+            .filter(|handle| is_downloading(handle) && !queued.contains(&handle.info_hash().as_string()))
+            .collect()
+    }
+
+    /// Start a torrent added paused, or queue it when the download slots are taken
+    pub async fn admit(
+        &self,
+        manager: &Arc<Session>,
+        streaming: &StreamingPriorities,
+        handle: &Arc<ManagedTorrent>,
+    ) -> Result<()> {
+        let info_hash = handle.info_hash().as_string();
+        let has_slot = match self.max_active {
+            Some(max_active) => self.active(manager).len() < max_active,
+            None => true,
+        };
+
+        if has_slot || streaming.is_streaming(&info_hash) {
+            return resume_torrent(manager, handle).await;
+        }
+
+        tracing::info!("Queueing torrent {}", info_hash);
+        self.queued.lock().unwrap().push(info_hash);
+        Ok(())
+    }
+
+    async fn schedule(&self, manager: &Arc<Session>, streaming: &StreamingPriorities) {
+        let Some(max_active) = self.max_active else {
+            return;
+        };
+
+        // Torrents deleted from the session leave the queue
+        let queued = {
+            let mut queued = self.queued.lock().unwrap();
+            queued.retain(|info_hash| get_torrent_handle(manager, info_hash).is_ok());
+            queued.clone()
+        };
+
+        // Queued torrents that started streaming jump ahead of everything
+        for info_hash in queued.iter().filter(|info_hash| streaming.is_streaming(info_hash)) {
+            if let Ok(handle) = get_torrent_handle(manager, info_hash) {
+                tracing::info!("Starting queued torrent {} for streaming", info_hash);
+                self.remove(info_hash);
+                if let Err(err) = resume_torrent(manager, &handle).await {
+                    tracing::error!("Could not start queued torrent {}: {}", info_hash, err);
+                }
+            }
+        }
+
+        let mut active = self.active(manager);
+
+        // Over the limit, the download with the most bytes left that is not streamed goes back to the front
+        while active.len() > max_active {
+            let Some((idx, handle)) = active
+                .iter()
+                .enumerate()
+                .filter(|(_, handle)| !streaming.is_streaming(&handle.info_hash().as_string()))
+                .max_by_key(|(_, handle)| {
+                    let stats = handle.stats();
+                    stats.total_bytes.saturating_sub(stats.progress_bytes)
+                })
+                .map(|(idx, handle)| (idx, handle.clone()))
+            else {
+                break;
+            };
+
+            let info_hash = handle.info_hash().as_string();
+            tracing::info!("Moving torrent {} back to the queue", info_hash);
+            if let Err(err) = pause_torrent(manager, &handle).await {
+                tracing::error!("Could not pause torrent {}: {}", info_hash, err);
+                break;
+            }
+            self.queued.lock().unwrap().insert(0, info_hash);
+            active.remove(idx);
+        }
+
+        // Free slots are given to the queue in order
+        while active.len() < max_active {
+            let Some(info_hash) = self.queued.lock().unwrap().first().cloned() else {
+                break;
+            };
+            self.remove(&info_hash);
+
+            let Ok(handle) = get_torrent_handle(manager, &info_hash) else {
+                continue;
+            };
+            tracing::info!("Starting queued torrent {}", info_hash);
+            if let Err(err) = resume_torrent(manager, &handle).await {
+                tracing::error!("Could not start queued torrent {}: {}", info_hash, err);
+                continue;
+            }
+            active.push(handle);
+        }
+    }
+
+    /// The queue is not persisted, torrents librqbit restored paused and unfinished are queued again in
+    /// session order. The ones in `paused_by_user` were paused by hand and stay paused
+    pub async fn restore(&self, manager: &Arc<Session>, paused_by_user: &HashSet<String>) {
+        if self.max_active.is_none() {
+            return;
+        }
+
+        for handle in get_torrent_handles(manager) {
+            let info_hash = handle.info_hash().as_string();
+            if paused_by_user.contains(&info_hash) {
+                continue;
+            }
+            if let Err(err) = handle.wait_until_initialized().await {
+                tracing::error!("Could not restore torrent {} into the queue: {:?}", info_hash, err);
+                continue;
+            }
+
+            let stats = handle.stats();
+            let mut queued = self.queued.lock().unwrap();
+            if !stats.finished && matches!(stats.state, TorrentStatsState::Paused) && !queued.contains(&info_hash) {
+                tracing::info!("Queueing restored torrent {}", info_hash);
+                queued.push(info_hash);
+            }
+        }
+    }
+
+    pub async fn run(self: Arc<Self>, manager: Arc<Session>, streaming: Arc<StreamingPriorities>) {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            self.schedule(&manager, &streaming).await;
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::config::QuotaPolicy;
+use librqbit::Session;
+use std::sync::Arc;
+
+/// Storage budget of the download directory, counted in bytes of selected files so
+/// torrents still downloading reserve their final size
+pub struct DiskQuota {
+    budget: Option<u64>,
+    policy: QuotaPolicy,
+}
+
+impl DiskQuota {
+    pub fn new(budget_gb: u64, policy: QuotaPolicy) -> Self {
+        Self {
+            budget: (budget_gb > 0).then(|| budget_gb * 1024 * 1024 * 1024),
+            policy,
+        }
+    }
+
+    pub fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    pub fn policy(&self) -> QuotaPolicy {
+        self.policy
+    }
+
+    /// Bytes reserved by the torrents of the session, except `exclude`
+    pub fn reserved(&self, manager: &Arc<Session>, exclude: Option<&str>) -> u64 {
+        manager.with_torrents(|torrents| {
+            torrents
+                .filter(|(_, handle)| exclude != Some(handle.info_hash().as_string().as_str()))
+                .map(|(_, handle)| handle.stats().total_bytes)
+                .sum()
+        })
+    }
+
+    pub fn fits(&self, reserved: u64, required: u64) -> bool {
+        match self.budget {
+            Some(budget) => reserved + required <= budget,
+            None => true,
+        }
+    }
+}
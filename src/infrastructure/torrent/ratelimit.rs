@@ -0,0 +1,174 @@
+use super::error::{Result, TorrentError};
+use super::throttle::Bandwidth;
+use apistos::ApiComponent;
+use chrono::{Local, NaiveTime};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time of day during which the limits are lifted, `01:00-07:00`, it may wrap around midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnlimitedWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl UnlimitedWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for UnlimitedWindow {
+    type Err = TorrentError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || TorrentError::InvalidSchedule(s.to_string());
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+
+        Ok(Self {
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Display for UnlimitedWindow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+/// Comma separated list of windows, an empty string means no schedule
+pub fn parse_schedule(schedule: &str) -> Result<Vec<UnlimitedWindow>> {
+    schedule
+        .split(',')
+        .map(str::trim)
+        .filter(|window| !window.is_empty())
+        .map(UnlimitedWindow::from_str)
+        .collect()
+}
+
+#[derive(Debug, Serialize, ApiComponent, JsonSchema)]
+pub struct RateLimitStatus {
+    /// KiB/s, `None` when unlimited
+    pub download_limit_kib: Option<u32>,
+    pub upload_limit_kib: Option<u32>,
+    pub unlimited_windows: Vec<String>,
+    /// Whether the current time falls in one of the unlimited windows
+    pub unlimited_now: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Limits {
+    download_kib: Option<u32>,
+    upload_kib: Option<u32>,
+    windows: Vec<UnlimitedWindow>,
+}
+
+/// Rate limits of the connections librqbit opens to peers, lifted during the unlimited windows of the schedule.
+/// They are enforced by the peer proxy, see [`super::PeerProxy`]
+pub struct RateLimits {
+    limits: RwLock<Limits>,
+    /// Download and upload limits last pushed to the peer proxy
+    applied: Mutex<Option<(Option<u32>, Option<u32>)>>,
+    download: Arc<Bandwidth>,
+    upload: Arc<Bandwidth>,
+}
+
+impl RateLimits {
+    pub fn new(download_kib: u32, upload_kib: u32, schedule: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            limits: RwLock::new(Limits {
+                download_kib: (download_kib > 0).then_some(download_kib),
+                upload_kib: (upload_kib > 0).then_some(upload_kib),
+                windows: parse_schedule(schedule.unwrap_or_default())?,
+            }),
+            applied: Mutex::new(None),
+            download: Arc::new(Bandwidth::default()),
+            upload: Arc::new(Bandwidth::default()),
+        })
+    }
+
+    pub fn download(&self) -> &Arc<Bandwidth> {
+        &self.download
+    }
+
+    pub fn upload(&self) -> &Arc<Bandwidth> {
+        &self.upload
+    }
+
+    pub fn get(&self) -> RateLimitStatus {
+        let limits = self.limits.read().unwrap();
+        let now = Local::now().time();
+
+        RateLimitStatus {
+            download_limit_kib: limits.download_kib,
+            upload_limit_kib: limits.upload_kib,
+            unlimited_windows: limits.windows.iter().map(ToString::to_string).collect(),
+            unlimited_now: limits.windows.iter().any(|window| window.contains(now)),
+        }
+    }
+
+    pub fn set(
+        &self,
+        download_kib: Option<u32>,
+        upload_kib: Option<u32>,
+        windows: Vec<UnlimitedWindow>,
+    ) -> RateLimitStatus {
+        *self.limits.write().unwrap() = Limits {
+            download_kib,
+            upload_kib,
+            windows,
+        };
+        self.apply();
+
+        self.get()
+    }
+
+    /// Push the limits in effect right now to the peer proxy, when they changed
+    pub fn apply(&self) {
+        let limits = self.limits.read().unwrap().clone();
+        let now = Local::now().time();
+
+        let effective = if limits.windows.iter().any(|window| window.contains(now)) {
+            (None, None)
+        } else {
+            (limits.download_kib, limits.upload_kib)
+        };
+
+        let mut applied = self.applied.lock().unwrap();
+        if *applied == Some(effective) {
+            return;
+        }
+
+        let to_bps = |kib: Option<u32>| kib.map(|kib| kib as u64 * 1024);
+        self.download.set_limit(to_bps(effective.0));
+        self.upload.set_limit(to_bps(effective.1));
+
+        tracing::info!(
+            "Rate limits set to {} KiB/s down, {} KiB/s up",
+            effective.0.map_or("unlimited".to_string(), |kib| kib.to_string()),
+            effective.1.map_or("unlimited".to_string(), |kib| kib.to_string())
+        );
+        *applied = Some(effective);
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            self.apply();
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use super::error::Result;
+use super::torrent::{get_first_missing_offset, get_torrent_file, TorrentFile};
+use crate::infrastructure::transcode::probe_bitrate;
+use apistos::ApiComponent;
+use librqbit::ManagedTorrent;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncSeek, AsyncSeekExt};
+
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// Torrents nobody has streamed from for this long go back to the normal download order
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// The container header may not be downloaded yet, a failed probe is retried after this long
+const PROBE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+type PriorityStream = Box<dyn AsyncSeek + Send + Unpin>;
+
+struct StreamingEntry {
+    handle: Arc<ManagedTorrent>,
+    file: TorrentFile,
+    readahead: u64,
+    playhead: u64,
+    bitrate: Option<u64>,
+    probed_at: Option<Instant>,
+    last_active_at: Instant,
+    /// librqbit fetches the pieces in front of its open streams first, the stream is only held
+    /// while the readahead window has holes so the rest of the torrent goes back to the normal order
+    stream: Arc<tokio::sync::Mutex<Option<PriorityStream>>>,
+}
+
+#[derive(Debug, Serialize, ApiComponent, JsonSchema)]
+pub struct StreamingStatus {
+    pub enabled: bool,
+    pub file_idx: Option<usize>,
+    pub readahead_bytes: u64,
+    pub playhead_bytes: u64,
+}
+
+/// Streaming priority of the torrents being watched: pieces are fetched sequentially from the playhead
+/// over a readahead window, while the rest of the torrent keeps downloading in librqbit's normal order
+pub struct StreamingPriorities {
+    entries: Mutex<HashMap<String, StreamingEntry>>,
+    /// Torrents on which the streaming mode was turned off through the API, files opened for streaming
+    /// do not switch them back on
+    disabled: RwLock<HashSet<String>>,
+    default_readahead: u64,
+    download_dir: PathBuf,
+}
+
+impl StreamingPriorities {
+    pub fn new(default_readahead: u64, download_dir: PathBuf) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            disabled: RwLock::new(HashSet::new()),
+            default_readahead,
+            download_dir,
+        }
+    }
+
+    pub fn get(&self, info_hash: &str) -> StreamingStatus {
+        match self.entries.lock().unwrap().get(&info_hash.to_lowercase()) {
+            Some(entry) => StreamingStatus {
+                enabled: true,
+                file_idx: Some(entry.file.index),
+                readahead_bytes: entry.readahead,
+                playhead_bytes: entry.playhead,
+            },
+            None => StreamingStatus {
+                enabled: false,
+                file_idx: None,
+                readahead_bytes: self.default_readahead,
+                playhead_bytes: 0,
+            },
+        }
+    }
+
+    pub fn is_streaming(&self, info_hash: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(&info_hash.to_lowercase())
+    }
+
+    /// Switch a torrent into streaming priority for one of its files
+    pub fn enable(&self, handle: Arc<ManagedTorrent>, file_idx: usize, readahead: Option<u64>) -> Result<()> {
+        let info_hash = handle.info_hash().as_string();
+        let file = get_torrent_file(&self.download_dir, &handle, file_idx)?;
+
+        self.disabled.write().unwrap().remove(&info_hash);
+
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get_mut(&info_hash) {
+            Some(entry) if entry.file.index == file_idx && Arc::ptr_eq(&entry.handle, &handle) => {
+                entry.readahead = readahead.unwrap_or(entry.readahead);
+                entry.last_active_at = Instant::now();
+            }
+            _ => {
+                entries.insert(
+                    info_hash,
+                    StreamingEntry {
+                        handle,
+                        file,
+                        readahead: readahead.unwrap_or(self.default_readahead),
+                        playhead: 0,
+                        bitrate: None,
+                        probed_at: None,
+                        last_active_at: Instant::now(),
+                        stream: Arc::new(tokio::sync::Mutex::new(None)),
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn disable(&self, info_hash: &str) {
+        let info_hash = info_hash.to_lowercase();
+
+        self.entries.lock().unwrap().remove(&info_hash);
+        self.disabled.write().unwrap().insert(info_hash);
+    }
+
+    /// Forget a torrent that was removed from the session, a torrent added again later starts from scratch
+    pub fn remove(&self, info_hash: &str) {
+        let info_hash = info_hash.to_lowercase();
+
+        self.entries.lock().unwrap().remove(&info_hash);
+        self.disabled.write().unwrap().remove(&info_hash);
+    }
+
+    /// Keep streaming a torrent that was added again to the session under a new handle
+    pub fn replace_handle(&self, handle: Arc<ManagedTorrent>) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&handle.info_hash().as_string()) {
+            entry.handle = handle;
+            entry.stream = Arc::new(tokio::sync::Mutex::new(None));
+        }
+    }
+
+    /// Called when a file is opened for streaming, unless streaming priority was turned off for the torrent
+    pub fn open(&self, handle: Arc<ManagedTorrent>, file_idx: usize) {
+        let info_hash = handle.info_hash().as_string();
+        if self.disabled.read().unwrap().contains(&info_hash) || handle.stats().finished {
+            return;
+        }
+
+        // A recheck adds the torrent again under a new handle, the entry of the old one is replaced
+        let already_open = match self.entries.lock().unwrap().get_mut(&info_hash) {
+            Some(entry) if entry.file.index == file_idx && Arc::ptr_eq(&entry.handle, &handle) => {
+                entry.last_active_at = Instant::now();
+                true
+            }
+            _ => false,
+        };
+
+        if !already_open {
+            if let Err(err) = self.enable(handle, file_idx, None) {
+                tracing::warn!("Could not enable streaming priority on {}: {}", info_hash, err);
+            }
+        }
+    }
+
+    pub fn set_playhead(&self, info_hash: &str, file_idx: usize, offset: u64) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&info_hash.to_lowercase()) {
+            if entry.file.index == file_idx {
+                entry.playhead = offset.min(entry.file.length);
+                entry.last_active_at = Instant::now();
+            }
+        }
+    }
+
+    /// Move the playhead to a position in seconds, converted to bytes with the bitrate of the file
+    pub async fn set_playhead_seconds(&self, info_hash: &str, file_idx: usize, seconds: f64) {
+        let info_hash = info_hash.to_lowercase();
+
+        let (bitrate, path) = match self.entries.lock().unwrap().get_mut(&info_hash) {
+            Some(entry) if entry.file.index == file_idx => {
+                entry.last_active_at = Instant::now();
+                if entry.bitrate.is_none() {
+                    // Segments are requested every few seconds, only one of them probes the file
+                    if entry.probed_at.is_some_and(|at| at.elapsed() < PROBE_RETRY_INTERVAL) {
+                        return;
+                    }
+                    entry.probed_at = Some(Instant::now());
+                }
+                (entry.bitrate, entry.file.path.clone())
+            }
+            _ => return,
+        };
+
+        let bitrate = match bitrate {
+            Some(bitrate) => bitrate,
+            None => {
+                let Some(bitrate) = probe_bitrate(&path).await else {
+                    return;
+                };
+                if let Some(entry) = self.entries.lock().unwrap().get_mut(&info_hash) {
+                    entry.bitrate = Some(bitrate);
+                }
+                bitrate
+            }
+        };
+
+        self.set_playhead(&info_hash, file_idx, (seconds.max(0.0) * bitrate as f64 / 8.0) as u64);
+    }
+
+    async fn tick(&self) {
+        let entries = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(info_hash, entry)| {
+                (
+                    info_hash.clone(),
+                    entry.handle.clone(),
+                    entry.file.clone(),
+                    entry.playhead,
+                    entry.readahead,
+                    entry.stream.clone(),
+                    entry.last_active_at,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (info_hash, handle, file, playhead, readahead, stream, last_active_at) in entries {
+            if handle.stats().finished {
+                tracing::debug!("Torrent {} finished, leaving streaming priority", info_hash);
+                self.entries.lock().unwrap().remove(&info_hash);
+                continue;
+            }
+            if last_active_at.elapsed() >= IDLE_TIMEOUT {
+                tracing::debug!("Torrent {} no longer streamed, leaving streaming priority", info_hash);
+                self.entries.lock().unwrap().remove(&info_hash);
+                continue;
+            }
+
+            // Holes are looked up piece by piece, the progress of the file only tells when none are left
+            let file = TorrentFile {
+                downloaded: handle
+                    .stats()
+                    .file_progress
+                    .get(file.index)
+                    .copied()
+                    .unwrap_or_default(),
+                ..file
+            };
+            let Ok(missing) =
+                get_first_missing_offset(&handle, file.index, &file, playhead, playhead.saturating_add(readahead))
+                    .await
+            else {
+                continue;
+            };
+
+            let mut stream = stream.lock().await;
+            match missing {
+                Some(offset) => {
+                    if stream.is_none() {
+                        match handle.clone().stream(file.index) {
+                            Ok(opened) => *stream = Some(Box::new(opened)),
+                            Err(err) => {
+                                tracing::warn!("Could not open priority stream on {}: {:?}", info_hash, err);
+                                continue;
+                            }
+                        }
+                    }
+
+                    if let Some(stream) = stream.as_mut() {
+                        if let Err(err) = stream.seek(SeekFrom::Start(offset)).await {
+                            tracing::warn!("Could not move priority stream on {}: {}", info_hash, err);
+                        }
+                    }
+                }
+                None => *stream = None,
+            }
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(TICK_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+            self.tick().await;
+        }
+    }
+}
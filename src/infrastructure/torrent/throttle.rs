@@ -0,0 +1,455 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_socks::tcp::Socks5Stream;
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CONNECT: u8 = 1;
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_GENERAL_FAILURE: u8 = 1;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 8;
+const BUFFER_SIZE: usize = 16 * 1024;
+/// First byte of the BitTorrent handshake, the length of "BitTorrent protocol"
+const HANDSHAKE_PROTOCOL_LENGTH: u8 = 19;
+
+/// Token bucket shared by every peer connection. Bytes are taken before they are sent and paid back by
+/// waiting, so the average rate holds whatever the size of the reads
+pub struct Bandwidth {
+    /// 0 when unlimited
+    bytes_per_second: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    available: f64,
+    updated_at: Instant,
+}
+
+impl Default for Bandwidth {
+    fn default() -> Self {
+        Self {
+            bytes_per_second: AtomicU64::new(0),
+            bucket: Mutex::new(Bucket {
+                available: 0.0,
+                updated_at: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl Bandwidth {
+    pub fn set_limit(&self, bytes_per_second: Option<u64>) {
+        *self.bucket.lock().unwrap() = Bucket {
+            available: 0.0,
+            updated_at: Instant::now(),
+        };
+        self.bytes_per_second
+            .store(bytes_per_second.unwrap_or_default(), Ordering::Relaxed);
+    }
+
+    /// Take `bytes` from the bucket, returns how long to wait before sending them
+    fn reserve(&self, bytes: usize, now: Instant) -> Option<Duration> {
+        let rate = self.bytes_per_second.load(Ordering::Relaxed);
+        if rate == 0 {
+            return None;
+        }
+        let rate = rate as f64;
+
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        // Idle time only builds up one second worth of burst
+        bucket.available = (bucket.available + elapsed * rate).min(rate) - bytes as f64;
+        bucket.updated_at = now;
+
+        (bucket.available < 0.0).then(|| Duration::from_secs_f64(-bucket.available / rate))
+    }
+
+    async fn consume(&self, bytes: usize) {
+        if let Some(wait) = self.reserve(bytes, Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Proxy `SOCKS_PROXY_URL` connections are chained to
+struct Upstream {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+}
+
+enum Target {
+    Address(SocketAddr),
+    Domain(String, u16),
+}
+
+/// Public listen port, peers connecting in are relayed to the port librqbit listens on
+struct Inbound {
+    listener: TcpListener,
+    librqbit_port: u16,
+}
+
+/// Local SOCKS5 proxy librqbit opens its connections through, it is where the rate limits and the peer
+/// connection limit are enforced since librqbit has no limits of its own. Peers connecting in go through
+/// it as well when it holds the public listen port, see [`PeerProxy::listen`]
+pub struct PeerProxy {
+    listener: TcpListener,
+    inbound: Option<Inbound>,
+    upstream: Option<Upstream>,
+    download: Arc<Bandwidth>,
+    upload: Arc<Bandwidth>,
+    /// Permits of the peer connections open at once, `None` when unlimited
+    connections: Option<Arc<Semaphore>>,
+}
+
+impl PeerProxy {
+    pub async fn bind(
+        download: Arc<Bandwidth>,
+        upload: Arc<Bandwidth>,
+        max_connections: usize,
+        upstream: Option<&str>,
+    ) -> io::Result<Self> {
+        let upstream = upstream
+            .map(|upstream| {
+                let url = reqwest::Url::parse(upstream).map_err(io::Error::other)?;
+                Ok::<_, io::Error>(Upstream {
+                    host: url.host_str().unwrap_or_default().to_string(),
+                    port: url.port().unwrap_or_default(),
+                    credentials: url
+                        .password()
+                        .map(|password| (url.username().to_string(), password.to_string())),
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            listener: TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?,
+            inbound: None,
+            upstream,
+            download,
+            upload,
+            connections: (max_connections > 0).then(|| Arc::new(Semaphore::new(max_connections))),
+        })
+    }
+
+    /// Take the first free port of `port_range` for the peers connecting in, returns the port range to
+    /// give librqbit, a single port only reached through the proxy
+    pub async fn listen(&mut self, port_range: Range<u16>) -> io::Result<Range<u16>> {
+        let mut listener = None;
+        for port in port_range.clone() {
+            match TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+                Ok(bound) => {
+                    listener = Some(bound);
+                    break;
+                }
+                Err(err) => tracing::debug!("Peer proxy could not listen on port {}: {}", port, err),
+            }
+        }
+        let listener = listener.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("no free port in the listen port range {:?}", port_range),
+            )
+        })?;
+
+        // Bound only to find a free port, librqbit listens on it once released
+        let librqbit_port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?.local_addr()?.port();
+        tracing::info!(
+            "Listening on port {} for incoming peer connections, relayed to librqbit on port {}",
+            listener.local_addr()?.port(),
+            librqbit_port
+        );
+        self.inbound = Some(Inbound {
+            listener,
+            librqbit_port,
+        });
+
+        Ok(librqbit_port..librqbit_port + 1)
+    }
+
+    /// Public port peers connect in on, when listening
+    pub fn public_port(&self) -> Option<u16> {
+        self.inbound
+            .as_ref()
+            .and_then(|inbound| inbound.listener.local_addr().ok())
+            .map(|address| address.port())
+    }
+
+    /// Proxy url to give librqbit
+    pub fn url(&self) -> io::Result<String> {
+        Ok(format!("socks5://{}", self.listener.local_addr()?))
+    }
+
+    pub async fn run(self) {
+        let proxy = Arc::new(self);
+
+        if proxy.inbound.is_some() {
+            tokio::spawn(proxy.clone().accept_inbound());
+        }
+
+        loop {
+            let client = match proxy.listener.accept().await {
+                Ok((client, _)) => client,
+                Err(err) => {
+                    tracing::warn!("Peer proxy could not accept a connection: {}", err);
+                    continue;
+                }
+            };
+
+            let proxy = proxy.clone();
+            tokio::spawn(async move {
+                if let Err(err) = proxy.relay(client).await {
+                    tracing::debug!("Peer proxy connection closed: {}", err);
+                }
+            });
+        }
+    }
+
+    async fn accept_inbound(self: Arc<Self>) {
+        let Some(inbound) = &self.inbound else {
+            return;
+        };
+
+        loop {
+            let peer = match inbound.listener.accept().await {
+                Ok((peer, _)) => peer,
+                Err(err) => {
+                    tracing::warn!("Peer proxy could not accept an incoming peer: {}", err);
+                    continue;
+                }
+            };
+
+            let proxy = self.clone();
+            let librqbit_port = inbound.librqbit_port;
+            tokio::spawn(async move {
+                if let Err(err) = proxy.relay_inbound(peer, librqbit_port).await {
+                    tracing::debug!("Incoming peer connection closed: {}", err);
+                }
+            });
+        }
+    }
+
+    /// Held until the relay ends, fails when the peer connection limit is reached
+    fn permit(&self) -> io::Result<Option<OwnedSemaphorePermit>> {
+        match &self.connections {
+            Some(connections) => connections
+                .clone()
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "peer connection limit reached")),
+            None => Ok(None),
+        }
+    }
+
+    async fn relay(&self, mut client: TcpStream) -> io::Result<()> {
+        let target = handshake(&mut client).await?;
+
+        let mut peer = match self.connect(&target).await {
+            Ok(peer) => peer,
+            Err(err) => {
+                reply(&mut client, REPLY_GENERAL_FAILURE).await?;
+                return Err(err);
+            }
+        };
+        reply(&mut client, REPLY_SUCCEEDED).await?;
+
+        // librqbit also fetches torrent files and announces to HTTP trackers through the proxy, only
+        // connections starting with the BitTorrent handshake are peers
+        let mut first = [0u8; 1];
+        if client.peek(&mut first).await? == 0 || first[0] != HANDSHAKE_PROTOCOL_LENGTH {
+            tokio::io::copy_bidirectional(&mut client, &mut peer).await?;
+            return Ok(());
+        }
+
+        // Closed before the handshake when refused, librqbit gives up on the peer
+        let _permit = self.permit()?;
+        self.throttle(client, peer).await
+    }
+
+    async fn relay_inbound(&self, peer: TcpStream, librqbit_port: u16) -> io::Result<()> {
+        let _permit = self.permit()?;
+        let client = TcpStream::connect((Ipv4Addr::LOCALHOST, librqbit_port)).await?;
+
+        self.throttle(client, peer).await
+    }
+
+    /// Relay between librqbit and a peer, what the peer sends counts as download
+    async fn throttle(&self, mut client: TcpStream, mut peer: TcpStream) -> io::Result<()> {
+        let (client_read, client_write) = client.split();
+        let (peer_read, peer_write) = peer.split();
+        tokio::try_join!(
+            pump(peer_read, client_write, &self.download),
+            pump(client_read, peer_write, &self.upload)
+        )?;
+
+        Ok(())
+    }
+
+    async fn connect(&self, target: &Target) -> io::Result<TcpStream> {
+        let Some(upstream) = &self.upstream else {
+            return match target {
+                Target::Address(address) => TcpStream::connect(address).await,
+                Target::Domain(host, port) => TcpStream::connect((host.as_str(), *port)).await,
+            };
+        };
+
+        let proxy = (upstream.host.as_str(), upstream.port);
+        let stream = match (target, &upstream.credentials) {
+            (Target::Address(address), None) => Socks5Stream::connect(proxy, *address).await,
+            (Target::Address(address), Some((username, password))) => {
+                Socks5Stream::connect_with_password(proxy, *address, username, password).await
+            }
+            (Target::Domain(host, port), None) => Socks5Stream::connect(proxy, (host.as_str(), *port)).await,
+            (Target::Domain(host, port), Some((username, password))) => {
+                Socks5Stream::connect_with_password(proxy, (host.as_str(), *port), username, password).await
+            }
+        };
+
+        // Past the handshake the upstream proxy is a plain pipe
+        stream.map(Socks5Stream::into_inner).map_err(io::Error::other)
+    }
+}
+
+/// Negotiate a SOCKS5 CONNECT without authentication, the proxy only listens on localhost
+async fn handshake(client: &mut TcpStream) -> io::Result<Target> {
+    let mut greeting = [0u8; 2];
+    client.read_exact(&mut greeting).await?;
+    if greeting[0] != SOCKS_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a SOCKS5 client"));
+    }
+
+    let mut methods = vec![0u8; greeting[1] as usize];
+    client.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTHENTICATION) {
+        client.write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHOD]).await?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no supported authentication",
+        ));
+    }
+    client.write_all(&[SOCKS_VERSION, NO_AUTHENTICATION]).await?;
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request).await?;
+    if request[1] != CONNECT {
+        reply(client, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "only CONNECT is supported"));
+    }
+
+    let target = match request[3] {
+        ADDRESS_IPV4 => {
+            let mut ip = [0u8; 4];
+            client.read_exact(&mut ip).await?;
+            Target::Address(SocketAddr::new(
+                IpAddr::from(Ipv4Addr::from(ip)),
+                client.read_u16().await?,
+            ))
+        }
+        ADDRESS_IPV6 => {
+            let mut ip = [0u8; 16];
+            client.read_exact(&mut ip).await?;
+            Target::Address(SocketAddr::new(
+                IpAddr::from(Ipv6Addr::from(ip)),
+                client.read_u16().await?,
+            ))
+        }
+        ADDRESS_DOMAIN => {
+            let mut host = vec![0u8; client.read_u8().await? as usize];
+            client.read_exact(&mut host).await?;
+            let host = String::from_utf8(host).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            Target::Domain(host, client.read_u16().await?)
+        }
+        _ => {
+            reply(client, REPLY_ADDRESS_NOT_SUPPORTED).await?;
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "unknown address type"));
+        }
+    };
+
+    Ok(target)
+}
+
+async fn reply(client: &mut TcpStream, status: u8) -> io::Result<()> {
+    client
+        .write_all(&[SOCKS_VERSION, status, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+async fn pump<R, W>(mut from: R, mut to: W, bandwidth: &Bandwidth) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    loop {
+        let read = from.read(&mut buffer).await?;
+        if read == 0 {
+            return to.shutdown().await;
+        }
+
+        bandwidth.consume(read).await;
+        to.write_all(&buffer[..read]).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(bytes_per_second: u64, now: Instant) -> Bandwidth {
+        let bandwidth = Bandwidth::default();
+        bandwidth.set_limit(Some(bytes_per_second));
+        bandwidth.bucket.lock().unwrap().updated_at = now;
+        bandwidth
+    }
+
+    #[test]
+    fn unlimited_bandwidth_never_waits() {
+        let bandwidth = Bandwidth::default();
+
+        assert_eq!(bandwidth.reserve(usize::MAX, Instant::now()), None);
+    }
+
+    #[test]
+    fn connections_share_the_rate() {
+        let now = Instant::now();
+        let bandwidth = limited(1000, now);
+
+        assert_eq!(bandwidth.reserve(1000, now), Some(Duration::from_secs(1)));
+        assert_eq!(bandwidth.reserve(1000, now), Some(Duration::from_secs(2)));
+        assert_eq!(bandwidth.reserve(500, now + Duration::from_secs(3)), None);
+    }
+
+    #[test]
+    fn idle_time_builds_up_one_second_of_burst() {
+        let now = Instant::now();
+        let bandwidth = limited(1000, now);
+
+        assert_eq!(
+            bandwidth.reserve(1500, now + Duration::from_secs(10)),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn changing_the_limit_empties_the_bucket() {
+        let bandwidth = limited(1000, Instant::now());
+        bandwidth.reserve(5000, Instant::now());
+
+        bandwidth.set_limit(Some(2000));
+
+        assert_eq!(bandwidth.reserve(0, Instant::now()), None);
+    }
+}
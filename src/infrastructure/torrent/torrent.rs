@@ -1,15 +1,35 @@
 use super::error::Result;
+use super::options::TorrentDefaults;
 use crate::infrastructure::torrent::error::TorrentError;
+use actix_web::web::Bytes;
+use futures::FutureExt;
 use librqbit::api::TorrentIdOrHash;
-use librqbit::{ManagedTorrent, Session};
+use librqbit::{AddTorrent, AddTorrentOptions, ManagedTorrent, Session, TorrentStatsState};
+use librqbit_core::lengths::Lengths;
 use librqbit_core::Id20;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
 
-pub fn get_torrent_handle<S: AsRef<str>>(
-    manager: &Arc<Session>,
-    hash: S,
-) -> Result<Arc<ManagedTorrent>> {
+#[derive(Debug, Clone)]
+pub struct TorrentFile {
+    pub index: usize,
+    pub name: String,
+    pub path: PathBuf,
+    pub offset: u64,
+    pub length: u64,
+    pub downloaded: u64,
+}
+
+impl TorrentFile {
+    pub fn is_finished(&self) -> bool {
+        self.downloaded >= self.length
+    }
+}
+
+pub fn get_torrent_handle<S: AsRef<str>>(manager: &Arc<Session>, hash: S) -> Result<Arc<ManagedTorrent>> {
     let hash_ref = hash.as_ref();
 
     let id = Id20::from_str(hash_ref).map_err(|_| TorrentError::InvalidHash)?;
@@ -22,3 +42,237 @@ pub fn get_torrent_handle<S: AsRef<str>>(
         None => Err(TorrentError::TorrentNotFound),
     }
 }
+
+/// Folder librqbit downloads a torrent added without an output folder to: multi-file torrents get a
+/// sub folder named after the torrent, or after their largest file when it has no name
+pub fn get_torrent_folder(download_dir: &Path, handle: &ManagedTorrent) -> Result<PathBuf> {
+    let info = &handle.shared().info;
+    let files = info
+        .iter_filenames_and_lengths()
+        .map_err(|_| TorrentError::InvalidLengths)?
+        .map(|(filename, length)| filename.to_pathbuf().map(|path| (path, length)))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| TorrentError::InvalidLengths)?;
+    if files.len() < 2 {
+        return Ok(download_dir.to_path_buf());
+    }
+
+    let sub_folder = match &info.name {
+        Some(name) => PathBuf::from(String::from_utf8_lossy(&name.0).into_owned()),
+        None => files
+            .iter()
+            .max_by_key(|(_, length)| *length)
+            .and_then(|(path, _)| path.file_stem())
+            .map(PathBuf::from)
+            .ok_or(TorrentError::InvalidLengths)?,
+    };
+
+    Ok(download_dir.join(sub_folder))
+}
+
+pub fn get_torrent_file(download_dir: &Path, handle: &ManagedTorrent, file_idx: usize) -> Result<TorrentFile> {
+    get_torrent_files(download_dir, handle)?
+        .into_iter()
+        .nth(file_idx)
+        .ok_or(TorrentError::FileNotFound)
+}
+
+/// Whether the piece holding `position` in the file is downloaded and verified.
+///
+/// librqbit keeps the pieces it has private. A file stream answers a read right away when the piece under its
+/// position is there and waits for it otherwise, so a single byte read polled once tells without blocking
+async fn has_piece_at<S: AsyncRead + AsyncSeek + Unpin>(stream: &mut S, position: u64) -> bool {
+    if stream.seek(SeekFrom::Start(position)).await.is_err() {
+        return false;
+    }
+
+    let mut byte = [0u8; 1];
+    matches!(stream.read(&mut byte).now_or_never(), Some(Ok(1)))
+}
+
+/// Offset in the file of the first missing byte in `[from, to)`, `None` when every piece of the range is there
+pub async fn get_first_missing_offset(
+    handle: &Arc<ManagedTorrent>,
+    file_idx: usize,
+    file: &TorrentFile,
+    from: u64,
+    to: u64,
+) -> Result<Option<u64>> {
+    let to = to.min(file.length);
+    if file.is_finished() || from >= to {
+        return Ok(None);
+    }
+
+    let piece_length = handle.shared().lengths.default_piece_length() as u64;
+    // Initializing torrents have no piece information yet
+    let mut stream = handle
+        .clone()
+        .stream(file_idx)
+        .map_err(|_| TorrentError::TorrentNotLive)?;
+
+    let mut position = from;
+    while position < to {
+        if !has_piece_at(&mut stream, position).await {
+            return Ok(Some(position));
+        }
+
+        // Pieces are aligned on the torrent, the first one of a file usually starts before it
+        let absolute = file.offset + position;
+        position = (absolute / piece_length + 1) * piece_length - file.offset;
+    }
+
+    Ok(None)
+}
+
+/// Number of bytes available contiguously from the start of the file
+pub async fn get_file_head_bytes(handle: &Arc<ManagedTorrent>, file_idx: usize, file: &TorrentFile) -> Result<u64> {
+    Ok(get_first_missing_offset(handle, file_idx, file, 0, file.length)
+        .await?
+        .unwrap_or(file.length))
+}
+
+pub fn get_torrent_handles(manager: &Arc<Session>) -> Vec<Arc<ManagedTorrent>> {
+    manager.with_torrents(|torrents| torrents.map(|(_, handle)| handle.clone()).collect())
+}
+
+/// Name from the metainfo, falling back to the info hash for torrents that do not set one
+pub fn get_torrent_name(handle: &ManagedTorrent) -> String {
+    handle
+        .shared()
+        .info
+        .name
+        .as_ref()
+        .map(|name| String::from_utf8_lossy(&name.0).into_owned())
+        .unwrap_or_else(|| handle.info_hash().as_string())
+}
+
+pub fn get_torrent_files(download_dir: &Path, handle: &ManagedTorrent) -> Result<Vec<TorrentFile>> {
+    let shared = handle.shared();
+    let lengths = Lengths::from_torrent(&shared.info).map_err(|_| TorrentError::InvalidLengths)?;
+    let file_progress = handle.stats().file_progress;
+    let folder = get_torrent_folder(download_dir, handle)?;
+
+    let files = shared
+        .info
+        .iter_file_details(&lengths)
+        .map_err(|_| TorrentError::InvalidLengths)?
+        .enumerate()
+        .map(|(file_idx, file_details)| {
+            let components = file_details.filename.to_vec().unwrap_or_default();
+            let path = components
+                .iter()
+                .fold(folder.clone(), |path, component| path.join(component));
+
+            TorrentFile {
+                index: file_idx,
+                name: components.last().cloned().unwrap_or_default(),
+                path,
+                offset: file_details.offset,
+                length: file_details.len,
+                downloaded: file_progress.get(file_idx).copied().unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    Ok(files)
+}
+
+/// Whether the file is downloaded, files left out of the selection never are
+pub fn is_file_selected(handle: &ManagedTorrent, file_idx: usize) -> bool {
+    handle
+        .only_files()
+        .map_or(true, |only_files| only_files.contains(&file_idx))
+}
+
+pub async fn pause_torrent(manager: &Arc<Session>, handle: &Arc<ManagedTorrent>) -> Result<()> {
+    if matches!(handle.stats().state, TorrentStatsState::Paused) {
+        return Err(TorrentError::AlreadyPaused);
+    }
+
+    manager.pause(handle).await.map_err(|e| {
+        tracing::error!("Error pausing torrent: {:?}", e);
+        TorrentError::PauseFailed
+    })
+}
+
+pub async fn resume_torrent(manager: &Arc<Session>, handle: &Arc<ManagedTorrent>) -> Result<()> {
+    if !matches!(
+        handle.stats().state,
+        TorrentStatsState::Paused | TorrentStatsState::Error
+    ) {
+        return Err(TorrentError::NotPaused);
+    }
+
+    manager.unpause(handle).await.map_err(|e| {
+        tracing::error!("Error resuming torrent: {:?}", e);
+        TorrentError::ResumeFailed
+    })
+}
+
+pub async fn delete_torrent(manager: &Arc<Session>, handle: &Arc<ManagedTorrent>, delete_files: bool) -> Result<()> {
+    manager
+        .delete(TorrentIdOrHash::Hash(handle.info_hash()), delete_files)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error deleting torrent: {:?}", e);
+            TorrentError::DeleteFailed
+        })
+}
+
+async fn add_torrent_bytes(
+    manager: &Arc<Session>,
+    torrent_bytes: Bytes,
+    options: AddTorrentOptions,
+) -> Result<Arc<ManagedTorrent>> {
+    let response = manager
+        .add_torrent(AddTorrent::from_bytes(torrent_bytes), Some(options))
+        .await
+        .map_err(|e| {
+            tracing::error!("Error adding torrent back for recheck: {:?}", e);
+            TorrentError::RecheckFailed
+        })?;
+
+    response.into_handle().ok_or(TorrentError::RecheckFailed)
+}
+
+/// librqbit only verifies pieces when a torrent is added, so the torrent is removed (keeping its files)
+/// and added back with the same file selection and the default options to hash every piece again. Its
+/// torrent file already lists the extra trackers of the magnet link it was added from.
+///
+/// When adding it back fails, the torrent is restored paused so it does not disappear from the session
+pub async fn recheck_torrent(
+    manager: &Arc<Session>,
+    defaults: &TorrentDefaults,
+    handle: &Arc<ManagedTorrent>,
+) -> Result<Arc<ManagedTorrent>> {
+    let shared = handle.shared();
+    let torrent_bytes = shared.torrent_bytes.clone();
+    let only_files = handle.only_files();
+    let paused = matches!(handle.stats().state, TorrentStatsState::Paused);
+
+    let options = |paused: bool| {
+        defaults.apply(AddTorrentOptions {
+            overwrite: true,
+            paused,
+            // Added back to the default folder of the session, like every torrent
+            only_files: only_files.clone(),
+            ..Default::default()
+        })
+    };
+
+    delete_torrent(manager, handle, false).await?;
+
+    match add_torrent_bytes(manager, torrent_bytes.clone(), options(paused)).await {
+        Ok(handle) => Ok(handle),
+        Err(err) => {
+            if add_torrent_bytes(manager, torrent_bytes, options(true)).await.is_err() {
+                tracing::error!(
+                    "Failed to restore torrent {} after a failed recheck",
+                    handle.info_hash().as_string()
+                );
+            }
+
+            Err(err)
+        }
+    }
+}
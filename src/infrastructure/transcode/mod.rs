@@ -0,0 +1,4 @@
+mod probe;
+mod session;
+pub use probe::*;
+pub use session::*;
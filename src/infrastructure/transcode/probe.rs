@@ -0,0 +1,19 @@
+use std::path::Path;
+use tokio::process::Command;
+
+/// Overall bitrate of a media file in bits per second, works on partially downloaded files
+/// as long as the container header is there
+pub async fn probe_bitrate(path: &Path) -> Option<u64> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=bit_rate", "-of", "csv=p=0"])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|bitrate| *bitrate > 0)
+}
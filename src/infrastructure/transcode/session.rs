@@ -0,0 +1,279 @@
+use apistos::ApiComponent;
+use chrono::{Duration, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+pub const CACHE_FOLDER: &str = "./cache";
+
+/// Sessions without any request for this long no longer count as active streams
+const IDLE_TIMEOUT_SECONDS: i64 = 60;
+/// Sessions without any request for this long are dropped along with their segments
+const EXPIRE_AFTER_SECONDS: i64 = 30 * 60;
+const CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ApiComponent, JsonSchema)]
+pub enum StreamKind {
+    #[serde(rename = "transcode")]
+    Transcode,
+    #[serde(rename = "direct")]
+    Direct,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodeSession {
+    pub id: String,
+    pub info_hash: String,
+    pub file_idx: usize,
+    pub path: PathBuf,
+    pub user_id: Option<Uuid>,
+    pub kind: StreamKind,
+    pub quality: String,
+    pub created_at: NaiveDateTime,
+    pub last_active_at: NaiveDateTime,
+    /// Direct play responses still being sent, a single range response can last the whole film
+    open_responses: usize,
+}
+
+impl TranscodeSession {
+    pub fn is_active(&self) -> bool {
+        self.open_responses > 0
+            || Utc::now().naive_utc() - self.last_active_at < Duration::seconds(IDLE_TIMEOUT_SECONDS)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.open_responses == 0
+            && Utc::now().naive_utc() - self.last_active_at >= Duration::seconds(EXPIRE_AFTER_SECONDS)
+    }
+}
+
+/// Keeps a direct play session active until the response body it is moved into is dropped
+pub struct DirectStream {
+    sessions: Arc<TranscodeSessions>,
+    session_id: String,
+}
+
+impl Drop for DirectStream {
+    fn drop(&mut self) {
+        if let Some(session) = self.sessions.sessions.write().unwrap().get_mut(&self.session_id) {
+            session.open_responses = session.open_responses.saturating_sub(1);
+            session.last_active_at = Utc::now().naive_utc();
+        }
+    }
+}
+
+pub struct TranscodeSessions {
+    sessions: RwLock<HashMap<String, TranscodeSession>>,
+    max_streams_per_user: usize,
+}
+
+impl TranscodeSessions {
+    pub fn new(max_streams_per_user: usize) -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            max_streams_per_user,
+        }
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<TranscodeSession> {
+        self.sessions.read().unwrap().get(session_id).cloned()
+    }
+
+    fn insert(
+        sessions: &mut HashMap<String, TranscodeSession>,
+        info_hash: String,
+        file_idx: usize,
+        path: PathBuf,
+        user_id: Option<Uuid>,
+        kind: StreamKind,
+    ) -> TranscodeSession {
+        let now = Utc::now().naive_utc();
+        let session = TranscodeSession {
+            id: Uuid::new_v4().to_string(),
+            info_hash,
+            file_idx,
+            path,
+            user_id,
+            kind,
+            quality: "original".to_string(),
+            created_at: now,
+            last_active_at: now,
+            open_responses: 0,
+        };
+
+        sessions.insert(session.id.clone(), session.clone());
+
+        session
+    }
+
+    /// Whether the user has as many active streams as allowed, a limit of 0 disables the check
+    fn limit_reached(&self, sessions: &HashMap<String, TranscodeSession>, user_id: Uuid) -> bool {
+        if self.max_streams_per_user == 0 {
+            return false;
+        }
+
+        let active = sessions
+            .values()
+            .filter(|session| session.user_id == Some(user_id) && session.is_active())
+            .count();
+
+        active >= self.max_streams_per_user
+    }
+
+    /// Create a session for a user, `None` when `limited` and the user already has as many active streams as allowed
+    pub fn create(
+        &self,
+        info_hash: String,
+        file_idx: usize,
+        path: PathBuf,
+        user_id: Uuid,
+        limited: bool,
+    ) -> Option<TranscodeSession> {
+        let mut sessions = self.sessions.write().unwrap();
+
+        if limited && self.limit_reached(&sessions, user_id) {
+            return None;
+        }
+
+        Some(Self::insert(
+            &mut sessions,
+            info_hash,
+            file_idx,
+            path,
+            Some(user_id),
+            StreamKind::Transcode,
+        ))
+    }
+
+    /// Drop a session that could not be started, it has no segments yet
+    pub fn remove(&self, session_id: &str) {
+        self.sessions.write().unwrap().remove(session_id);
+    }
+
+    /// Anonymous sessions (DLNA clients) are shared per file so browsing the library does not pile up sessions
+    pub fn get_or_create_shared(&self, info_hash: &str, file_idx: usize, path: PathBuf) -> TranscodeSession {
+        let mut sessions = self.sessions.write().unwrap();
+
+        let existing = sessions
+            .values()
+            .find(|session| session.user_id.is_none() && session.info_hash == info_hash && session.file_idx == file_idx)
+            .cloned();
+
+        existing.unwrap_or_else(|| {
+            Self::insert(
+                &mut sessions,
+                info_hash.to_string(),
+                file_idx,
+                path,
+                None,
+                StreamKind::Transcode,
+            )
+        })
+    }
+
+    /// Record a request on a session, video is always copied so the quality stays the original one
+    pub fn touch(&self, session_id: &str) {
+        if let Some(session) = self.sessions.write().unwrap().get_mut(session_id) {
+            session.last_active_at = Utc::now().naive_utc();
+        }
+    }
+
+    /// Record a direct play request, reusing the session the user already has on this file. The session stays
+    /// active as long as the returned guard is alive.
+    ///
+    /// Returns `None` without starting a session when `limited` and the user already has as many active streams
+    /// as allowed
+    pub fn touch_direct(
+        self: &Arc<Self>,
+        user_id: Option<Uuid>,
+        info_hash: &str,
+        file_idx: usize,
+        path: PathBuf,
+        limited: bool,
+    ) -> Option<DirectStream> {
+        let mut sessions = self.sessions.write().unwrap();
+
+        let existing = sessions.values_mut().find(|session| {
+            session.user_id == user_id
+                && session.info_hash.eq_ignore_ascii_case(info_hash)
+                && session.file_idx == file_idx
+        });
+
+        let session = match existing {
+            Some(session) => {
+                session.kind = StreamKind::Direct;
+                session.quality = "original".to_string();
+                session.last_active_at = Utc::now().naive_utc();
+                session
+            }
+            None => {
+                if let Some(user_id) = user_id.filter(|_| limited) {
+                    if self.limit_reached(&sessions, user_id) {
+                        return None;
+                    }
+                }
+
+                let session = Self::insert(
+                    &mut sessions,
+                    info_hash.to_string(),
+                    file_idx,
+                    path,
+                    user_id,
+                    StreamKind::Direct,
+                );
+                sessions.get_mut(&session.id)?
+            }
+        };
+        session.open_responses += 1;
+
+        Some(DirectStream {
+            sessions: self.clone(),
+            session_id: session.id.clone(),
+        })
+    }
+
+    pub fn active(&self) -> Vec<TranscodeSession> {
+        self.sessions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|session| session.is_active())
+            .cloned()
+            .collect()
+    }
+
+    /// Drop the sessions idle for too long and the segments transcoded for them
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let expired = {
+                let mut sessions = self.sessions.write().unwrap();
+                let expired = sessions
+                    .values()
+                    .filter(|session| session.is_expired())
+                    .map(|session| session.id.clone())
+                    .collect::<Vec<_>>();
+                for session_id in &expired {
+                    sessions.remove(session_id);
+                }
+
+                expired
+            };
+
+            for session_id in expired {
+                tracing::info!("Removing expired transcode session {}", session_id);
+                if let Err(err) = tokio::fs::remove_dir_all(format!("{}/{}", CACHE_FOLDER, session_id)).await {
+                    if err.kind() != std::io::ErrorKind::NotFound {
+                        tracing::error!("Failed to remove the segments of session {}: {}", session_id, err);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,2 @@
+mod webhook;
+pub use webhook::*;
@@ -0,0 +1,161 @@
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const SIGNATURE_HEADER: &str = "X-Hypertube-Signature";
+pub const EVENT_HEADER: &str = "X-Hypertube-Event";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    TorrentAdded,
+    MetadataResolved,
+    DownloadFinished,
+    TorrentErrored,
+    TorrentDeleted,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::TorrentAdded => "torrent_added",
+            WebhookEvent::MetadataResolved => "metadata_resolved",
+            WebhookEvent::DownloadFinished => "download_finished",
+            WebhookEvent::TorrentErrored => "torrent_errored",
+            WebhookEvent::TorrentDeleted => "torrent_deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    pub info_hash: String,
+    pub name: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub error: Option<String>,
+    pub occurred_at: NaiveDateTime,
+}
+
+impl WebhookPayload {
+    pub fn new(event: WebhookEvent, info_hash: String, name: Option<String>, user_id: Option<Uuid>) -> Self {
+        Self {
+            event,
+            info_hash,
+            name,
+            user_id,
+            error: None,
+            occurred_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// Outgoing webhooks for the torrent lifecycle.
+///
+/// Bodies are signed with `sha256=<hex hmac of the body>` in the `X-Hypertube-Signature` header when a
+/// secret is configured, failed deliveries are retried with an exponential backoff
+pub struct Webhooks {
+    client: reqwest::Client,
+    urls: Vec<reqwest::Url>,
+    secret: Option<Vec<u8>>,
+}
+
+impl Webhooks {
+    pub fn new(urls: Vec<reqwest::Url>, secret: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Failed to build the webhook client"),
+            urls,
+            secret: secret.map(String::into_bytes),
+        }
+    }
+
+    fn signature(&self, body: &[u8]) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        mac.update(body);
+
+        let hex = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            });
+
+        Some(format!("sha256={}", hex))
+    }
+
+    /// Deliver a payload to every webhook in the background
+    pub fn dispatch(self: &Arc<Self>, payload: WebhookPayload) {
+        if self.urls.is_empty() {
+            return;
+        }
+
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::error!("Could not serialize webhook payload: {}", err);
+                return;
+            }
+        };
+
+        for url in self.urls.clone() {
+            let webhooks = self.clone();
+            let body = body.clone();
+            tokio::spawn(async move { webhooks.deliver(url, payload.event, body).await });
+        }
+    }
+
+    async fn deliver(&self, url: reqwest::Url, event: WebhookEvent, body: Vec<u8>) {
+        let signature = self.signature(&body);
+        let mut backoff = INITIAL_BACKOFF;
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let mut request = self
+                .client
+                .post(url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event.as_str())
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => format!("status {}", response.status()),
+                Err(err) => err.to_string(),
+            };
+
+            if attempt == MAX_ATTEMPTS {
+                tracing::error!("Giving up on {} webhook to {}: {}", event.as_str(), url, error);
+                return;
+            }
+
+            tracing::warn!(
+                "{} webhook to {} failed ({}), retrying in {:?}",
+                event.as_str(),
+                url,
+                error,
+                backoff
+            );
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
@@ -16,9 +16,18 @@ mod utils;
 pub use config::*;
 pub use server::start_server;
 pub use state::new_application_state;
+pub use torrents::{
+    cleanup_unwatched, enforce_seeding_rules, organize_library, reconcile_torrents, restore_download_queue,
+    watch_lifecycle, SeedingRules,
+};
 pub use utils::telemetry::init_telemetry;
 
 mod auth;
+mod cast;
+mod dlna;
+mod exports;
+mod notifications;
+mod playback;
 mod shows;
 mod torrents;
 mod transcode;
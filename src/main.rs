@@ -1,4 +1,7 @@
-use hypertube::{database, init_service_logging, init_telemetry, new_application_state, start_server, Config};
+use hypertube::{
+    cleanup_unwatched, database, enforce_seeding_rules, init_service_logging, init_telemetry, new_application_state,
+    organize_library, reconcile_torrents, restore_download_queue, start_server, watch_lifecycle, Config, SeedingRules,
+};
 use std::sync::Arc;
 
 #[actix_web::main]
@@ -20,7 +23,29 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to run migrations");
 
-    let state = Arc::new(new_application_state(cfg.clone()).await);
+    let state = Arc::new(
+        new_application_state(cfg.clone())
+            .await
+            .expect("Failed to create application state"),
+    );
+
+    if let Err(err) = reconcile_torrents(&state, &pool).await {
+        tracing::error!("Failed to reconcile torrents with the session: {}", err);
+    }
+
+    tokio::spawn(restore_download_queue(state.clone(), pool.clone()));
+    tokio::spawn(watch_lifecycle(state.clone(), pool.clone()));
+    tokio::spawn(organize_library(state.clone(), pool.clone()));
+    tokio::spawn(cleanup_unwatched(
+        state.clone(),
+        pool.clone(),
+        cfg.unwatched_retention_days,
+    ));
+    tokio::spawn(enforce_seeding_rules(
+        state.clone(),
+        pool.clone(),
+        SeedingRules::from_config(&cfg),
+    ));
 
     let port = cfg.port;
     let host = cfg.host.clone();
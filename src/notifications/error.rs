@@ -0,0 +1,26 @@
+use crate::ApiErrorImpl;
+use actix_web::http::StatusCode;
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotificationError {
+    #[error("Notification not found")]
+    NotificationNotFound,
+    #[error("Database error")]
+    DatabaseError,
+}
+
+impl ApiErrorImpl for NotificationError {
+    fn get_codes(&self) -> (StatusCode, &str) {
+        match self {
+            NotificationError::NotificationNotFound => (StatusCode::NOT_FOUND, "notification_not_found"),
+            NotificationError::DatabaseError => (StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for NotificationError {
+    fn from(err: sqlx::Error) -> Self {
+        tracing::error!("Database error: {}", err);
+        NotificationError::DatabaseError
+    }
+}
@@ -0,0 +1,5 @@
+mod requests;
+mod routes;
+pub mod error;
+
+pub use routes::config_notifications;
@@ -0,0 +1,9 @@
+use apistos::ApiComponent;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema)]
+pub struct ListNotificationsParams {
+    #[serde(default)]
+    pub unread: bool,
+}
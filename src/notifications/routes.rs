@@ -0,0 +1,75 @@
+use crate::error::ApiError;
+use crate::infrastructure::authorization::security::{GetUserFromSession, Security};
+use crate::infrastructure::models::notification::Notification;
+use crate::notifications::error::NotificationError;
+use crate::notifications::requests::ListNotificationsParams;
+use actix_web::web;
+use apistos::actix::NoContent;
+use apistos::api_operation;
+use apistos::web::{get, post, resource, scope, ServiceConfig};
+use sqlx::SqlitePool;
+use tracing::instrument;
+
+pub fn config_notifications(cfg: &mut ServiceConfig) {
+    cfg.service(
+        scope("/notifications")
+            .service(resource("").route(get().to(list_notifications)))
+            .service(resource("/read").route(post().to(mark_all_notifications_read)))
+            .service(resource("/{id}/read").route(post().to(mark_notification_read))),
+    );
+}
+
+#[api_operation(
+    tag = "notifications",
+    operation_id = "list_notifications",
+    summary = "List the latest notifications of the user"
+)]
+#[instrument(skip(security, pool))]
+pub async fn list_notifications(
+    query: web::Query<ListNotificationsParams>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+) -> Result<web::Json<Vec<Notification>>, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    Ok(web::Json(
+        Notification::get_by_user(&pool, user.id, query.unread).await?,
+    ))
+}
+
+#[api_operation(
+    tag = "notifications",
+    operation_id = "mark_notification_read",
+    summary = "Mark a notification as read"
+)]
+#[instrument(skip(security, pool))]
+pub async fn mark_notification_read(
+    path: web::Path<i64>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    if Notification::mark_read(&pool, user.id, Some(path.into_inner())).await? == 0 {
+        return Err(NotificationError::NotificationNotFound.into());
+    }
+
+    Ok(NoContent)
+}
+
+#[api_operation(
+    tag = "notifications",
+    operation_id = "mark_all_notifications_read",
+    summary = "Mark every notification of the user as read"
+)]
+#[instrument(skip(security, pool))]
+pub async fn mark_all_notifications_read(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    Notification::mark_read(&pool, user.id, None).await?;
+
+    Ok(NoContent)
+}
@@ -0,0 +1,23 @@
+use crate::ApiErrorImpl;
+use actix_web::http::StatusCode;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlaybackError {
+    #[error("Database error")]
+    DatabaseError,
+}
+
+impl ApiErrorImpl for PlaybackError {
+    fn get_codes(&self) -> (StatusCode, &str) {
+        match self {
+            PlaybackError::DatabaseError => (StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for PlaybackError {
+    fn from(err: sqlx::Error) -> Self {
+        tracing::error!("Database error: {}", err);
+        PlaybackError::DatabaseError
+    }
+}
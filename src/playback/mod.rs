@@ -0,0 +1,5 @@
+pub mod error;
+mod requests;
+mod routes;
+
+pub use routes::config_playback;
@@ -0,0 +1,34 @@
+use crate::infrastructure::models::playback_event::PlaybackEventType;
+use apistos::ApiComponent;
+use garde::Validate;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct PostPlaybackEvents {
+    #[garde(length(min = 1))]
+    pub session_id: String,
+    #[garde(length(min = 1, max = 100), dive)]
+    pub events: Vec<PlaybackEventBody>,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct PlaybackEventBody {
+    #[serde(rename = "type")]
+    #[garde(skip)]
+    pub event_type: PlaybackEventType,
+    /// When the event happened on the client, in milliseconds since the Unix epoch
+    #[garde(range(min = 0))]
+    pub timestamp_ms: i64,
+    /// Playback position in seconds
+    #[garde(range(min = 0.0))]
+    pub position: Option<f64>,
+    /// Startup time for `start` events, rebuffering time for `stall` events
+    #[garde(range(min = 0))]
+    pub duration_ms: Option<i64>,
+    /// New bitrate for `bitrate_switch` events, in bits per second
+    #[garde(range(min = 0))]
+    pub bitrate: Option<i64>,
+    #[garde(length(max = 1000))]
+    pub message: Option<String>,
+}
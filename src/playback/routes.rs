@@ -0,0 +1,109 @@
+use crate::error::ApiError;
+use crate::infrastructure::authorization::security::{GetUserFromSession, Security};
+use crate::infrastructure::models::playback_event::{FileQoe, PlaybackEvent, PlaybackEventInsert, UserQoe};
+use crate::infrastructure::transcode::StreamKind;
+use crate::playback::requests::PostPlaybackEvents;
+use crate::state::ApplicationState;
+use crate::transcode::error::TranscodeError;
+use actix_web::web;
+use apistos::actix::NoContent;
+use apistos::api_operation;
+use apistos::web::{get, post, resource, scope, ServiceConfig};
+use garde::Validate;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tracing::instrument;
+
+pub fn config_playback(cfg: &mut ServiceConfig) {
+    cfg.service(
+        scope("/playback")
+            .service(resource("/events").route(post().to(post_playback_events)))
+            .service(
+                scope("/stats")
+                    .service(resource("/files").route(get().to(get_file_stats)))
+                    .service(resource("/users").route(get().to(get_user_stats))),
+            ),
+    );
+}
+
+#[api_operation(
+    tag = "playback",
+    operation_id = "post_playback_events",
+    summary = "Report playback events of a streaming session"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn post_playback_events(
+    body: web::Json<PostPlaybackEvents>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    let user = security.get_user(&pool).await?;
+
+    let session = state
+        .transcode_sessions()
+        .get(&body.session_id)
+        .filter(|session| session.user_id == Some(user.id))
+        .ok_or(TranscodeError::SessionNotFound)?;
+
+    let stream_kind = match session.kind {
+        StreamKind::Transcode => "transcode",
+        StreamKind::Direct => "direct",
+    };
+
+    let events = body
+        .events
+        .into_iter()
+        .map(|event| PlaybackEventInsert {
+            session_id: session.id.clone(),
+            user_id: session.user_id,
+            info_hash: session.info_hash.clone(),
+            file_idx: session.file_idx,
+            stream_kind,
+            quality: session.quality.clone(),
+            event_type: event.event_type,
+            timestamp_ms: event.timestamp_ms,
+            position: event.position,
+            duration_ms: event.duration_ms,
+            bitrate: event.bitrate,
+            message: event.message,
+        })
+        .collect::<Vec<_>>();
+
+    PlaybackEvent::create_many(&pool, &events).await?;
+
+    Ok(NoContent)
+}
+
+#[api_operation(
+    tag = "playback",
+    operation_id = "get_file_stats",
+    summary = "Get quality of experience metrics per file and quality, admin only"
+)]
+#[instrument(skip(security, pool))]
+pub async fn get_file_stats(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+) -> Result<web::Json<Vec<FileQoe>>, ApiError> {
+    security.get_admin(&pool).await?;
+
+    Ok(web::Json(PlaybackEvent::stats_per_file(&pool).await?))
+}
+
+#[api_operation(
+    tag = "playback",
+    operation_id = "get_user_stats",
+    summary = "Get quality of experience metrics per user, admin only"
+)]
+#[instrument(skip(security, pool))]
+pub async fn get_user_stats(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+) -> Result<web::Json<Vec<UserQoe>>, ApiError> {
+    security.get_admin(&pool).await?;
+
+    Ok(web::Json(PlaybackEvent::stats_per_user(&pool).await?))
+}
@@ -102,6 +102,7 @@ pub fn start_server(
                 web::JsonConfig::default().error_handler(|err, _req| ApiError::BadRequest(err.to_string()).into()),
             )
             .configure(|app| {
+                let dlna_enabled = cfg.dlna_enabled;
                 app.service(
                     scope("/api")
                         .wrap(default_cors(cfg.origins.clone()))
@@ -112,6 +113,13 @@ pub fn start_server(
                             crate::transcode::config_transcode(cfg);
                             crate::auth::config_auth(cfg);
                             crate::users::config_users(cfg);
+                            crate::exports::config_exports(cfg);
+                            if dlna_enabled {
+                                crate::dlna::config_dlna(cfg);
+                            }
+                            crate::cast::config_cast(cfg);
+                            crate::playback::config_playback(cfg);
+                            crate::notifications::config_notifications(cfg);
                         }),
                 );
             })
@@ -1,47 +1,167 @@
 use crate::config::Config;
 use crate::error::ApiError;
+use crate::infrastructure::authorization::stream::StreamSigner;
+use crate::infrastructure::dlna::ssdp::SsdpServer;
+use crate::infrastructure::dlna::{lan_base_url, DlnaDevice, RendererControl};
+use crate::infrastructure::export::ExportManager;
 use crate::infrastructure::indexers::global::GlobalIndexer;
 use crate::infrastructure::indexers::prowlarr::ProwlarrIndexer;
+use crate::infrastructure::library::Library;
 use crate::infrastructure::metadata::tmdb::TmdbProvider;
-use librqbit::{Session, SessionOptions, SessionPersistenceConfig};
-use std::path::PathBuf;
+use crate::infrastructure::torrent::{
+    DiskQuota, DownloadQueue, PeerProxy, RateLimits, StreamingPriorities, TorrentDefaults, TorrentPreviews,
+};
+use crate::infrastructure::transcode::TranscodeSessions;
+use crate::infrastructure::webhook::Webhooks;
+use librqbit::{PeerConnectionOptions, Session, SessionOptions, SessionPersistenceConfig};
+use librqbit_upnp::UpnpPortForwarder;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
 pub struct ApplicationState {
     torrent_manager: Arc<Session>,
+    download_dir: PathBuf,
+    lan_base_url: String,
+    torrent_previews: Arc<TorrentPreviews>,
+    library: Arc<Library>,
+    disk_quota: Arc<DiskQuota>,
+    rate_limits: Arc<RateLimits>,
+    download_queue: Arc<DownloadQueue>,
+    torrent_defaults: Arc<TorrentDefaults>,
+    webhooks: Arc<Webhooks>,
+    streaming_priorities: Arc<StreamingPriorities>,
     metadata_provider: Arc<TmdbProvider>,
     global_indexer: Arc<GlobalIndexer>,
     prowlarr_indexer: Arc<ProwlarrIndexer>,
+    export_manager: Arc<ExportManager>,
+    transcode_sessions: Arc<TranscodeSessions>,
+    dlna_device: Arc<DlnaDevice>,
+    renderer_control: Arc<RendererControl>,
+    stream_signer: Arc<StreamSigner>,
 }
 
-pub async fn new_application_state(cfg: Config) -> ApplicationState {
-    let output_dir = "./downloads";
+pub async fn new_application_state(cfg: Config) -> Result<ApplicationState, Box<dyn std::error::Error>> {
+    let dlna_allowed_networks = cfg.dlna_allowed_networks()?;
+    let rate_limits = Arc::new(RateLimits::new(
+        cfg.download_limit_kib,
+        cfg.upload_limit_kib,
+        cfg.rate_limit_unlimited_hours.as_deref(),
+    )?);
+
+    // Peer connections always go through the local proxy so the limits can be changed at runtime, it chains
+    // to the SOCKS_PROXY_URL. Peers connecting in on the listen port are relayed to librqbit by it too
+    let mut peer_proxy = PeerProxy::bind(
+        rate_limits.download().clone(),
+        rate_limits.upload().clone(),
+        cfg.max_peer_connections,
+        cfg.socks_proxy_url.as_deref(),
+    )
+    .await?;
+    let listen_port_range = match cfg.listen_port_range()? {
+        Some(port_range) => Some(peer_proxy.listen(port_range).await?),
+        None => None,
+    };
+    // librqbit would forward the port it listens on, which only the proxy connects to
+    if let Some(port) = peer_proxy.public_port().filter(|_| cfg.upnp_enabled) {
+        let port_forwarder = UpnpPortForwarder::new(vec![port], None)?;
+        tokio::spawn(port_forwarder.run_forever());
+    }
+    let peer_proxy_url = peer_proxy.url()?;
+    tokio::spawn(peer_proxy.run());
+
+    let torrent_defaults = Arc::new(TorrentDefaults::new(
+        cfg.tracker_urls()?.into_iter().map(String::from).collect(),
+        cfg.trackers_enabled,
+        cfg.tracker_interval_secs,
+    ));
+
+    let webhooks = Arc::new(Webhooks::new(cfg.webhook_urls()?, cfg.webhook_secret.clone()));
+
+    let output_dir = cfg.download_dir.clone();
     let manager = Session::new_with_opts(
-        output_dir.into(),
+        output_dir.clone(),
         SessionOptions {
             fastresume: true,
             persistence: Some(SessionPersistenceConfig::Json {
-                folder: Some(output_dir.into()),
+                folder: Some(output_dir.clone()),
             }),
+            disable_dht: !cfg.dht_enabled,
+            listen_port_range,
+            peer_opts: Some(PeerConnectionOptions {
+                connect_timeout: Some(Duration::from_secs(cfg.peer_connect_timeout_secs)),
+                ..Default::default()
+            }),
+            socks_proxy_url: Some(peer_proxy_url),
 
             ..Default::default()
         },
     )
-    .await
-    .expect("Failed to create torrents manager");
+    .await?;
 
     let provider = TmdbProvider::new(cfg.tmdb_api_key);
 
     let global_indexer = GlobalIndexer::new();
     let prowlarr_indexer = ProwlarrIndexer::new(cfg.prowlarr_api_url, cfg.prowlarr_api_key);
 
-    ApplicationState {
+    let lan_base_url = lan_base_url(cfg.dlna_base_url, cfg.port);
+    let dlna_device = Arc::new(DlnaDevice::new(
+        cfg.dlna_friendly_name,
+        lan_base_url.clone(),
+        dlna_allowed_networks,
+    ));
+    if cfg.dlna_enabled {
+        let ssdp_server = SsdpServer::new(dlna_device.clone());
+        tokio::spawn(async move {
+            if let Err(err) = ssdp_server.run().await {
+                tracing::error!("SSDP server stopped: {}", err);
+            }
+        });
+    }
+
+    tokio::spawn(rate_limits.clone().run());
+
+    let streaming_priorities = Arc::new(StreamingPriorities::new(
+        cfg.streaming_readahead_mb * 1024 * 1024,
+        output_dir.clone(),
+    ));
+    tokio::spawn(streaming_priorities.clone().run());
+
+    let download_queue = Arc::new(DownloadQueue::new(cfg.max_active_downloads));
+    tokio::spawn(
+        download_queue
+            .clone()
+            .run(manager.clone(), streaming_priorities.clone()),
+    );
+
+    let export_manager = Arc::new(ExportManager::new());
+    tokio::spawn(export_manager.clone().run());
+
+    let transcode_sessions = Arc::new(TranscodeSessions::new(cfg.max_streams_per_user));
+    tokio::spawn(transcode_sessions.clone().run());
+
+    Ok(ApplicationState {
         torrent_manager: manager,
+        download_dir: output_dir,
+        lan_base_url,
+        torrent_previews: Arc::new(TorrentPreviews::new()),
+        library: Arc::new(Library::new(cfg.library_dir)),
+        disk_quota: Arc::new(DiskQuota::new(cfg.disk_quota_gb, cfg.disk_quota_policy)),
+        rate_limits,
+        download_queue,
+        torrent_defaults,
+        webhooks,
+        streaming_priorities,
         metadata_provider: Arc::new(provider),
         global_indexer: Arc::new(global_indexer),
         prowlarr_indexer: Arc::new(prowlarr_indexer),
-    }
+        export_manager,
+        transcode_sessions,
+        dlna_device,
+        renderer_control: Arc::new(RendererControl::new()),
+        stream_signer: Arc::new(StreamSigner::new(cfg.stream_token_secret, cfg.stream_token_ttl)),
+    })
 }
 
 impl ApplicationState {
@@ -49,6 +169,48 @@ impl ApplicationState {
         &self.torrent_manager
     }
 
+    /// Default output folder of the session, every torrent is downloaded under it
+    pub fn download_dir(&self) -> &Path {
+        &self.download_dir
+    }
+
+    /// Url renderers on the LAN reach the server at, whether the media server is enabled or not
+    pub fn lan_base_url(&self) -> &str {
+        &self.lan_base_url
+    }
+
+    pub fn torrent_previews(&self) -> &Arc<TorrentPreviews> {
+        &self.torrent_previews
+    }
+
+    pub fn library(&self) -> &Arc<Library> {
+        &self.library
+    }
+
+    pub fn disk_quota(&self) -> &Arc<DiskQuota> {
+        &self.disk_quota
+    }
+
+    pub fn rate_limits(&self) -> &Arc<RateLimits> {
+        &self.rate_limits
+    }
+
+    pub fn download_queue(&self) -> &Arc<DownloadQueue> {
+        &self.download_queue
+    }
+
+    pub fn torrent_defaults(&self) -> &Arc<TorrentDefaults> {
+        &self.torrent_defaults
+    }
+
+    pub fn webhooks(&self) -> &Arc<Webhooks> {
+        &self.webhooks
+    }
+
+    pub fn streaming_priorities(&self) -> &Arc<StreamingPriorities> {
+        &self.streaming_priorities
+    }
+
     pub fn metadata_provider(&self) -> &Arc<TmdbProvider> {
         &self.metadata_provider
     }
@@ -60,4 +222,24 @@ impl ApplicationState {
     pub fn prowlarr_indexer(&self) -> &Arc<ProwlarrIndexer> {
         &self.prowlarr_indexer
     }
+
+    pub fn export_manager(&self) -> &Arc<ExportManager> {
+        &self.export_manager
+    }
+
+    pub fn transcode_sessions(&self) -> &Arc<TranscodeSessions> {
+        &self.transcode_sessions
+    }
+
+    pub fn dlna_device(&self) -> &Arc<DlnaDevice> {
+        &self.dlna_device
+    }
+
+    pub fn renderer_control(&self) -> &Arc<RendererControl> {
+        &self.renderer_control
+    }
+
+    pub fn stream_signer(&self) -> &Arc<StreamSigner> {
+        &self.stream_signer
+    }
 }
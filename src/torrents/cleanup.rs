@@ -0,0 +1,154 @@
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::torrent::{delete_torrent, get_torrent_files, get_torrent_handle, get_torrent_name};
+use crate::infrastructure::webhook::{WebhookEvent, WebhookPayload};
+use crate::state::ApplicationState;
+use crate::torrents::error::TorrentError;
+use crate::torrents::library::unlink_files;
+use crate::torrents::lifecycle::emit;
+use crate::torrents::responses::CleanupCandidate;
+use chrono::{Duration, Utc};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::fs;
+
+const CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Torrents that were not watched for `retention_days`, without the ones someone is streaming right now
+pub async fn find_cleanup_candidates(
+    state: &ApplicationState,
+    pool: &SqlitePool,
+    retention_days: u32,
+) -> Result<Vec<CleanupCandidate>, TorrentError> {
+    let cutoff = Utc::now().naive_utc() - Duration::days(retention_days as i64);
+    let playing = state
+        .transcode_sessions()
+        .active()
+        .into_iter()
+        .map(|session| session.info_hash.to_lowercase())
+        .collect::<HashSet<_>>();
+
+    let candidates = Torrent::get_unwatched_since(pool, cutoff)
+        .await?
+        .into_iter()
+        .filter(|torrent| !playing.contains(&torrent.info_hash))
+        .filter(|torrent| !state.streaming_priorities().is_streaming(&torrent.info_hash))
+        .map(|torrent| CleanupCandidate {
+            name: get_torrent_handle(state.manager(), &torrent.info_hash)
+                .ok()
+                .map(|handle| get_torrent_name(&handle)),
+            info_hash: torrent.info_hash,
+            user_id: torrent.user_id,
+            added_at: torrent.added_at,
+            last_watched_at: torrent.last_watched_at,
+            library_path: torrent.library_path,
+        })
+        .collect();
+
+    Ok(candidates)
+}
+
+/// Delete a torrent with its files and library entry, returns whether it is gone
+pub(crate) async fn remove_candidate(
+    state: &ApplicationState,
+    pool: &SqlitePool,
+    candidate: &CleanupCandidate,
+) -> bool {
+    let file_names: Vec<String> = match get_torrent_handle(state.manager(), &candidate.info_hash) {
+        Ok(handle) => {
+            let file_names = get_torrent_files(state.download_dir(), &handle)
+                .map(|files| files.into_iter().map(|file| file.name).collect())
+                .unwrap_or_default();
+
+            if let Err(err) = delete_torrent(state.manager(), &handle, true).await {
+                tracing::error!("Could not delete torrent {}: {}", candidate.info_hash, err);
+                return false;
+            }
+
+            file_names
+        }
+        Err(_) => {
+            // Torrents removed by the seeding rules left their files behind
+            let kept_files = match Torrent::get_by_info_hash(pool, &candidate.info_hash).await {
+                Ok(torrent) => torrent.map(|torrent| torrent.kept_files).unwrap_or_default(),
+                Err(err) => {
+                    tracing::error!("Could not load torrent {}: {}", candidate.info_hash, err);
+                    return false;
+                }
+            };
+
+            for kept_file in &kept_files {
+                if let Err(err) = fs::remove_file(kept_file).await {
+                    if err.kind() != std::io::ErrorKind::NotFound {
+                        tracing::error!("Could not delete {}: {}", kept_file.display(), err);
+                        return false;
+                    }
+                }
+            }
+
+            kept_files
+                .iter()
+                .filter_map(|kept_file| kept_file.file_name())
+                .map(|file_name| file_name.to_string_lossy().into_owned())
+                .collect()
+        }
+    };
+
+    if let Some(library_path) = &candidate.library_path {
+        unlink_files(state.library(), library_path, &file_names).await;
+    }
+
+    if let Err(err) = Torrent::delete(pool, &candidate.info_hash).await {
+        tracing::error!("Could not forget torrent {}: {}", candidate.info_hash, err);
+        return false;
+    }
+
+    emit(
+        state,
+        pool,
+        WebhookPayload::new(
+            WebhookEvent::TorrentDeleted,
+            candidate.info_hash.clone(),
+            candidate.name.clone(),
+            candidate.user_id,
+        ),
+    )
+    .await;
+
+    tracing::info!(
+        "Removed torrent {} ({}), last watched {}",
+        candidate.info_hash,
+        candidate.name.as_deref().unwrap_or("unknown"),
+        candidate
+            .last_watched_at
+            .map(|last_watched_at| last_watched_at.to_string())
+            .unwrap_or_else(|| "never".to_string())
+    );
+
+    true
+}
+
+/// Hourly job deleting the torrents and files of media nobody watched for `retention_days`
+pub async fn cleanup_unwatched(state: Arc<ApplicationState>, pool: SqlitePool, retention_days: u32) {
+    if retention_days == 0 {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let candidates = match find_cleanup_candidates(&state, &pool, retention_days).await {
+            Ok(candidates) => candidates,
+            Err(err) => {
+                tracing::error!("Could not list unwatched torrents: {}", err);
+                continue;
+            }
+        };
+
+        for candidate in &candidates {
+            remove_candidate(&state, &pool, candidate).await;
+        }
+    }
+}
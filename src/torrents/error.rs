@@ -11,23 +11,57 @@ pub enum TorrentError {
     TorrentInitializationFailed,
     #[error("Failed to acquire stream")]
     FailedToAcquireStream,
+    #[error("Invalid torrent file")]
+    InvalidTorrentFile,
+    #[error("Torrent file too large")]
+    TorrentFileTooLarge,
+    #[error("Invalid torrent url")]
+    InvalidTorrentUrl,
+    #[error("Failed to fetch torrent file")]
+    FetchTorrentFailed,
+    #[error("Torrent preview not found")]
+    PreviewNotFound,
+    #[error("Invalid file selection")]
+    InvalidFileSelection,
+    #[error("Database error")]
+    DatabaseError,
+    #[error("Not enough space left in the disk quota")]
+    DiskQuotaExceeded,
+    #[error("Subtitle not found")]
+    SubtitleNotFound,
+    #[error("Subtitle cannot be downloaded while the file is deselected or the torrent is not running")]
+    SubtitleUnavailable,
+    #[error("Timed out reading the subtitle")]
+    SubtitleTimedOut,
 }
 
 impl ApiErrorImpl for TorrentError {
     fn get_codes(&self) -> (StatusCode, &str) {
         match self {
-            TorrentError::AddTorrentError => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "add_torrent_error")
-            }
+            TorrentError::AddTorrentError => (StatusCode::INTERNAL_SERVER_ERROR, "add_torrent_error"),
             TorrentError::TorrentAlreadyAdded => (StatusCode::CONFLICT, "torrent_already_added"),
-            TorrentError::TorrentInitializationFailed => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "torrent_initialization_failed",
-            ),
-            TorrentError::FailedToAcquireStream => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed_to_acquire_stream",
-            ),
+            TorrentError::TorrentInitializationFailed => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "torrent_initialization_failed")
+            }
+            TorrentError::FailedToAcquireStream => (StatusCode::INTERNAL_SERVER_ERROR, "failed_to_acquire_stream"),
+            TorrentError::InvalidTorrentFile => (StatusCode::BAD_REQUEST, "invalid_torrent_file"),
+            TorrentError::TorrentFileTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "torrent_file_too_large"),
+            TorrentError::InvalidTorrentUrl => (StatusCode::BAD_REQUEST, "invalid_torrent_url"),
+            TorrentError::FetchTorrentFailed => (StatusCode::BAD_GATEWAY, "fetch_torrent_failed"),
+            TorrentError::PreviewNotFound => (StatusCode::NOT_FOUND, "preview_not_found"),
+            TorrentError::InvalidFileSelection => (StatusCode::BAD_REQUEST, "invalid_file_selection"),
+            TorrentError::DatabaseError => (StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
+            TorrentError::DiskQuotaExceeded => (StatusCode::INSUFFICIENT_STORAGE, "disk_quota_exceeded"),
+            TorrentError::SubtitleNotFound => (StatusCode::NOT_FOUND, "subtitle_not_found"),
+            TorrentError::SubtitleUnavailable => (StatusCode::CONFLICT, "subtitle_unavailable"),
+            TorrentError::SubtitleTimedOut => (StatusCode::GATEWAY_TIMEOUT, "subtitle_timed_out"),
         }
     }
 }
+
+impl From<sqlx::Error> for TorrentError {
+    fn from(err: sqlx::Error) -> Self {
+        tracing::error!("Database error: {}", err);
+        TorrentError::DatabaseError
+    }
+}
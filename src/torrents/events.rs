@@ -0,0 +1,117 @@
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::models::user::User;
+use crate::infrastructure::torrent::{get_torrent_handles, get_torrent_name};
+use crate::state::ApplicationState;
+use crate::torrents::responses::{TorrentLifecycleEvent, TorrentStats, TorrentStatsEvent};
+use actix_web::web::Bytes;
+use futures::Stream;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Copy)]
+struct LastSeen {
+    finished: bool,
+    error: bool,
+}
+
+fn sse_event<T: Serialize>(event: &str, data: &T) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// Torrents the user is allowed to follow, along with every torrent that has a recorded owner
+async fn owned_torrents(pool: &SqlitePool, user: &User) -> (HashSet<String>, HashSet<String>) {
+    let torrents = Torrent::get_all(pool).await.unwrap_or_default();
+    let owned = torrents
+        .iter()
+        .filter(|torrent| torrent.user_id == Some(user.id))
+        .map(|torrent| torrent.info_hash.clone())
+        .collect();
+
+    (owned, torrents.into_iter().map(|torrent| torrent.info_hash).collect())
+}
+
+/// Server-sent events stream of the stats of the user's torrents, with lifecycle events
+/// whenever a torrent shows up, finishes or fails
+pub fn torrent_events(
+    state: Arc<ApplicationState>,
+    pool: SqlitePool,
+    user: User,
+    interval: Duration,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    async_stream::stream! {
+        let mut last_seen: Option<HashMap<String, LastSeen>> = None;
+        let mut ticker = tokio::time::interval(interval);
+
+        // Owners never change, the owned torrents are only looked up again when the session holds a torrent
+        // whose owner is not known yet. `None` for admins, who follow every torrent
+        let mut followed = (!user.is_admin()).then(HashSet::new);
+        let mut known = HashSet::new();
+
+        loop {
+            ticker.tick().await;
+
+            let handles = get_torrent_handles(state.manager())
+                .into_iter()
+                .map(|handle| (handle.info_hash().as_string(), handle))
+                .collect::<Vec<_>>();
+
+            if let Some(followed) = followed.as_mut() {
+                if handles.iter().any(|(info_hash, _)| !known.contains(info_hash)) {
+                    (*followed, known) = owned_torrents(&pool, &user).await;
+                }
+            }
+
+            let handles = handles.into_iter().filter(|(info_hash, _)| {
+                followed
+                    .as_ref()
+                    .map(|followed| followed.contains(info_hash))
+                    .unwrap_or(true)
+            });
+
+            let mut seen = HashMap::new();
+            for (info_hash, handle) in handles {
+                let stats = handle.stats();
+                let current = LastSeen {
+                    finished: stats.finished,
+                    error: stats.error.is_some(),
+                };
+
+                let lifecycle = TorrentLifecycleEvent {
+                    info_hash: info_hash.clone(),
+                    name: get_torrent_name(&handle),
+                    error: stats.error.clone(),
+                };
+
+                // librqbit only creates a handle once the metadata of a magnet is known, so a torrent
+                // appearing while the stream is open has just been resolved
+                if let Some(last_seen) = &last_seen {
+                    match last_seen.get(&info_hash) {
+                        None => yield Ok(sse_event("metadata_resolved", &lifecycle)),
+                        Some(previous) => {
+                            if current.finished && !previous.finished {
+                                yield Ok(sse_event("download_finished", &lifecycle));
+                            }
+                            if current.error && !previous.error {
+                                yield Ok(sse_event("torrent_error", &lifecycle));
+                            }
+                        }
+                    }
+                }
+
+                yield Ok(sse_event("stats", &TorrentStatsEvent {
+                    info_hash: info_hash.clone(),
+                    stats: TorrentStats::from(stats)
+                        .with_queue_position(state.download_queue().position(&info_hash)),
+                }));
+
+                seen.insert(info_hash, current);
+            }
+
+            last_seen = Some(seen);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::infrastructure::library::Library;
+use crate::infrastructure::metadata::models::ShowType;
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::torrent::{get_torrent_files, get_torrent_handle, is_file_selected, TorrentFile};
+use crate::state::ApplicationState;
+use regex::Regex;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::fs;
+
+type OrganizeError = Box<dyn std::error::Error + Send + Sync>;
+
+const ORGANIZE_INTERVAL: Duration = Duration::from_secs(60);
+
+static EPISODE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)S(\d{1,2})[\s._-]*E(\d{1,3})").unwrap());
+
+pub(crate) fn is_playable(file: &TorrentFile) -> bool {
+    mime_guess::from_path(&file.name)
+        .first()
+        .map(|mime| mime.type_() == mime_guess::mime::VIDEO)
+        .unwrap_or(false)
+}
+
+fn parse_episode(name: &str) -> Option<(u32, u32)> {
+    let captures = EPISODE_RE.captures(name)?;
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?))
+}
+
+fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "mkv".to_string())
+}
+
+/// Library entries linked from the files of a torrent: `library_path` itself, or for season packs whose
+/// `library_path` is a season folder, the episodes of the pack in the season folders of the show
+fn linked_files(library: &Library, library_path: &Path, file_names: &[String]) -> Vec<PathBuf> {
+    if !library_path.is_dir() {
+        return vec![library_path.to_path_buf()];
+    }
+
+    let Some(show) = library_path.parent().and_then(Path::file_name) else {
+        return Vec::new();
+    };
+    let show = show.to_string_lossy();
+
+    file_names
+        .iter()
+        .filter_map(|name| {
+            let (season, episode) = parse_episode(name)?;
+            Some(library.episode_path(&show, season, episode, &extension(name)))
+        })
+        .collect()
+}
+
+/// Remove the library entries of a torrent whose files were deleted, hardlinks keep the space in use otherwise
+pub(crate) async fn unlink_files(library: &Library, library_path: &Path, file_names: &[String]) {
+    for linked_file in linked_files(library, library_path, file_names) {
+        if let Err(err) = fs::remove_file(&linked_file).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Could not remove {} from the library: {}", linked_file.display(), err);
+            }
+        }
+    }
+}
+
+/// Periodically link the finished downloads that are tied to a movie or show into the library
+pub async fn organize_library(state: Arc<ApplicationState>, pool: SqlitePool) {
+    let mut interval = tokio::time::interval(ORGANIZE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let torrents = match Torrent::get_unorganized(&pool).await {
+            Ok(torrents) => torrents,
+            Err(err) => {
+                tracing::error!("Could not list torrents to organize: {}", err);
+                continue;
+            }
+        };
+
+        for torrent in torrents {
+            let Ok(handle) = get_torrent_handle(state.manager(), &torrent.info_hash) else {
+                continue;
+            };
+            if !handle.stats().finished {
+                continue;
+            }
+
+            match organize_torrent(&state, &torrent).await {
+                Ok(Some(library_path)) => {
+                    tracing::info!(
+                        "Organized torrent {} into {}",
+                        torrent.info_hash,
+                        library_path.display()
+                    );
+                    if let Err(err) = Torrent::set_library_path(&pool, &torrent.info_hash, &library_path).await {
+                        tracing::error!("Could not record the library path of {}: {}", torrent.info_hash, err);
+                    }
+                }
+                Ok(None) => {
+                    tracing::info!("Nothing to organize in torrent {}", torrent.info_hash);
+                    if let Err(err) = Torrent::set_nothing_to_organize(&pool, &torrent.info_hash).await {
+                        tracing::error!("Could not record torrent {} as organized: {}", torrent.info_hash, err);
+                    }
+                }
+                Err(err) => tracing::warn!("Could not organize torrent {}: {}", torrent.info_hash, err),
+            }
+        }
+    }
+}
+
+/// Returns the organized file, or the season folder for season packs
+async fn organize_torrent(state: &ApplicationState, torrent: &Torrent) -> Result<Option<PathBuf>, OrganizeError> {
+    let (Some(tmdb_id), Some(media_type)) = (torrent.tmdb_id, torrent.media_type) else {
+        return Ok(None);
+    };
+
+    let handle = get_torrent_handle(state.manager(), &torrent.info_hash)?;
+    let files = get_torrent_files(state.download_dir(), &handle)?
+        .into_iter()
+        .filter(is_playable)
+        // Files left out of the selection are never downloaded
+        .filter(|file| is_file_selected(&handle, file.index))
+        .collect::<Vec<_>>();
+
+    let main_file = match torrent.file_idx {
+        Some(file_idx) => files.iter().find(|file| file.index == file_idx),
+        None => files.iter().max_by_key(|file| file.length),
+    };
+
+    let library = state.library();
+    let provider = state.metadata_provider();
+
+    match media_type {
+        ShowType::Movie => {
+            let Some(file) = main_file else {
+                return Ok(None);
+            };
+            let movie = provider.get_movie_metadata(tmdb_id, None).await?;
+            let year = movie.release_date.get(..4);
+            let destination = library.movie_path(&movie.title, year, &extension(&file.name));
+            library.link(&file.path, &destination).await?;
+
+            Ok(Some(destination))
+        }
+        ShowType::TV => {
+            let show = provider.get_tv_metadata(tmdb_id, None).await?;
+
+            if let (Some(season), Some(episode), Some(file)) = (torrent.season, torrent.episode, main_file) {
+                let destination = library.episode_path(&show.name, season, episode, &extension(&file.name));
+                library.link(&file.path, &destination).await?;
+
+                return Ok(Some(destination));
+            }
+
+            // Season packs, every file that names its episode gets linked
+            let mut season_folder = None;
+            for file in &files {
+                let Some((season, episode)) = parse_episode(&file.name) else {
+                    continue;
+                };
+                let destination = library.episode_path(&show.name, season, episode, &extension(&file.name));
+                library.link(&file.path, &destination).await?;
+                season_folder = destination.parent().map(Path::to_path_buf);
+            }
+
+            Ok(season_folder)
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::infrastructure::models::notification::{Notification, NotificationKind};
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::torrent::{get_torrent_handles, get_torrent_name};
+use crate::infrastructure::webhook::{WebhookEvent, WebhookPayload};
+use crate::state::ApplicationState;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy)]
+struct LastSeen {
+    finished: bool,
+    error: bool,
+}
+
+/// Send a lifecycle event to the webhooks, and notify the owner of finished or failed downloads
+pub(crate) async fn emit(state: &ApplicationState, pool: &SqlitePool, payload: WebhookPayload) {
+    let notification = match payload.event {
+        WebhookEvent::DownloadFinished => Some((
+            NotificationKind::DownloadFinished,
+            format!(
+                "{} finished downloading",
+                payload.name.as_deref().unwrap_or(&payload.info_hash)
+            ),
+        )),
+        WebhookEvent::TorrentErrored => Some((
+            NotificationKind::DownloadFailed,
+            format!(
+                "{} failed to download: {}",
+                payload.name.as_deref().unwrap_or(&payload.info_hash),
+                payload.error.as_deref().unwrap_or("unknown error")
+            ),
+        )),
+        _ => None,
+    };
+
+    if let (Some((kind, message)), Some(user_id)) = (notification, payload.user_id) {
+        if let Err(err) = Notification::create(pool, user_id, &payload.info_hash, kind, &message).await {
+            tracing::error!("Could not notify user {} about {}: {}", user_id, payload.info_hash, err);
+        }
+    }
+
+    state.webhooks().dispatch(payload);
+}
+
+/// Background task turning the state changes of the session into lifecycle events
+pub async fn watch_lifecycle(state: Arc<ApplicationState>, pool: SqlitePool) {
+    let mut last_seen: Option<HashMap<String, LastSeen>> = None;
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let owners = match Torrent::get_all(&pool).await {
+            Ok(torrents) => torrents
+                .into_iter()
+                .map(|torrent| (torrent.info_hash, torrent.user_id))
+                .collect::<HashMap<_, _>>(),
+            Err(err) => {
+                tracing::error!("Could not list torrent owners: {}", err);
+                continue;
+            }
+        };
+
+        let mut seen = HashMap::new();
+        for handle in get_torrent_handles(state.manager()) {
+            let info_hash = handle.info_hash().as_string();
+            let stats = handle.stats();
+            let current = LastSeen {
+                finished: stats.finished,
+                error: stats.error.is_some(),
+            };
+            seen.insert(info_hash.clone(), current);
+
+            // The first pass only records what the session already holds
+            let Some(last_seen) = &last_seen else {
+                continue;
+            };
+
+            let payload = |event| {
+                WebhookPayload::new(
+                    event,
+                    info_hash.clone(),
+                    Some(get_torrent_name(&handle)),
+                    owners.get(&info_hash).copied().flatten(),
+                )
+            };
+
+            let previous = last_seen.get(&info_hash).copied();
+
+            if current.finished && !previous.is_some_and(|previous| previous.finished) {
+                emit(&state, &pool, payload(WebhookEvent::DownloadFinished)).await;
+            }
+
+            if current.error && !previous.is_some_and(|previous| previous.error) {
+                emit(
+                    &state,
+                    &pool,
+                    WebhookPayload {
+                        error: stats.error.clone(),
+                        ..payload(WebhookEvent::TorrentErrored)
+                    },
+                )
+                .await;
+            }
+        }
+
+        last_seen = Some(seen);
+    }
+}
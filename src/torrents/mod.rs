@@ -1,23 +1,113 @@
+mod cleanup;
+mod events;
+mod library;
+mod lifecycle;
+mod preview;
+mod quota;
+mod readiness;
 mod requests;
 mod responses;
 mod routes;
+mod seeding;
+mod sources;
+mod subtitles;
 
 use crate::error::ApiError;
+use crate::infrastructure::models::torrent::{Torrent, TorrentInsert};
+use crate::infrastructure::torrent::get_torrent_handles;
+use crate::state::ApplicationState;
+use crate::torrents::error::TorrentError;
 use librqbit::ManagedTorrent;
+use librqbit_core::torrent_metainfo::TorrentMetaV1Info;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub use cleanup::cleanup_unwatched;
+pub use library::organize_library;
+pub use lifecycle::watch_lifecycle;
 pub use routes::config_torrent;
+pub(crate) use routes::ensure_can_manage;
+pub(crate) use seeding::resume_for_playback;
+pub use seeding::{enforce_seeding_rules, SeedingRules};
 
 pub mod error;
 
-fn create_torrent_playlist_items(
-    handle: &ManagedTorrent,
+/// Bring the torrent table in line with the librqbit session: torrents restored from librqbit's own
+/// persistence get a row without owner. Rows of torrents the session no longer has are kept, their owner
+/// and library entry may still be needed, they are only reported
+pub async fn reconcile_torrents(state: &ApplicationState, pool: &SqlitePool) -> Result<(), TorrentError> {
+    let handles = get_torrent_handles(state.manager());
+    let session_hashes = handles
+        .iter()
+        .map(|handle| handle.info_hash().as_string())
+        .collect::<HashSet<_>>();
+
+    let known = Torrent::get_all(pool).await?;
+    let known_hashes = known
+        .iter()
+        .map(|torrent| torrent.info_hash.clone())
+        .collect::<HashSet<_>>();
+    // Torrents removed by the seeding rules are expected to be gone, only their files are left
+    let removed_hashes = known
+        .iter()
+        .filter(|torrent| !torrent.kept_files.is_empty())
+        .map(|torrent| torrent.info_hash.clone())
+        .collect::<HashSet<_>>();
+
+    for info_hash in session_hashes.difference(&known_hashes) {
+        tracing::info!("Recording torrent {} found in the session", info_hash);
+        Torrent::create(
+            pool,
+            &TorrentInsert {
+                info_hash: info_hash.clone(),
+                ..Default::default()
+            },
+        )
+        .await?;
+    }
+
+    for info_hash in known_hashes
+        .difference(&session_hashes)
+        .filter(|info_hash| !removed_hashes.contains(*info_hash))
+    {
+        tracing::warn!("Torrent {} is recorded but missing from the session", info_hash);
+    }
+
+    Ok(())
+}
+
+/// Queue the unfinished torrents librqbit restored paused, except the ones paused by hand
+pub async fn restore_download_queue(state: Arc<ApplicationState>, pool: SqlitePool) {
+    let paused_by_user = match Torrent::get_all(&pool).await {
+        Ok(torrents) => torrents
+            .into_iter()
+            .filter(|torrent| torrent.paused_by_user)
+            .map(|torrent| torrent.info_hash)
+            .collect::<HashSet<_>>(),
+        Err(err) => {
+            tracing::error!(
+                "Could not load the torrents paused by hand, the queue is not restored: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    state.download_queue().restore(state.manager(), &paused_by_user).await;
+}
+
+pub(crate) fn create_torrent_playlist_items(handle: &ManagedTorrent) -> Result<Vec<(usize, String)>, ApiError> {
+    create_playlist_items_from_info(&handle.shared().info)
+}
+
+pub(crate) fn create_playlist_items_from_info<BufType: AsRef<[u8]>>(
+    info: &TorrentMetaV1Info<BufType>,
 ) -> Result<Vec<(usize, String)>, ApiError> {
     // TODO: Change errors
-    let mut playlist_items = handle
-        .shared()
-        .info
+    let mut playlist_items = info
         .iter_file_details(
-            &librqbit_core::lengths::Lengths::from_torrent(&handle.shared().info)
-                .map_err(|_| ApiError::InternalServerError)?,
+            &librqbit_core::lengths::Lengths::from_torrent(info).map_err(|_| ApiError::InternalServerError)?,
         )
         .map_err(|_| ApiError::InternalServerError)?
         .enumerate()
@@ -0,0 +1,43 @@
+use crate::error::ApiError;
+use crate::torrents::create_playlist_items_from_info;
+use crate::torrents::responses::{PreviewFile, TorrentPreview};
+use librqbit_core::lengths::Lengths;
+use librqbit_core::torrent_metainfo::TorrentMetaV1Info;
+use std::collections::HashSet;
+
+pub fn build_preview<BufType: AsRef<[u8]>>(
+    info_hash: String,
+    info: &TorrentMetaV1Info<BufType>,
+) -> Result<TorrentPreview, ApiError> {
+    let playable = create_playlist_items_from_info(info)?
+        .into_iter()
+        .map(|(file_idx, _)| file_idx)
+        .collect::<HashSet<_>>();
+
+    let lengths = Lengths::from_torrent(info).map_err(|_| ApiError::InternalServerError)?;
+    let files = info
+        .iter_file_details(&lengths)
+        .map_err(|_| ApiError::InternalServerError)?
+        .enumerate()
+        .map(|(index, file_details)| PreviewFile {
+            index,
+            name: file_details
+                .filename
+                .to_vec()
+                .map(|components| components.join("/"))
+                .unwrap_or_default(),
+            length: file_details.len,
+            playable: playable.contains(&index),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(TorrentPreview {
+        name: info
+            .name
+            .as_ref()
+            .map(|name| String::from_utf8_lossy(name.as_ref()).into_owned()),
+        total_bytes: lengths.total_length(),
+        info_hash,
+        files,
+    })
+}
@@ -0,0 +1,156 @@
+use crate::config::QuotaPolicy;
+use crate::error::ApiError;
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::torrent::{delete_torrent, get_torrent_handle, get_torrent_handles, get_torrent_name};
+use crate::state::ApplicationState;
+use crate::torrents::cleanup::{find_cleanup_candidates, remove_candidate};
+use crate::torrents::error::TorrentError;
+use crate::torrents::responses::{DiskUsage, TorrentUsage, UserUsage};
+use librqbit::ManagedTorrent;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::fs;
+
+/// Make room for a torrent that was just added paused, it is removed again when it cannot fit
+pub async fn enforce_quota(
+    state: &ApplicationState,
+    pool: &SqlitePool,
+    handle: &Arc<ManagedTorrent>,
+) -> Result<(), ApiError> {
+    let quota = state.disk_quota();
+    let info_hash = handle.info_hash().as_string();
+    let required = handle.stats().total_bytes;
+    let kept = kept_bytes(state, pool).await?;
+    let mut reserved = quota.reserved(state.manager(), Some(&info_hash))
+        + kept
+            .iter()
+            .filter(|(kept_hash, _)| **kept_hash != info_hash)
+            .map(|(_, bytes)| bytes)
+            .sum::<u64>();
+
+    if quota.fits(reserved, required) {
+        return Ok(());
+    }
+
+    if quota.policy() == QuotaPolicy::Evict {
+        // Candidates come least recently watched first
+        for candidate in find_cleanup_candidates(state, pool, 0).await? {
+            if candidate.info_hash == info_hash {
+                continue;
+            }
+            let freed = match get_torrent_handle(state.manager(), &candidate.info_hash) {
+                Ok(evicted) => {
+                    let stats = evicted.stats();
+                    if !stats.finished {
+                        continue;
+                    }
+                    stats.total_bytes
+                }
+                Err(_) => match kept.get(&candidate.info_hash) {
+                    Some(&bytes) if bytes > 0 => bytes,
+                    _ => continue,
+                },
+            };
+
+            tracing::info!(
+                "Evicting torrent {} to make room for {}",
+                candidate.info_hash,
+                info_hash
+            );
+            if remove_candidate(state, pool, &candidate).await {
+                reserved = reserved.saturating_sub(freed);
+            }
+
+            if quota.fits(reserved, required) {
+                return Ok(());
+            }
+        }
+    }
+
+    tracing::warn!(
+        "Torrent {} needs {} bytes, {} of {} are reserved",
+        info_hash,
+        required,
+        reserved,
+        quota.budget().unwrap_or_default()
+    );
+    delete_torrent(state.manager(), handle, true).await?;
+
+    Err(TorrentError::DiskQuotaExceeded.into())
+}
+
+/// Size on disk of the files left by torrents the seeding rules removed from the session
+async fn kept_bytes(state: &ApplicationState, pool: &SqlitePool) -> Result<HashMap<String, u64>, ApiError> {
+    let mut kept = HashMap::new();
+    for torrent in Torrent::get_all(pool).await? {
+        if torrent.kept_files.is_empty() || get_torrent_handle(state.manager(), &torrent.info_hash).is_ok() {
+            continue;
+        }
+
+        let mut bytes = 0;
+        for kept_file in &torrent.kept_files {
+            if let Ok(metadata) = fs::metadata(kept_file).await {
+                bytes += metadata.len();
+            }
+        }
+        kept.insert(torrent.info_hash, bytes);
+    }
+
+    Ok(kept)
+}
+
+pub async fn disk_usage(state: &ApplicationState, pool: &SqlitePool) -> Result<DiskUsage, ApiError> {
+    let owners = Torrent::get_all(pool)
+        .await?
+        .into_iter()
+        .map(|torrent| (torrent.info_hash, torrent.user_id))
+        .collect::<HashMap<_, _>>();
+
+    let mut torrents = get_torrent_handles(state.manager())
+        .iter()
+        .map(|handle| {
+            let info_hash = handle.info_hash().as_string();
+            let stats = handle.stats();
+
+            TorrentUsage {
+                user_id: owners.get(&info_hash).copied().flatten(),
+                name: get_torrent_name(handle),
+                info_hash,
+                used_bytes: stats.progress_bytes,
+                reserved_bytes: stats.total_bytes,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    for (info_hash, bytes) in kept_bytes(state, pool).await? {
+        torrents.push(TorrentUsage {
+            user_id: owners.get(&info_hash).copied().flatten(),
+            name: info_hash.clone(),
+            info_hash,
+            used_bytes: bytes,
+            reserved_bytes: bytes,
+        });
+    }
+
+    let mut users = HashMap::new();
+    for torrent in &torrents {
+        let usage = users.entry(torrent.user_id).or_insert_with(|| UserUsage {
+            user_id: torrent.user_id,
+            torrents: 0,
+            used_bytes: 0,
+            reserved_bytes: 0,
+        });
+        usage.torrents += 1;
+        usage.used_bytes += torrent.used_bytes;
+        usage.reserved_bytes += torrent.reserved_bytes;
+    }
+
+    Ok(DiskUsage {
+        budget_bytes: state.disk_quota().budget(),
+        used_bytes: torrents.iter().map(|torrent| torrent.used_bytes).sum(),
+        reserved_bytes: torrents.iter().map(|torrent| torrent.reserved_bytes).sum(),
+        users: users.into_values().collect(),
+        torrents,
+    })
+}
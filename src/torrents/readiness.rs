@@ -0,0 +1,51 @@
+use crate::infrastructure::torrent::TorrentFile;
+use crate::torrents::responses::{PlaybackReadiness, Speed};
+
+/// Seconds of playback that must be on disk before starting
+const BUFFER_SECONDS: u64 = 30;
+/// Used when the file cannot be probed yet, a typical 1080p release
+const FALLBACK_BITRATE: u64 = 8_000_000;
+const MIB: f64 = 1024.0 * 1024.0;
+
+pub fn estimate_readiness(
+    file: &TorrentFile,
+    head_available: u64,
+    download_speed_mbps: f64,
+    bitrate: Option<u64>,
+) -> PlaybackReadiness {
+    let bitrate_probed = bitrate.is_some();
+    let bitrate = bitrate.unwrap_or(FALLBACK_BITRATE);
+    let byte_rate = (bitrate / 8).max(1) as f64;
+
+    let head_required = ((byte_rate as u64) * BUFFER_SECONDS).min(file.length);
+    let missing_head = head_required.saturating_sub(head_available) as f64;
+    let download_rate = download_speed_mbps * MIB;
+
+    let estimated_seconds = if file.is_finished() || missing_head == 0.0 {
+        Some(0.0)
+    } else if download_rate <= 0.0 {
+        None
+    } else {
+        let head_wait = missing_head / download_rate;
+
+        // When downloading slower than the bitrate, wait long enough for the rest of the file
+        // to arrive before the player catches up with it
+        let remaining = file.length.saturating_sub(file.downloaded) as f64;
+        let duration = file.length as f64 / byte_rate;
+        let stall_wait = remaining / download_rate - duration;
+
+        Some(head_wait.max(stall_wait).max(0.0))
+    };
+
+    PlaybackReadiness {
+        ready: estimated_seconds == Some(0.0),
+        estimated_seconds,
+        head_available_bytes: head_available,
+        head_required_bytes: head_required,
+        download_speed: Speed {
+            mbps: download_speed_mbps,
+        },
+        bitrate,
+        bitrate_probed,
+    }
+}
@@ -1,10 +1,112 @@
+use crate::config::SeedAction;
+use crate::infrastructure::metadata::models::ShowType;
 use apistos::ApiComponent;
 use garde::Validate;
 use schemars::JsonSchema;
 use serde::Deserialize;
 
+/// Movie or episode a torrent is downloaded for
+#[derive(Deserialize, Debug, Default, ApiComponent, JsonSchema, Validate)]
+pub struct MediaLink {
+    #[garde(skip)]
+    pub tmdb_id: Option<u32>,
+    #[garde(skip)]
+    pub media_type: Option<ShowType>,
+    #[garde(skip)]
+    pub season: Option<u32>,
+    #[garde(skip)]
+    pub episode: Option<u32>,
+    /// File of the torrent holding the movie or episode
+    #[garde(skip)]
+    pub file_idx: Option<usize>,
+}
+
 #[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
 pub struct AddTorrentWithMagnet {
-    #[garde(pattern("magnet:\\?xt=urn:btih:[a-zA-Z0-9]*"))]
+    #[garde(length(min = 1, max = 8192))]
     pub magnet: String,
+    #[serde(flatten)]
+    #[garde(dive)]
+    pub media: MediaLink,
+}
+
+/// Either a magnet or the url of a .torrent file
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct PreviewTorrent {
+    #[garde(length(min = 1, max = 8192))]
+    pub magnet: Option<String>,
+    #[garde(length(min = 1, max = 2048))]
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct AddPreviewedTorrent {
+    /// Indices of the files to download, as listed by the preview
+    #[garde(length(min = 1))]
+    pub files: Vec<usize>,
+    #[serde(flatten)]
+    #[garde(dive)]
+    pub media: MediaLink,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct AddTorrentWithUrl {
+    #[garde(length(min = 1, max = 2048))]
+    pub url: String,
+    #[serde(flatten)]
+    #[garde(dive)]
+    pub media: MediaLink,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema)]
+pub struct DeleteTorrentParams {
+    #[serde(default)]
+    pub delete_files: bool,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct TorrentEventsParams {
+    /// Seconds between two stats snapshots
+    #[garde(range(min = 1, max = 60))]
+    #[serde(default = "default_events_interval")]
+    pub interval: u64,
+}
+
+fn default_events_interval() -> u64 {
+    2
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct SetStreamingMode {
+    #[garde(skip)]
+    pub enabled: bool,
+    /// File to stream, defaults to the file currently prioritized
+    #[garde(skip)]
+    pub file_idx: Option<usize>,
+    #[garde(range(min = 1, max = 4096))]
+    pub readahead_mb: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct SetRateLimits {
+    /// KiB/s, unlimited when unset
+    #[garde(range(min = 1))]
+    pub download_limit_kib: Option<u32>,
+    #[garde(range(min = 1))]
+    pub upload_limit_kib: Option<u32>,
+    /// Windows like `01:00-07:00` during which the limits are lifted
+    #[garde(length(max = 24))]
+    #[serde(default)]
+    pub unlimited_windows: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, ApiComponent, JsonSchema, Validate)]
+pub struct SetSeedingRules {
+    /// Unset values fall back to the server defaults
+    #[garde(range(min = 0.1, max = 100.0))]
+    pub ratio_limit: Option<f64>,
+    #[garde(range(min = 1, max = 8760))]
+    pub time_limit_hours: Option<u64>,
+    #[garde(skip)]
+    pub action: Option<SeedAction>,
 }
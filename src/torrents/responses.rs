@@ -1,7 +1,14 @@
+use crate::config::SeedAction;
+use crate::infrastructure::metadata::models::ShowType;
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::torrent::TorrentFile;
 use apistos::ApiComponent;
+use chrono::NaiveDateTime;
 use schemars::JsonSchema;
 use serde::Serialize;
+use std::path::PathBuf;
 use std::time::Duration;
+use uuid::Uuid;
 
 #[derive(Serialize, Debug, ApiComponent, JsonSchema)]
 pub enum TorrentStatsState {
@@ -11,6 +18,8 @@ pub enum TorrentStatsState {
     Live,
     #[serde(rename = "paused")]
     Paused,
+    #[serde(rename = "queued")]
+    Queued,
     #[serde(rename = "error")]
     Error,
 }
@@ -20,12 +29,22 @@ pub struct Speed {
     pub mbps: f64,
 }
 
+#[derive(Serialize, Default, Debug, ApiComponent, JsonSchema)]
+pub struct PeerStats {
+    pub live: usize,
+    pub connecting: usize,
+    pub queued: usize,
+    pub seen: usize,
+    pub dead: usize,
+}
+
 #[derive(Serialize, Default, Debug, ApiComponent, JsonSchema)]
 pub struct LiveStats {
     pub average_piece_download_time: Option<Duration>,
     pub download_speed: Speed,
     pub upload_speed: Speed,
     pub time_remaining: Option<String>,
+    pub peers: PeerStats,
 }
 
 #[derive(Serialize, Debug, ApiComponent, JsonSchema)]
@@ -38,6 +57,18 @@ pub struct TorrentStats {
     pub total_bytes: u64,
     pub finished: bool,
     pub live: Option<LiveStats>,
+    /// 1-based position in the download queue
+    pub queue_position: Option<usize>,
+}
+
+impl TorrentStats {
+    pub fn with_queue_position(mut self, queue_position: Option<usize>) -> Self {
+        if queue_position.is_some() {
+            self.state = TorrentStatsState::Queued;
+        }
+        self.queue_position = queue_position;
+        self
+    }
 }
 
 impl From<librqbit::TorrentStats> for TorrentStats {
@@ -64,7 +95,191 @@ impl From<librqbit::TorrentStats> for TorrentStats {
                     mbps: live.upload_speed.mbps,
                 },
                 time_remaining: live.time_remaining.map(|duration| duration.to_string()),
+                peers: PeerStats {
+                    live: live.snapshot.peer_stats.live,
+                    connecting: live.snapshot.peer_stats.connecting,
+                    queued: live.snapshot.peer_stats.queued,
+                    seen: live.snapshot.peer_stats.seen,
+                    dead: live.snapshot.peer_stats.dead,
+                },
             }),
+            queue_position: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct TorrentFileDetails {
+    pub index: usize,
+    pub name: String,
+    pub length: u64,
+    pub downloaded: u64,
+    pub progress: f64,
+}
+
+impl TorrentFileDetails {
+    pub fn new(index: usize, file: TorrentFile) -> Self {
+        Self {
+            index,
+            progress: match file.length {
+                0 => 1.0,
+                length => file.downloaded as f64 / length as f64,
+            },
+            name: file.name,
+            length: file.length,
+            downloaded: file.downloaded,
         }
     }
 }
+
+/// Movie or episode a torrent was added for
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct TorrentMedia {
+    pub tmdb_id: u32,
+    pub media_type: Option<ShowType>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub file_idx: Option<usize>,
+}
+
+impl TorrentMedia {
+    pub fn from_torrent(torrent: &Torrent) -> Option<Self> {
+        Some(Self {
+            tmdb_id: torrent.tmdb_id?,
+            media_type: torrent.media_type,
+            season: torrent.season,
+            episode: torrent.episode,
+            file_idx: torrent.file_idx,
+        })
+    }
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct TorrentDetails {
+    pub info_hash: String,
+    pub name: String,
+    pub files: Vec<TorrentFileDetails>,
+    pub stats: TorrentStats,
+    /// `None` for torrents the database has no record of
+    pub added_at: Option<NaiveDateTime>,
+    pub media: Option<TorrentMedia>,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct PreviewFile {
+    pub index: usize,
+    pub name: String,
+    pub length: u64,
+    pub playable: bool,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct TorrentPreview {
+    pub info_hash: String,
+    pub name: Option<String>,
+    pub total_bytes: u64,
+    pub files: Vec<PreviewFile>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TorrentStatsEvent {
+    pub info_hash: String,
+    pub stats: TorrentStats,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TorrentLifecycleEvent {
+    pub info_hash: String,
+    pub name: String,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct PlaybackReadiness {
+    pub ready: bool,
+    /// Estimated seconds before playback can start without stalling, `None` when nothing is downloading
+    pub estimated_seconds: Option<f64>,
+    pub head_available_bytes: u64,
+    pub head_required_bytes: u64,
+    pub download_speed: Speed,
+    /// Bitrate of the file in bits per second
+    pub bitrate: u64,
+    pub bitrate_probed: bool,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct CleanupCandidate {
+    pub info_hash: String,
+    pub name: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub added_at: NaiveDateTime,
+    pub last_watched_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub library_path: Option<PathBuf>,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct TorrentUsage {
+    pub info_hash: String,
+    pub name: String,
+    pub user_id: Option<Uuid>,
+    pub used_bytes: u64,
+    pub reserved_bytes: u64,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct UserUsage {
+    pub user_id: Option<Uuid>,
+    pub torrents: usize,
+    pub used_bytes: u64,
+    pub reserved_bytes: u64,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct DiskUsage {
+    pub budget_bytes: Option<u64>,
+    pub used_bytes: u64,
+    pub reserved_bytes: u64,
+    pub users: Vec<UserUsage>,
+    pub torrents: Vec<TorrentUsage>,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct SeedingStatus {
+    pub ratio_limit: Option<f64>,
+    pub time_limit_hours: Option<u64>,
+    pub action: SeedAction,
+    /// Uploaded bytes over the size of the selected files
+    pub ratio: f64,
+    pub seeding_since: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct TorrentAdded {
+    pub info_hash: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ApiComponent, JsonSchema)]
+pub enum SubtitleFormat {
+    #[serde(rename = "srt")]
+    Srt,
+    #[serde(rename = "ass")]
+    Ass,
+    #[serde(rename = "ssa")]
+    Ssa,
+    #[serde(rename = "vtt")]
+    Vtt,
+}
+
+#[derive(Serialize, Debug, ApiComponent, JsonSchema)]
+pub struct SubtitleTrack {
+    /// Index of the subtitle file in the torrent
+    pub file_idx: usize,
+    pub language: Option<String>,
+    pub label: String,
+    /// Format shipped in the torrent, tracks are always served as WebVTT
+    pub format: SubtitleFormat,
+    pub forced: bool,
+    pub sdh: bool,
+}
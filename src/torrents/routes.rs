@@ -1,18 +1,334 @@
+use crate::auth::error::AuthError;
+use crate::config::Config;
 use crate::error::ApiError;
+use crate::infrastructure::authorization::security::{GetUserFromSession, Security};
+use crate::infrastructure::authorization::stream::StreamAuth;
+use crate::infrastructure::models::torrent::{Torrent, TorrentInsert};
+use crate::infrastructure::models::user::User;
+use crate::infrastructure::torrent::error::TorrentError as LibTorrentError;
+use crate::infrastructure::torrent::{
+    delete_torrent, get_file_head_bytes, get_torrent_file, get_torrent_files, get_torrent_handle, get_torrent_handles,
+    get_torrent_name, is_file_selected, pause_torrent, recheck_torrent, resume_torrent, Magnet, RateLimitStatus,
+    StreamingStatus, UnlimitedWindow,
+};
+use crate::infrastructure::transcode::probe_bitrate;
+use crate::infrastructure::webhook::{WebhookEvent, WebhookPayload};
 use crate::state::ApplicationState;
+use crate::torrents::cleanup::find_cleanup_candidates;
 use crate::torrents::error::TorrentError;
-use crate::torrents::requests::AddTorrentWithMagnet;
-use actix_web::web;
+use crate::torrents::events::torrent_events;
+use crate::torrents::library::unlink_files;
+use crate::torrents::lifecycle::emit;
+use crate::torrents::preview::build_preview;
+use crate::torrents::quota::{disk_usage, enforce_quota};
+use crate::torrents::readiness::estimate_readiness;
+use crate::torrents::requests::{
+    AddPreviewedTorrent, AddTorrentWithMagnet, AddTorrentWithUrl, DeleteTorrentParams, MediaLink, PreviewTorrent,
+    SetRateLimits, SetSeedingRules, SetStreamingMode, TorrentEventsParams,
+};
+use crate::torrents::responses::{
+    CleanupCandidate, DiskUsage, PlaybackReadiness, SeedingStatus, SubtitleTrack, TorrentAdded, TorrentDetails,
+    TorrentFileDetails, TorrentMedia, TorrentPreview, TorrentStats,
+};
+use crate::torrents::seeding::{resume_for_playback, seeding_status, SeedingRules};
+use crate::torrents::sources::{fetch_torrent_url, read_torrent_upload};
+use crate::torrents::subtitles::{find_subtitle_tracks, to_webvtt, SUBTITLE_READ_TIMEOUT};
+use crate::transcode::error::TranscodeError;
+use actix_multipart::Multipart;
+use actix_web::http::header::{self, ContentRange, ContentRangeSpec, Range};
+use actix_web::{web, HttpRequest, HttpResponse};
 use apistos::actix::NoContent;
 use apistos::api_operation;
-use apistos::web::{post, resource, scope, ServiceConfig};
+use apistos::web::{delete, get, post, put, resource, scope, ServiceConfig};
+use futures::StreamExt;
 use garde::Validate;
-use librqbit::{AddTorrent, AddTorrentOptions, AddTorrentResponse};
+use librqbit::{AddTorrent, AddTorrentOptions, AddTorrentResponse, ManagedTorrent, TorrentStatsState};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
 use tracing::instrument;
 
 pub fn config_torrent(cfg: &mut ServiceConfig) {
-    cfg.service(scope("/torrents").service(resource("/magnet").route(post().to(add_torrent_with_magnet))));
+    cfg.service(
+        scope("/torrents")
+            .service(resource("").route(get().to(list_torrents)))
+            .service(resource("/magnet").route(post().to(add_torrent_with_magnet)))
+            .service(resource("/file").route(post().to(add_torrent_with_file)))
+            .service(resource("/url").route(post().to(add_torrent_with_url)))
+            .service(resource("/preview").route(post().to(preview_torrent)))
+            .service(resource("/preview/{hash}").route(post().to(add_previewed_torrent)))
+            .service(resource("/events").route(get().to(get_torrent_events)))
+            .service(resource("/cleanup").route(get().to(get_cleanup_candidates)))
+            .service(resource("/usage").route(get().to(get_disk_usage)))
+            .service(
+                resource("/limits")
+                    .route(get().to(get_rate_limits))
+                    .route(put().to(set_rate_limits)),
+            )
+            .service(
+                resource("/{hash}")
+                    .route(get().to(get_torrent))
+                    .route(delete().to(delete_torrent_handler)),
+            )
+            .service(resource("/{hash}/pause").route(post().to(pause_torrent_handler)))
+            .service(resource("/{hash}/resume").route(post().to(resume_torrent_handler)))
+            .service(resource("/{hash}/recheck").route(post().to(recheck_torrent_handler)))
+            .service(
+                resource("/{hash}/seeding")
+                    .route(get().to(get_seeding_rules))
+                    .route(put().to(set_seeding_rules)),
+            )
+            .service(
+                resource("/{hash}/streaming")
+                    .route(get().to(get_streaming_mode))
+                    .route(put().to(set_streaming_mode)),
+            )
+            .service(
+                scope("/{hash}/files/{file_idx}")
+                    .service(resource("/stream").route(get().to(stream_torrent_file)))
+                    .service(resource("/readiness").route(get().to(get_playback_readiness)))
+                    .service(resource("/subtitles").route(get().to(list_subtitles)))
+                    .service(resource("/subtitles/{subtitle_idx}").route(get().to(get_subtitle))),
+            ),
+    );
+}
+
+fn get_torrent_details(
+    state: &ApplicationState,
+    handle: &ManagedTorrent,
+    torrent: Option<&Torrent>,
+) -> Result<TorrentDetails, ApiError> {
+    let files = get_torrent_files(state.download_dir(), handle)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, file)| TorrentFileDetails::new(index, file))
+        .collect();
+
+    let info_hash = handle.info_hash().as_string();
+
+    Ok(TorrentDetails {
+        name: get_torrent_name(handle),
+        files,
+        stats: TorrentStats::from(handle.stats()).with_queue_position(state.download_queue().position(&info_hash)),
+        added_at: torrent.map(|torrent| torrent.added_at),
+        media: torrent.and_then(TorrentMedia::from_torrent),
+        info_hash,
+    })
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "list_torrents",
+    summary = "List the torrents of the session with their live stats"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn list_torrents(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<Vec<TorrentDetails>>, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    let records = Torrent::get_all(&pool)
+        .await?
+        .into_iter()
+        .map(|torrent| (torrent.info_hash.clone(), torrent))
+        .collect::<HashMap<_, _>>();
+
+    // Admins see every torrent of the session, other users the ones they added
+    let torrents = get_torrent_handles(state.manager())
+        .iter()
+        .map(|handle| (handle, records.get(&handle.info_hash().as_string())))
+        .filter(|(_, torrent)| user.is_admin() || torrent.is_some_and(|torrent| torrent.user_id == Some(user.id)))
+        .filter_map(|(handle, torrent)| match get_torrent_details(&state, handle, torrent) {
+            Ok(details) => Some(details),
+            Err(err) => {
+                tracing::warn!("Skipping torrent {}: {}", handle.info_hash().as_string(), err);
+                None
+            }
+        })
+        .collect();
+
+    Ok(web::Json(torrents))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_torrent",
+    summary = "Get a torrent with its live stats"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn get_torrent(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<TorrentDetails>, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+    let torrent = Torrent::get_by_info_hash(&pool, &info_hash).await?;
+
+    Ok(web::Json(get_torrent_details(&state, &handle, torrent.as_ref())?))
+}
+
+/// Torrents can be managed by the user who added them or by an admin
+pub(crate) async fn ensure_can_manage(pool: &SqlitePool, user: &User, info_hash: &str) -> Result<(), ApiError> {
+    if user.is_admin() {
+        return Ok(());
+    }
+
+    match Torrent::get_by_info_hash(pool, info_hash).await? {
+        Some(torrent) if torrent.user_id == Some(user.id) => Ok(()),
+        _ => Err(AuthError::Forbidden.into()),
+    }
+}
+
+/// Parse a magnet, refusing the ones librqbit cannot add or already has. Comes with the normalized link
+/// librqbit is given
+fn parse_magnet(state: &ApplicationState, magnet: &str) -> Result<(Magnet, String), ApiError> {
+    let magnet = Magnet::from_str(magnet)?;
+    let link = magnet.to_v1_link().ok_or(LibTorrentError::UnsupportedMagnet)?;
+
+    if get_torrent_handle(state.manager(), magnet.info_hash().unwrap_or_default()).is_ok() {
+        return Err(TorrentError::TorrentAlreadyAdded.into());
+    }
+
+    Ok((magnet, link))
+}
+
+/// Fetch the torrent behind a url, a redirect to a magnet goes through the checks of [`parse_magnet`]
+async fn fetch_torrent(state: &ApplicationState, url: &str) -> Result<AddTorrent<'static>, ApiError> {
+    match fetch_torrent_url(url, state.prowlarr_indexer().api_url()).await? {
+        AddTorrent::Url(magnet) => {
+            let (_, link) = parse_magnet(state, &magnet)?;
+            Ok(AddTorrent::from_url(link))
+        }
+        torrent => Ok(torrent),
+    }
+}
+
+/// Add a torrent to the session on behalf of a user and record them as its owner
+async fn add_torrent_for_user(
+    state: &ApplicationState,
+    pool: &SqlitePool,
+    user: &User,
+    torrent: AddTorrent<'_>,
+    only_files: Option<Vec<usize>>,
+    media: MediaLink,
+) -> Result<web::Json<TorrentAdded>, ApiError> {
+    let is_magnet = matches!(&torrent, AddTorrent::Url(url) if url.starts_with("magnet:"));
+
+    match state
+        .manager()
+        .add_torrent(
+            state.torrent_defaults().add_trackers(torrent),
+            Some(state.torrent_defaults().apply(AddTorrentOptions {
+                overwrite: true,
+                only_files,
+                // Held until the quota is checked against the size of the selected files
+                paused: true,
+                ..Default::default()
+            })),
+        )
+        .await
+    {
+        Ok(AddTorrentResponse::Added(_, handle)) => {
+            if let Err(e) = register_added_torrent(state, pool, user, &handle, media).await {
+                // Nothing owns the torrent yet, so it must not stay in the session
+                let info_hash = handle.info_hash().as_string();
+                state.download_queue().remove(&info_hash);
+                if get_torrent_handle(state.manager(), &info_hash).is_ok() {
+                    if let Err(e) = delete_torrent(state.manager(), &handle, true).await {
+                        tracing::error!("Error removing torrent {} after a failed add: {:?}", info_hash, e);
+                    }
+                }
+                return Err(e);
+            }
+
+            // librqbit only returns the handle of a magnet once its metadata is known, nothing is sent
+            // for the torrents that were removed again
+            if is_magnet {
+                emit(
+                    state,
+                    pool,
+                    WebhookPayload::new(
+                        WebhookEvent::MetadataResolved,
+                        handle.info_hash().as_string(),
+                        Some(get_torrent_name(&handle)),
+                        Some(user.id),
+                    ),
+                )
+                .await;
+            }
+
+            emit(
+                state,
+                pool,
+                WebhookPayload::new(
+                    WebhookEvent::TorrentAdded,
+                    handle.info_hash().as_string(),
+                    Some(get_torrent_name(&handle)),
+                    Some(user.id),
+                ),
+            )
+            .await;
+
+            Ok(web::Json(TorrentAdded {
+                info_hash: handle.info_hash().as_string(),
+                name: get_torrent_name(&handle),
+            }))
+        }
+        Ok(AddTorrentResponse::AlreadyManaged(..)) => Err(TorrentError::TorrentAlreadyAdded.into()),
+        Err(e) => {
+            tracing::error!("Error adding torrents: {:?}", e);
+            Err(TorrentError::AddTorrentError.into())
+        }
+        _ => Err(TorrentError::AddTorrentError.into()),
+    }
+}
+
+/// Fit, start and record a torrent that was just added to the session
+async fn register_added_torrent(
+    state: &ApplicationState,
+    pool: &SqlitePool,
+    user: &User,
+    handle: &Arc<ManagedTorrent>,
+    media: MediaLink,
+) -> Result<(), ApiError> {
+    enforce_quota(state, pool, handle).await?;
+    handle.wait_until_initialized().await.map_err(|e| {
+        tracing::error!("Error initializing torrent: {:?}", e);
+        TorrentError::TorrentInitializationFailed
+    })?;
+    state
+        .download_queue()
+        .admit(state.manager(), state.streaming_priorities(), handle)
+        .await?;
+
+    tracing::info!("Torrent added successfully");
+    Torrent::create(
+        pool,
+        &TorrentInsert {
+            info_hash: handle.info_hash().as_string(),
+            user_id: Some(user.id),
+            tmdb_id: media.tmdb_id,
+            media_type: media.media_type,
+            season: media.season,
+            episode: media.episode,
+            file_idx: media.file_idx,
+        },
+    )
+    .await?;
+
+    Ok(())
 }
 
 #[api_operation(
@@ -20,34 +336,734 @@ pub fn config_torrent(cfg: &mut ServiceConfig) {
     operation_id = "add_torrent_with_magnet",
     summary = "Add a new torrents with a magnet link"
 )]
-#[instrument(skip(state))]
+#[instrument(skip(security, pool, state))]
 pub async fn add_torrent_with_magnet(
     body: web::Json<AddTorrentWithMagnet>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
     state: web::Data<Arc<ApplicationState>>,
-) -> Result<NoContent, ApiError> {
+) -> Result<web::Json<TorrentAdded>, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    let (magnet, link) = parse_magnet(&state, &body.magnet)?;
+    tracing::info!(
+        "Adding magnet {} ({}), {} trackers, {} bytes{}",
+        magnet.info_hash().unwrap_or_default(),
+        magnet.display_name.as_deref().unwrap_or("unnamed"),
+        magnet.trackers.len(),
+        magnet
+            .exact_length
+            .map_or("unknown".to_string(), |length| length.to_string()),
+        if magnet.info_hash_v2.is_some() {
+            ", hybrid v1/v2"
+        } else {
+            ""
+        }
+    );
+
+    let user = security.get_user(&pool).await?;
+
+    add_torrent_for_user(&state, &pool, &user, AddTorrent::from_url(link), None, body.media).await
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "add_torrent_with_file",
+    summary = "Add a new torrent from an uploaded .torrent file, sent in the `file` field"
+)]
+#[instrument(skip(payload, security, pool, state))]
+pub async fn add_torrent_with_file(
+    payload: Multipart,
+    query: web::Query<MediaLink>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<TorrentAdded>, ApiError> {
+    let user = security.get_user(&pool).await?;
+
+    let torrent_bytes = read_torrent_upload(payload).await?;
+
+    add_torrent_for_user(
+        &state,
+        &pool,
+        &user,
+        AddTorrent::from_bytes(torrent_bytes),
+        None,
+        query.into_inner(),
+    )
+    .await
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "add_torrent_with_url",
+    summary = "Add a new torrent from the url of a .torrent file, as returned by indexers"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn add_torrent_with_url(
+    body: web::Json<AddTorrentWithUrl>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<TorrentAdded>, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    let user = security.get_user(&pool).await?;
+
+    let torrent = fetch_torrent(&state, &body.url).await?;
+
+    add_torrent_for_user(&state, &pool, &user, torrent, None, body.media).await
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "preview_torrent",
+    summary = "Resolve the metadata of a magnet or .torrent url and list its files without downloading"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn preview_torrent(
+    body: web::Json<PreviewTorrent>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<TorrentPreview>, ApiError> {
     let body = body.into_inner();
     body.validate()?;
 
+    let user = security.get_user(&pool).await?;
+
+    let torrent = match (body.magnet, body.url) {
+        (Some(magnet), None) => {
+            let (_, link) = parse_magnet(&state, &magnet)?;
+            AddTorrent::from_url(link)
+        }
+        (None, Some(url)) => fetch_torrent(&state, &url).await?,
+        _ => return Err(ApiError::BadRequest("Either magnet or url is required".to_string())),
+    };
+
     match state
         .manager()
         .add_torrent(
-            AddTorrent::from_url(&body.magnet),
-            Some(AddTorrentOptions {
-                overwrite: true,
+            state.torrent_defaults().add_trackers(torrent),
+            Some(state.torrent_defaults().apply(AddTorrentOptions {
+                list_only: true,
                 ..Default::default()
-            }),
+            })),
         )
         .await
     {
-        Ok(AddTorrentResponse::Added(_, _)) => {
-            tracing::info!("Torrent added successfully");
-            Ok(NoContent)
+        Ok(AddTorrentResponse::ListOnly(list)) => {
+            let info_hash = list.info_hash.as_string();
+            let preview = build_preview(info_hash.clone(), &list.info)?;
+
+            state
+                .torrent_previews()
+                .insert(&info_hash, list.torrent_bytes, preview.files.len(), user.id);
+
+            Ok(web::Json(preview))
         }
         Ok(AddTorrentResponse::AlreadyManaged(..)) => Err(TorrentError::TorrentAlreadyAdded.into()),
         Err(e) => {
-            tracing::error!("Error adding torrents: {:?}", e);
+            tracing::error!("Error resolving torrent metadata: {:?}", e);
             Err(TorrentError::AddTorrentError.into())
         }
         _ => Err(TorrentError::AddTorrentError.into()),
     }
 }
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "add_previewed_torrent",
+    summary = "Add a previewed torrent, downloading only the selected files"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn add_previewed_torrent(
+    path: web::Path<String>,
+    body: web::Json<AddPreviewedTorrent>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<TorrentAdded>, ApiError> {
+    let info_hash = path.into_inner();
+    let body = body.into_inner();
+    body.validate()?;
+
+    let user = security.get_user(&pool).await?;
+
+    let preview = state
+        .torrent_previews()
+        .get(&info_hash, user.id)
+        .ok_or(TorrentError::PreviewNotFound)?;
+
+    if body.files.iter().any(|file_idx| *file_idx >= preview.file_count) {
+        return Err(TorrentError::InvalidFileSelection.into());
+    }
+
+    let added = add_torrent_for_user(
+        &state,
+        &pool,
+        &user,
+        AddTorrent::from_bytes(preview.torrent_bytes),
+        Some(body.files),
+        body.media,
+    )
+    .await?;
+
+    state.torrent_previews().remove(&info_hash, user.id);
+
+    Ok(added)
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "stream_torrent_file",
+    summary = "Stream a torrent file as is, with range requests support"
+)]
+#[instrument(skip(req, auth, pool, state), fields(user_id = ?auth.user_id))]
+pub async fn stream_torrent_file(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    auth: StreamAuth,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<HttpResponse, ApiError> {
+    let (hash, file_idx) = path.into_inner();
+
+    let handle = get_torrent_handle(state.manager(), &hash)?;
+    let file = get_torrent_file(state.download_dir(), &handle, file_idx)?;
+
+    let limited = match auth.user_id {
+        Some(user_id) => !User::get_by_id(&pool, user_id).await?.is_admin(),
+        None => false,
+    };
+    let direct_stream = state
+        .transcode_sessions()
+        .touch_direct(auth.user_id, &hash, file_idx, file.path.clone(), limited)
+        .ok_or(TranscodeError::StreamLimitReached)?;
+
+    resume_for_playback(&state, &handle).await?;
+    Torrent::mark_watched(&pool, &hash).await?;
+    state.streaming_priorities().open(handle.clone(), file_idx);
+
+    let mut stream = handle.clone().stream(file_idx).map_err(|e| {
+        tracing::error!("Error acquiring stream: {:?}", e);
+        TorrentError::FailedToAcquireStream
+    })?;
+    let length = stream.len();
+
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| Range::from_str(value).ok())
+        .and_then(|range| match range {
+            Range::Bytes(ranges) => ranges.first().and_then(|range| range.to_satisfiable_range(length)),
+            Range::Unregistered(..) => None,
+        });
+
+    let (mut response, content_length) = match range {
+        Some((start, end)) => {
+            state.streaming_priorities().set_playhead(&hash, file_idx, start);
+
+            stream
+                .seek(SeekFrom::Start(start))
+                .await
+                .map_err(|_| TorrentError::FailedToAcquireStream)?;
+
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header(ContentRange(ContentRangeSpec::Bytes {
+                range: Some((start, end)),
+                instance_length: Some(length),
+            }));
+            (response, end - start + 1)
+        }
+        None => (HttpResponse::Ok(), length),
+    };
+
+    Ok(response
+        .content_type(mime_guess::from_path(&file.name).first_or_octet_stream().to_string())
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header(("transferMode.dlna.org", "Streaming"))
+        .no_chunking(content_length)
+        .streaming(ReaderStream::new(stream.take(content_length)).map(move |chunk| {
+            // The session counts as an active stream until the body is dropped
+            let _direct_stream = &direct_stream;
+            chunk
+        })))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_playback_readiness",
+    summary = "Estimate how long before a torrent file can be played without stalling"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn get_playback_readiness(
+    path: web::Path<(String, usize)>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<PlaybackReadiness>, ApiError> {
+    security.get_user(&pool).await?;
+    let (hash, file_idx) = path.into_inner();
+
+    let handle = get_torrent_handle(state.manager(), &hash)?;
+    let file = get_torrent_file(state.download_dir(), &handle, file_idx)?;
+
+    // Initializing torrents have no piece information yet, nothing is playable
+    let head_available = get_file_head_bytes(&handle, file_idx, &file).await.unwrap_or_default();
+
+    let download_speed = handle
+        .stats()
+        .live
+        .map(|live| live.download_speed.mbps)
+        .unwrap_or_default();
+
+    let bitrate = match head_available > 0 {
+        true => probe_bitrate(&file.path).await,
+        false => None,
+    };
+
+    Ok(web::Json(estimate_readiness(
+        &file,
+        head_available,
+        download_speed,
+        bitrate,
+    )))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "list_subtitles",
+    summary = "List the subtitle tracks shipped in the torrent for a video file"
+)]
+#[instrument(skip(_auth, state))]
+pub async fn list_subtitles(
+    path: web::Path<(String, usize)>,
+    _auth: StreamAuth,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<Vec<SubtitleTrack>>, ApiError> {
+    let (hash, file_idx) = path.into_inner();
+
+    let handle = get_torrent_handle(state.manager(), &hash)?;
+    let files = get_torrent_files(state.download_dir(), &handle)?;
+    let video = files.get(file_idx).ok_or(LibTorrentError::FileNotFound)?;
+
+    Ok(web::Json(find_subtitle_tracks(&files, video)))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_subtitle",
+    summary = "Get a subtitle track of a video file as WebVTT"
+)]
+#[instrument(skip(_auth, state))]
+pub async fn get_subtitle(
+    path: web::Path<(String, usize, usize)>,
+    _auth: StreamAuth,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<HttpResponse, ApiError> {
+    let (hash, file_idx, subtitle_idx) = path.into_inner();
+
+    let handle = get_torrent_handle(state.manager(), &hash)?;
+    let files = get_torrent_files(state.download_dir(), &handle)?;
+    let video = files.get(file_idx).ok_or(LibTorrentError::FileNotFound)?;
+    let track = find_subtitle_tracks(&files, video)
+        .into_iter()
+        .find(|track| track.file_idx == subtitle_idx)
+        .ok_or(TorrentError::SubtitleNotFound)?;
+
+    let subtitle = files.get(subtitle_idx).ok_or(LibTorrentError::FileNotFound)?;
+    let content = match subtitle.is_finished() {
+        // Also the subtitles of finished torrents that stopped seeding
+        true => tokio::fs::read(&subtitle.path)
+            .await
+            .map_err(|_| TorrentError::SubtitleUnavailable)?,
+        false => read_missing_subtitle(&handle, subtitle_idx).await?,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/vtt; charset=utf-8")
+        .body(to_webvtt(track.format, &content)))
+}
+
+/// Read a subtitle that is not fully downloaded through librqbit, which fetches its pieces first
+async fn read_missing_subtitle(handle: &Arc<ManagedTorrent>, file_idx: usize) -> Result<Vec<u8>, ApiError> {
+    // Deselected files are never downloaded, and a torrent that is not running does not fetch pieces
+    if !is_file_selected(handle, file_idx) || !matches!(handle.stats().state, TorrentStatsState::Live) {
+        return Err(TorrentError::SubtitleUnavailable.into());
+    }
+
+    let mut stream = handle.clone().stream(file_idx).map_err(|e| {
+        tracing::error!("Error acquiring stream: {:?}", e);
+        TorrentError::FailedToAcquireStream
+    })?;
+    let mut content = Vec::new();
+    tokio::time::timeout(SUBTITLE_READ_TIMEOUT, stream.read_to_end(&mut content))
+        .await
+        .map_err(|_| TorrentError::SubtitleTimedOut)?
+        .map_err(|_| TorrentError::FailedToAcquireStream)?;
+
+    Ok(content)
+}
+
+#[api_operation(tag = "torrents", operation_id = "pause_torrent", summary = "Pause a torrent")]
+#[instrument(skip(security, pool, state))]
+pub async fn pause_torrent_handler(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+
+    // Queued torrents are already paused, they just leave the queue
+    if state.download_queue().position(&info_hash).is_some() {
+        state.download_queue().remove(&info_hash);
+    } else {
+        pause_torrent(state.manager(), &handle).await?;
+    }
+    Torrent::set_paused_by_user(&pool, &info_hash, true).await?;
+
+    Ok(NoContent)
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "resume_torrent",
+    summary = "Resume a paused torrent"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn resume_torrent_handler(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+
+    state.download_queue().remove(&info_hash);
+    resume_torrent(state.manager(), &handle).await?;
+    Torrent::set_paused_by_user(&pool, &info_hash, false).await?;
+
+    Ok(NoContent)
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "recheck_torrent",
+    summary = "Verify the hash of every downloaded piece of a torrent"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn recheck_torrent_handler(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<TorrentDetails>, ApiError> {
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+
+    let handle = recheck_torrent(state.manager(), state.torrent_defaults(), &handle).await?;
+    state.streaming_priorities().replace_handle(handle.clone());
+    let torrent = Torrent::get_by_info_hash(&pool, &info_hash).await?;
+
+    Ok(web::Json(get_torrent_details(&state, &handle, torrent.as_ref())?))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "delete_torrent",
+    summary = "Remove a torrent, optionally deleting its downloaded files"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn delete_torrent_handler(
+    path: web::Path<String>,
+    query: web::Query<DeleteTorrentParams>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<NoContent, ApiError> {
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+    let torrent = Torrent::get_by_info_hash(&pool, &info_hash).await?;
+    let file_names: Vec<String> = get_torrent_files(state.download_dir(), &handle)
+        .map(|files| files.into_iter().map(|file| file.name).collect())
+        .unwrap_or_default();
+
+    delete_torrent(state.manager(), &handle, query.delete_files).await?;
+    if let Some(library_path) = torrent.as_ref().and_then(|torrent| torrent.library_path.as_ref()) {
+        if query.delete_files {
+            unlink_files(state.library(), library_path, &file_names).await;
+        }
+    }
+    state.download_queue().remove(&info_hash);
+    state.streaming_priorities().remove(&info_hash);
+    Torrent::delete(&pool, &info_hash).await?;
+
+    emit(
+        &state,
+        &pool,
+        WebhookPayload::new(
+            WebhookEvent::TorrentDeleted,
+            info_hash,
+            Some(get_torrent_name(&handle)),
+            torrent.and_then(|torrent| torrent.user_id),
+        ),
+    )
+    .await;
+
+    Ok(NoContent)
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_torrent_events",
+    summary = "Follow the progress of the user's torrents as server-sent events"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn get_torrent_events(
+    query: web::Query<TorrentEventsParams>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    query.validate()?;
+
+    let user = security.get_user(&pool).await?;
+
+    let events = torrent_events(
+        state.get_ref().clone(),
+        pool.get_ref().clone(),
+        user,
+        Duration::from_secs(query.interval),
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_streaming_mode",
+    summary = "Get the streaming priority of a torrent"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn get_streaming_mode(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<StreamingStatus>, ApiError> {
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+
+    Ok(web::Json(state.streaming_priorities().get(&info_hash)))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "set_streaming_mode",
+    summary = "Turn the sequential download of a file ahead of its playhead on or off"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn set_streaming_mode(
+    path: web::Path<String>,
+    body: web::Json<SetStreamingMode>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<StreamingStatus>, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+
+    let priorities = state.streaming_priorities();
+    match body.enabled {
+        true => {
+            let file_idx = body
+                .file_idx
+                .or(priorities.get(&info_hash).file_idx)
+                .ok_or(ApiError::BadRequest(
+                    "file_idx is required to enable streaming".to_string(),
+                ))?;
+            let readahead = body.readahead_mb.map(|readahead| readahead * 1024 * 1024);
+
+            priorities.enable(handle, file_idx, readahead)?;
+        }
+        false => priorities.disable(&info_hash),
+    }
+
+    Ok(web::Json(priorities.get(&info_hash)))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_cleanup_candidates",
+    summary = "List the unwatched torrents the next cleanup would delete"
+)]
+#[instrument(skip(security, pool, state, cfg))]
+pub async fn get_cleanup_candidates(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+    cfg: web::Data<Arc<Config>>,
+) -> Result<web::Json<Vec<CleanupCandidate>>, ApiError> {
+    security.get_admin(&pool).await?;
+
+    if cfg.unwatched_retention_days == 0 {
+        return Ok(web::Json(vec![]));
+    }
+
+    let candidates = find_cleanup_candidates(&state, &pool, cfg.unwatched_retention_days).await?;
+
+    Ok(web::Json(candidates))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_disk_usage",
+    summary = "Get the disk usage of the downloads per user and per torrent"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn get_disk_usage(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<DiskUsage>, ApiError> {
+    security.get_admin(&pool).await?;
+
+    Ok(web::Json(disk_usage(&state, &pool).await?))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_rate_limits",
+    summary = "Get the download and upload rate limits of the peer connections"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn get_rate_limits(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<RateLimitStatus>, ApiError> {
+    security.get_admin(&pool).await?;
+
+    Ok(web::Json(state.rate_limits().get()))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "set_rate_limits",
+    summary = "Change the download and upload rate limits of the peer connections and their schedule"
+)]
+#[instrument(skip(security, pool, state))]
+pub async fn set_rate_limits(
+    body: web::Json<SetRateLimits>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<RateLimitStatus>, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    security.get_admin(&pool).await?;
+
+    let windows = body
+        .unlimited_windows
+        .iter()
+        .map(|window| UnlimitedWindow::from_str(window))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let status = state
+        .rate_limits()
+        .set(body.download_limit_kib, body.upload_limit_kib, windows);
+
+    Ok(web::Json(status))
+}
+
+async fn get_managed_torrent(pool: &SqlitePool, info_hash: &str) -> Result<Torrent, ApiError> {
+    Torrent::get_by_info_hash(pool, info_hash)
+        .await?
+        .ok_or_else(|| LibTorrentError::TorrentNotFound.into())
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "get_seeding_rules",
+    summary = "Get the seeding rules of a torrent and its current ratio"
+)]
+#[instrument(skip(security, pool, state, cfg))]
+pub async fn get_seeding_rules(
+    path: web::Path<String>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+    cfg: web::Data<Arc<Config>>,
+) -> Result<web::Json<SeedingStatus>, ApiError> {
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+
+    let torrent = get_managed_torrent(&pool, &info_hash).await?;
+
+    Ok(web::Json(seeding_status(
+        &SeedingRules::from_config(&cfg),
+        &torrent,
+        &handle,
+    )))
+}
+
+#[api_operation(
+    tag = "torrents",
+    operation_id = "set_seeding_rules",
+    summary = "Override the server seeding rules for a torrent"
+)]
+#[instrument(skip(security, pool, state, cfg))]
+pub async fn set_seeding_rules(
+    path: web::Path<String>,
+    body: web::Json<SetSeedingRules>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+    cfg: web::Data<Arc<Config>>,
+) -> Result<web::Json<SeedingStatus>, ApiError> {
+    let body = body.into_inner();
+    body.validate()?;
+
+    let user = security.get_user(&pool).await?;
+    let handle = get_torrent_handle(state.manager(), path.into_inner())?;
+    let info_hash = handle.info_hash().as_string();
+    ensure_can_manage(&pool, &user, &info_hash).await?;
+
+    Torrent::set_seeding_rules(&pool, &info_hash, body.ratio_limit, body.time_limit_hours, body.action).await?;
+    let torrent = get_managed_torrent(&pool, &info_hash).await?;
+
+    Ok(web::Json(seeding_status(
+        &SeedingRules::from_config(&cfg),
+        &torrent,
+        &handle,
+    )))
+}
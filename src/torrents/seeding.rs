@@ -0,0 +1,191 @@
+use crate::config::{Config, SeedAction};
+use crate::error::ApiError;
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::torrent::{
+    delete_torrent, get_torrent_files, get_torrent_handles, get_torrent_name, pause_torrent, resume_torrent,
+};
+use crate::infrastructure::webhook::{WebhookEvent, WebhookPayload};
+use crate::state::ApplicationState;
+use crate::torrents::lifecycle::emit;
+use crate::torrents::responses::SeedingStatus;
+use chrono::{NaiveDateTime, Utc};
+use librqbit::{ManagedTorrent, TorrentStatsState};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const SEEDING_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub struct SeedingRules {
+    pub ratio_limit: Option<f64>,
+    pub time_limit_hours: Option<u64>,
+    pub action: SeedAction,
+}
+
+impl SeedingRules {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            ratio_limit: (cfg.seed_ratio_limit > 0.0).then_some(cfg.seed_ratio_limit),
+            time_limit_hours: (cfg.seed_time_limit_hours > 0).then_some(cfg.seed_time_limit_hours),
+            action: cfg.seed_limit_action,
+        }
+    }
+
+    /// Rules of a torrent, its own values take precedence over the server ones
+    pub fn for_torrent(&self, torrent: &Torrent) -> Self {
+        Self {
+            ratio_limit: torrent.seed_ratio_limit.or(self.ratio_limit),
+            time_limit_hours: torrent.seed_time_limit_hours.or(self.time_limit_hours),
+            action: torrent.seed_limit_action.unwrap_or(self.action),
+        }
+    }
+
+    fn is_met(&self, ratio: f64, finished_at: NaiveDateTime) -> bool {
+        let ratio_met = self.ratio_limit.is_some_and(|limit| ratio >= limit);
+        let time_met = self
+            .time_limit_hours
+            .is_some_and(|hours| Utc::now().naive_utc() - finished_at >= chrono::Duration::hours(hours as i64));
+
+        ratio_met || time_met
+    }
+}
+
+fn seed_ratio(handle: &ManagedTorrent) -> f64 {
+    let stats = handle.stats();
+    if stats.total_bytes == 0 {
+        return 0.0;
+    }
+
+    stats.uploaded_bytes as f64 / stats.total_bytes as f64
+}
+
+pub fn seeding_status(defaults: &SeedingRules, torrent: &Torrent, handle: &ManagedTorrent) -> SeedingStatus {
+    let rules = defaults.for_torrent(torrent);
+
+    SeedingStatus {
+        ratio_limit: rules.ratio_limit,
+        time_limit_hours: rules.time_limit_hours,
+        action: rules.action,
+        ratio: seed_ratio(handle),
+        seeding_since: torrent.finished_at,
+    }
+}
+
+/// Resume a finished torrent the seeding rules paused, so it can be played again
+pub async fn resume_for_playback(state: &ApplicationState, handle: &Arc<ManagedTorrent>) -> Result<(), ApiError> {
+    let stats = handle.stats();
+    if stats.finished && matches!(stats.state, TorrentStatsState::Paused) {
+        tracing::info!("Resuming torrent {} for playback", handle.info_hash().as_string());
+        resume_torrent(state.manager(), handle).await?;
+    }
+
+    Ok(())
+}
+
+/// Files of a torrent that are on disk, they stay there when the torrent is removed
+fn downloaded_files(state: &ApplicationState, handle: &ManagedTorrent) -> Vec<PathBuf> {
+    get_torrent_files(state.download_dir(), handle)
+        .map(|files| {
+            files
+                .into_iter()
+                .filter(|file| file.downloaded > 0)
+                .map(|file| file.path)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Background task stopping finished torrents once their ratio or seeding time limit is reached
+pub async fn enforce_seeding_rules(state: Arc<ApplicationState>, pool: SqlitePool, defaults: SeedingRules) {
+    let mut interval = tokio::time::interval(SEEDING_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let torrents = match Torrent::get_all(&pool).await {
+            Ok(torrents) => torrents
+                .into_iter()
+                .map(|torrent| (torrent.info_hash.clone(), torrent))
+                .collect::<HashMap<_, _>>(),
+            Err(err) => {
+                tracing::error!("Could not list torrents to check their seeding rules: {}", err);
+                continue;
+            }
+        };
+
+        let playing = state
+            .transcode_sessions()
+            .active()
+            .into_iter()
+            .map(|session| session.info_hash.to_lowercase())
+            .collect::<Vec<_>>();
+
+        for handle in get_torrent_handles(state.manager()) {
+            let info_hash = handle.info_hash().as_string();
+            let stats = handle.stats();
+            if !stats.finished || !matches!(stats.state, TorrentStatsState::Live) {
+                continue;
+            }
+            let Some(torrent) = torrents.get(&info_hash) else {
+                continue;
+            };
+
+            let Some(finished_at) = torrent.finished_at else {
+                if let Err(err) = Torrent::set_finished(&pool, &info_hash).await {
+                    tracing::error!("Could not record the end of the download of {}: {}", info_hash, err);
+                }
+                continue;
+            };
+
+            let rules = defaults.for_torrent(torrent);
+            if !rules.is_met(seed_ratio(&handle), finished_at)
+                || playing.contains(&info_hash)
+                || state.streaming_priorities().is_streaming(&info_hash)
+            {
+                continue;
+            }
+
+            let kept_files = match rules.action {
+                SeedAction::Pause => Vec::new(),
+                SeedAction::Remove => downloaded_files(&state, &handle),
+            };
+
+            let result = match rules.action {
+                SeedAction::Pause => pause_torrent(state.manager(), &handle).await,
+                SeedAction::Remove => delete_torrent(state.manager(), &handle, false).await,
+            };
+            if let Err(err) = result {
+                tracing::error!("Could not stop seeding torrent {}: {}", info_hash, err);
+                continue;
+            }
+
+            if rules.action == SeedAction::Remove {
+                // The row stays so cleanup and the disk quota still know about the files
+                if let Err(err) = Torrent::set_kept_files(&pool, &info_hash, &kept_files).await {
+                    tracing::error!("Could not record the files kept by torrent {}: {}", info_hash, err);
+                }
+                emit(
+                    &state,
+                    &pool,
+                    WebhookPayload::new(
+                        WebhookEvent::TorrentDeleted,
+                        info_hash.clone(),
+                        Some(get_torrent_name(&handle)),
+                        torrent.user_id,
+                    ),
+                )
+                .await;
+            }
+
+            tracing::info!(
+                "Seeding rules met for torrent {} (ratio {:.2}), {:?}",
+                info_hash,
+                seed_ratio(&handle),
+                rules.action
+            );
+        }
+    }
+}
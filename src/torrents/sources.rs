@@ -0,0 +1,136 @@
+use crate::torrents::error::TorrentError;
+use crate::utils::net::is_local_address;
+use actix_multipart::Multipart;
+use actix_web::web::{Bytes, BytesMut};
+use futures::TryStreamExt;
+use librqbit::AddTorrent;
+use librqbit_core::torrent_metainfo::{torrent_from_bytes, TorrentMetaV1Borrowed};
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::lookup_host;
+
+/// .torrent files are a few hundred kilobytes at most, even for season packs
+const MAX_TORRENT_FILE_SIZE: usize = 10 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 10;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn validate_torrent_bytes(bytes: Bytes) -> Result<Bytes, TorrentError> {
+    let metainfo: Result<TorrentMetaV1Borrowed, _> = torrent_from_bytes(&bytes);
+    metainfo.map_err(|_| TorrentError::InvalidTorrentFile)?;
+
+    Ok(bytes)
+}
+
+pub async fn read_torrent_upload(mut payload: Multipart) -> Result<Bytes, TorrentError> {
+    while let Some(mut field) = payload.try_next().await.map_err(|_| TorrentError::InvalidTorrentFile)? {
+        if field.name() != "file" {
+            continue;
+        }
+
+        let mut data = BytesMut::new();
+        while let Some(chunk) = field.try_next().await.map_err(|_| TorrentError::InvalidTorrentFile)? {
+            if data.len() + chunk.len() > MAX_TORRENT_FILE_SIZE {
+                return Err(TorrentError::TorrentFileTooLarge);
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        return validate_torrent_bytes(data.freeze());
+    }
+
+    Err(TorrentError::InvalidTorrentFile)
+}
+
+fn fetch_failed(err: impl std::fmt::Display) -> TorrentError {
+    tracing::warn!("Failed to fetch torrent file: {}", err);
+    TorrentError::FetchTorrentFailed
+}
+
+/// Addresses a hop of a download may connect to. Links of the configured indexer are trusted, any other host
+/// must only resolve to public addresses, which are then pinned so a second lookup cannot point elsewhere
+async fn resolve_public(url: &Url, trusted: Option<&Url>) -> Result<Option<Vec<SocketAddr>>, TorrentError> {
+    if trusted.is_some_and(|trusted| trusted.origin() == url.origin()) {
+        return Ok(None);
+    }
+
+    let port = url.port_or_known_default().ok_or(TorrentError::InvalidTorrentUrl)?;
+    let host = url.host_str().ok_or(TorrentError::InvalidTorrentUrl)?;
+    let addrs = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => lookup_host((host, port)).await.map_err(fetch_failed)?.collect(),
+    };
+
+    if addrs.is_empty() || addrs.iter().any(|addr| is_local_address(addr.ip())) {
+        tracing::warn!("Refusing to fetch a torrent file from {}", url);
+        return Err(TorrentError::InvalidTorrentUrl);
+    }
+
+    Ok(Some(addrs))
+}
+
+/// Download a .torrent file. Indexers often answer their download links with a redirect to a magnet,
+/// which is handed to librqbit as is.
+///
+/// Redirects are followed by hand so every hop goes through the same host checks
+pub async fn fetch_torrent_url(url: &str, indexer_url: &str) -> Result<AddTorrent<'static>, TorrentError> {
+    let trusted = Url::parse(indexer_url).ok();
+    let mut url = Url::parse(url).map_err(|_| TorrentError::InvalidTorrentUrl)?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(TorrentError::InvalidTorrentUrl);
+        }
+
+        let mut client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .redirect(Policy::none());
+        if let (Some(addrs), Some(domain)) = (resolve_public(&url, trusted.as_ref()).await?, url.domain()) {
+            client = client.resolve_to_addrs(domain, &addrs);
+        }
+        let client = client.build().map_err(fetch_failed)?;
+
+        let response = client.get(url.clone()).send().await.map_err(fetch_failed)?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| fetch_failed("redirect without a location"))?;
+
+            if location.starts_with("magnet:") {
+                return Ok(AddTorrent::from_url(location.to_string()));
+            }
+
+            url = url.join(location).map_err(|_| TorrentError::InvalidTorrentUrl)?;
+            continue;
+        }
+
+        return read_torrent_response(response).await;
+    }
+
+    Err(fetch_failed("too many redirects"))
+}
+
+async fn read_torrent_response(mut response: reqwest::Response) -> Result<AddTorrent<'static>, TorrentError> {
+    if !response.status().is_success() {
+        return Err(fetch_failed(response.status()));
+    }
+
+    if response.content_length().unwrap_or_default() > MAX_TORRENT_FILE_SIZE as u64 {
+        return Err(TorrentError::TorrentFileTooLarge);
+    }
+
+    let mut data = BytesMut::new();
+    while let Some(chunk) = response.chunk().await.map_err(fetch_failed)? {
+        if data.len() + chunk.len() > MAX_TORRENT_FILE_SIZE {
+            return Err(TorrentError::TorrentFileTooLarge);
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(AddTorrent::from_bytes(validate_torrent_bytes(data.freeze())?))
+}
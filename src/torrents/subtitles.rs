@@ -0,0 +1,383 @@
+use crate::infrastructure::torrent::TorrentFile;
+use crate::torrents::library::is_playable;
+use crate::torrents::responses::{SubtitleFormat, SubtitleTrack};
+use encoding_rs::{Encoding, WINDOWS_1252};
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Larger files are not subtitles, or not worth converting
+const MAX_SUBTITLE_SIZE: u64 = 5 * 1024 * 1024;
+/// Pieces of a subtitle that are not downloaded yet may never arrive when the torrent has no peers
+pub const SUBTITLE_READ_TIMEOUT: Duration = Duration::from_secs(30);
+const SUBTITLE_DIRS: [&str; 3] = ["subs", "subtitles", "sub"];
+const SDH_TAGS: [&str; 2] = ["sdh", "cc"];
+/// Hearing impaired, or Hindi when the subtitle names no other language
+const HEARING_IMPAIRED_TAG: &str = "hi";
+
+static SRT_TIMESTAMP_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d{1,2}):(\d{2}):(\d{2}),(\d{3})").unwrap());
+static ASS_TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{[^}]*\}").unwrap());
+
+fn subtitle_format(name: &str) -> Option<SubtitleFormat> {
+    let extension = Path::new(name).extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "srt" => Some(SubtitleFormat::Srt),
+        "ass" => Some(SubtitleFormat::Ass),
+        "ssa" => Some(SubtitleFormat::Ssa),
+        "vtt" => Some(SubtitleFormat::Vtt),
+        _ => None,
+    }
+}
+
+fn stem(name: &str) -> String {
+    Path::new(name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn dir_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    value
+        .get(..prefix.len())
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &value[prefix.len()..])
+}
+
+/// Part of the subtitle name that describes the track, `None` when the subtitle belongs to another video
+fn track_suffix(subtitle: &TorrentFile, video: &TorrentFile, single_video: bool) -> Option<String> {
+    let video_dir = video.path.parent()?;
+    let video_stem = stem(&video.name);
+    let subtitle_stem = stem(&subtitle.name);
+    let dir = subtitle.path.parent()?;
+
+    // Movie.Name.en.forced.srt, or English.srt when there is nothing else to match
+    let by_name = || match strip_prefix_ignore_case(&subtitle_stem, &video_stem) {
+        Some(suffix) => Some(suffix.to_string()),
+        None => single_video.then(|| subtitle_stem.clone()),
+    };
+
+    if dir == video_dir {
+        return by_name();
+    }
+
+    // Subs/English.srt
+    if SUBTITLE_DIRS.contains(&dir_name(dir).as_str()) && dir.parent() == Some(video_dir) {
+        return by_name();
+    }
+
+    // Subs/Episode.Name/2_English.srt
+    let parent = dir.parent()?;
+    let in_video_dir = SUBTITLE_DIRS.contains(&dir_name(parent).as_str()) && parent.parent() == Some(video_dir);
+    match in_video_dir && dir_name(dir) == video_stem.to_lowercase() {
+        true => Some(subtitle_stem),
+        false => None,
+    }
+}
+
+fn build_track(subtitle: &TorrentFile, format: SubtitleFormat, suffix: &str) -> SubtitleTrack {
+    let mut forced = false;
+    let mut sdh = false;
+    let mut hearing_impaired = None;
+    let mut language = Vec::new();
+
+    for tag in suffix
+        .split(['.', '_', ' ', '[', ']', '(', ')'])
+        .map(|tag| tag.trim_matches('-'))
+        .filter(|tag| !tag.is_empty())
+    {
+        let lowercase = tag.to_lowercase();
+        if lowercase == "forced" {
+            forced = true;
+        } else if SDH_TAGS.contains(&lowercase.as_str()) {
+            sdh = true;
+        } else if lowercase == HEARING_IMPAIRED_TAG {
+            hearing_impaired = Some(tag);
+        } else if !tag.chars().all(|c| c.is_ascii_digit()) {
+            // Numeric tags are ordering prefixes, such as 2_English.srt
+            language.push(tag);
+        }
+    }
+
+    if let Some(tag) = hearing_impaired {
+        match language.is_empty() {
+            true => language.push(tag),
+            false => sdh = true,
+        }
+    }
+
+    let language = (!language.is_empty()).then(|| language.join(" "));
+
+    let mut label = language.clone().unwrap_or_else(|| "Unknown".to_string());
+    if forced {
+        label.push_str(" (Forced)");
+    }
+    if sdh {
+        label.push_str(" (SDH)");
+    }
+
+    SubtitleTrack {
+        file_idx: subtitle.index,
+        language,
+        label,
+        format,
+        forced,
+        sdh,
+    }
+}
+
+/// Subtitle files of the torrent that belong to the given video file
+pub fn find_subtitle_tracks(files: &[TorrentFile], video: &TorrentFile) -> Vec<SubtitleTrack> {
+    let single_video = files.iter().filter(|file| is_playable(file)).count() == 1;
+
+    let mut tracks = files
+        .iter()
+        .filter(|file| file.length <= MAX_SUBTITLE_SIZE)
+        .filter_map(|file| {
+            let format = subtitle_format(&file.name)?;
+            let suffix = track_suffix(file, video, single_video)?;
+            Some(build_track(file, format, &suffix))
+        })
+        .collect::<Vec<_>>();
+
+    tracks.sort_by(|left, right| left.label.cmp(&right.label));
+    tracks
+}
+
+fn vtt_timestamp(millis: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// `h:mm:ss.cc` to milliseconds
+fn parse_ass_timestamp(value: &str) -> Option<u64> {
+    let mut parts = value.split(':');
+    let hours = parts.next()?.parse::<u64>().ok()?;
+    let minutes = parts.next()?.parse::<u64>().ok()?;
+    let (seconds, centis) = parts.next()?.split_once('.')?;
+    let seconds = seconds.parse::<u64>().ok()?;
+    let centis = centis.parse::<u64>().ok()?;
+
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + centis * 10)
+}
+
+fn srt_to_webvtt(text: &str) -> String {
+    let text = SRT_TIMESTAMP_RE.replace_all(text, |captures: &Captures| {
+        format!(
+            "{:0>2}:{}:{}.{}",
+            &captures[1], &captures[2], &captures[3], &captures[4]
+        )
+    });
+
+    format!("WEBVTT\n\n{}", text)
+}
+
+fn ass_to_webvtt(text: &str) -> String {
+    let mut in_events = false;
+    let mut fields = Vec::new();
+    let mut cues = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format.split(',').map(|field| field.trim().to_lowercase()).collect();
+            continue;
+        }
+        let Some(dialogue) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+
+        // Text is the last field and may contain commas itself
+        let values = dialogue
+            .splitn(fields.len().max(1), ',')
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        let field = |name: &str| {
+            fields
+                .iter()
+                .position(|field| field == name)
+                .and_then(|position| values.get(position).copied())
+        };
+
+        let (Some(start), Some(end), Some(text)) = (
+            field("start").and_then(parse_ass_timestamp),
+            field("end").and_then(parse_ass_timestamp),
+            field("text"),
+        ) else {
+            continue;
+        };
+
+        let text = ASS_TAG_RE
+            .replace_all(text, "")
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace("\\N", "\n")
+            .replace("\\n", "\n")
+            .replace("\\h", " ");
+        let text = text.trim();
+        if !text.is_empty() {
+            cues.push((start, end, text.to_string()));
+        }
+    }
+
+    cues.sort_by_key(|(start, ..)| *start);
+
+    cues.into_iter()
+        .fold(String::from("WEBVTT\n\n"), |mut output, (start, end, text)| {
+            output.push_str(&format!(
+                "{} --> {}\n{}\n\n",
+                vtt_timestamp(start),
+                vtt_timestamp(end),
+                text
+            ));
+            output
+        })
+}
+
+/// Subtitles without a byte order mark that are not UTF-8 are mostly Windows-1252, a superset of Latin-1
+fn decode(content: &[u8]) -> Cow<'_, str> {
+    if let Some((encoding, _)) = Encoding::for_bom(content) {
+        return encoding.decode_with_bom_removal(content).0;
+    }
+
+    match std::str::from_utf8(content) {
+        Ok(text) => Cow::Borrowed(text),
+        Err(_) => WINDOWS_1252.decode_without_bom_handling(content).0,
+    }
+}
+
+/// Convert a subtitle file to WebVTT
+pub fn to_webvtt(format: SubtitleFormat, content: &[u8]) -> String {
+    let text = decode(content);
+    let text = text
+        .trim_start_matches('\u{feff}')
+        .replace("\r\n", "\n")
+        .replace('\r', "\n");
+
+    match format {
+        SubtitleFormat::Vtt => text,
+        SubtitleFormat::Srt => srt_to_webvtt(&text),
+        SubtitleFormat::Ass | SubtitleFormat::Ssa => ass_to_webvtt(&text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn file(index: usize, path: &str) -> TorrentFile {
+        let path = PathBuf::from("/downloads").join(path);
+        TorrentFile {
+            index,
+            name: path.file_name().unwrap().to_string_lossy().into_owned(),
+            path,
+            offset: 0,
+            length: 1024,
+            downloaded: 0,
+        }
+    }
+
+    fn summary(tracks: &[SubtitleTrack]) -> Vec<(usize, &str, bool, bool)> {
+        tracks
+            .iter()
+            .map(|track| (track.file_idx, track.label.as_str(), track.forced, track.sdh))
+            .collect()
+    }
+
+    #[test]
+    fn matches_numbered_subtitles_in_per_episode_folders() {
+        let files = [
+            file(0, "Show/Show.S01E01.mkv"),
+            file(1, "Show/Show.S01E02.mkv"),
+            file(2, "Show/Subs/Show.S01E01/2_English.srt"),
+            file(3, "Show/Subs/Show.S01E01/3_French.srt"),
+            file(4, "Show/Subs/Show.S01E02/2_English.srt"),
+        ];
+
+        let tracks = find_subtitle_tracks(&files, &files[0]);
+
+        assert_eq!(
+            summary(&tracks),
+            vec![(2, "English", false, false), (3, "French", false, false)]
+        );
+        assert_eq!(tracks[0].language.as_deref(), Some("English"));
+    }
+
+    #[test]
+    fn reads_forced_and_sdh_tags() {
+        let files = [
+            file(0, "Movie/Movie.2020.mkv"),
+            file(1, "Movie/Movie.2020.en.forced.srt"),
+            file(2, "Movie/Movie.2020.es.cc.ass"),
+            file(3, "Movie/Subs/English.SDH.srt"),
+            file(4, "Movie/Movie.2020.fr.hi.srt"),
+            file(5, "Movie/Movie.2020.hi.srt"),
+        ];
+
+        let tracks = find_subtitle_tracks(&files, &files[0]);
+
+        assert_eq!(
+            summary(&tracks),
+            vec![
+                (3, "English (SDH)", false, true),
+                (1, "en (Forced)", true, false),
+                (2, "es (SDH)", false, true),
+                (4, "fr (SDH)", false, true),
+                (5, "hi", false, false),
+            ]
+        );
+        assert_eq!(tracks[1].language.as_deref(), Some("en"));
+        assert_eq!(tracks[2].format, SubtitleFormat::Ass);
+    }
+
+    #[test]
+    fn converts_ass_dialogue_with_commas_and_line_breaks() {
+        let ass = "[Script Info]\r\n\
+            Title: Test\r\n\
+            \r\n\
+            [V4+ Styles]\r\n\
+            Format: Name, Fontname\r\n\
+            Style: Default,Arial\r\n\
+            \r\n\
+            [Events]\r\n\
+            Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r\n\
+            Dialogue: 0,0:00:05.00,0:00:07.50,Default,,0,0,0,,Well, hello there\\Nsecond line\r\n\
+            Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\i1}First{\\i0}\r\n";
+
+        assert_eq!(
+            to_webvtt(SubtitleFormat::Ass, ass.as_bytes()),
+            "WEBVTT\n\n\
+            00:00:01.000 --> 00:00:02.000\nFirst\n\n\
+            00:00:05.000 --> 00:00:07.500\nWell, hello there\nsecond line\n\n"
+        );
+    }
+
+    #[test]
+    fn decodes_latin_subtitles() {
+        let srt = b"1\r\n00:00:01,000 --> 00:00:02,500\r\nCaf\xe9 cr\xe8me\r\n";
+
+        assert_eq!(
+            to_webvtt(SubtitleFormat::Srt, srt),
+            "WEBVTT\n\n1\n00:00:01.000 --> 00:00:02.500\nCaf\u{e9} cr\u{e8}me\n"
+        );
+    }
+}
@@ -5,12 +5,21 @@ use actix_web::http::StatusCode;
 pub enum TranscodeError {
     #[error("Failed to acquire stream")]
     FailedToAcquireStream,
+    #[error("Transcode session not found")]
+    SessionNotFound,
+    #[error("Manifest unavailable")]
+    ManifestUnavailable,
+    #[error("Maximum number of concurrent streams reached")]
+    StreamLimitReached,
 }
 
 impl ApiErrorImpl for TranscodeError {
     fn get_codes(&self) -> (StatusCode, &str) {
         match self {
             TranscodeError::FailedToAcquireStream => (StatusCode::INTERNAL_SERVER_ERROR, "failed_to_acquire_stream"),
+            TranscodeError::SessionNotFound => (StatusCode::NOT_FOUND, "session_not_found"),
+            TranscodeError::ManifestUnavailable => (StatusCode::INTERNAL_SERVER_ERROR, "manifest_unavailable"),
+            TranscodeError::StreamLimitReached => (StatusCode::TOO_MANY_REQUESTS, "stream_limit_reached"),
         }
     }
 }
@@ -1,4 +1,5 @@
-mod error;
+pub mod error;
+mod profile;
 mod route;
 
 pub use route::config_transcode;
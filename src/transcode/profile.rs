@@ -0,0 +1,212 @@
+use apistos::ApiComponent;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const AUDIO_CODEC: &str = "aac";
+const AUDIO_BITRATE: &str = "192k";
+
+// EBU R128 targets: -23 LUFS integrated loudness, -1 dBTP true peak
+const LOUDNORM_TARGET: &str = "I=-23:LRA=11:TP=-1";
+const NIGHT_TARGET: &str = "I=-23:LRA=5:TP=-1";
+const NIGHT_COMPRESSOR: &str = "acompressor=threshold=-30dB:ratio=6:attack=10:release=300:makeup=8dB";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ApiComponent, JsonSchema)]
+pub enum AudioProfile {
+    #[default]
+    #[serde(rename = "original")]
+    Original,
+    #[serde(rename = "normalized")]
+    Normalized,
+    #[serde(rename = "night")]
+    Night,
+}
+
+/// Loudness of the whole audio track, measured by a first loudnorm pass. Segments are transcoded one by one,
+/// the second pass of the normalized profile applies the single linear gain derived from it so the level does
+/// not jump between them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessMeasurement {
+    pub input_i: String,
+    pub input_tp: String,
+    pub input_lra: String,
+    pub input_thresh: String,
+    pub target_offset: String,
+}
+
+impl LoudnessMeasurement {
+    /// Read the report loudnorm prints last on stderr with `print_format=json`
+    pub fn parse(stderr: &str) -> Option<Self> {
+        let start = stderr.rfind('{')?;
+        let end = start + stderr[start..].find('}')?;
+        let measurement: Self = serde_json::from_str(&stderr[start..=end]).ok()?;
+
+        // Silent tracks are reported as -inf, there is nothing to normalise then
+        [
+            &measurement.input_i,
+            &measurement.input_tp,
+            &measurement.input_lra,
+            &measurement.input_thresh,
+            &measurement.target_offset,
+        ]
+        .iter()
+        .all(|value| value.parse::<f64>().map(f64::is_finite).unwrap_or(false))
+        .then_some(measurement)
+    }
+}
+
+impl AudioProfile {
+    pub fn as_str(&self) -> &'static str {
+        self.cache_key().unwrap_or("original")
+    }
+
+    /// Name of the cache folder holding the audio segments for this profile,
+    /// `None` for the untouched source track
+    pub fn cache_key(&self) -> Option<&'static str> {
+        match self {
+            AudioProfile::Original => None,
+            AudioProfile::Normalized => Some("normalized"),
+            AudioProfile::Night => Some("night"),
+        }
+    }
+
+    /// Filters applied before loudnorm and the loudnorm targets, `None` for the untouched source track
+    fn filters(&self) -> Option<(Option<&'static str>, &'static str)> {
+        match self {
+            AudioProfile::Original => None,
+            AudioProfile::Normalized => Some((None, LOUDNORM_TARGET)),
+            AudioProfile::Night => Some((Some(NIGHT_COMPRESSOR), NIGHT_TARGET)),
+        }
+    }
+
+    /// Whether the measured gain is applied linearly. loudnorm falls back to dynamic mode whenever the range of
+    /// the track exceeds the LRA target, which the narrow night target almost always is, so night is dynamic
+    fn linear(&self) -> bool {
+        matches!(self, AudioProfile::Normalized)
+    }
+
+    /// Filter of the measuring pass over the whole track, `None` when the profile does not normalise
+    pub fn measure_filter(&self) -> Option<String> {
+        let (compressor, target) = self.filters()?;
+        let loudnorm = format!("loudnorm={}:print_format=json", target);
+
+        Some(match compressor {
+            Some(compressor) => format!("{},{}", compressor, loudnorm),
+            None => loudnorm,
+        })
+    }
+
+    /// ffmpeg audio arguments, the source track is copied as-is unless the profile normalises it.
+    /// Until the `loudness` of the whole track is measured, each segment is normalised on its own by a single
+    /// dynamic loudnorm pass
+    pub fn ffmpeg_args(&self, loudness: Option<&LoudnessMeasurement>) -> Vec<String> {
+        let Some((compressor, target)) = self.filters() else {
+            return vec!["-c:a".to_string(), "copy".to_string()];
+        };
+
+        let loudnorm = match loudness {
+            Some(loudness) => format!(
+                "loudnorm={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}{}",
+                target,
+                loudness.input_i,
+                loudness.input_tp,
+                loudness.input_lra,
+                loudness.input_thresh,
+                loudness.target_offset,
+                if self.linear() { ":linear=true" } else { "" }
+            ),
+            None => format!("loudnorm={}", target),
+        };
+        let filter = match compressor {
+            Some(compressor) => format!("{},{}", compressor, loudnorm),
+            None => loudnorm,
+        };
+
+        vec![
+            "-af".to_string(),
+            filter,
+            "-c:a".to_string(),
+            AUDIO_CODEC.to_string(),
+            "-b:a".to_string(),
+            AUDIO_BITRATE.to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOUDNORM_REPORT: &str = r#"size=N/A time=01:52:03.12 bitrate=N/A speed= 212x
+[Parsed_loudnorm_0 @ 0x55d5c8a0c2c0]
+{
+	"input_i" : "-27.61",
+	"input_tp" : "-4.47",
+	"input_lra" : "18.06",
+	"input_thresh" : "-39.20",
+	"output_i" : "-23.05",
+	"output_tp" : "-1.00",
+	"output_lra" : "9.30",
+	"output_thresh" : "-34.05",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.05"
+}
+"#;
+
+    #[test]
+    fn parses_the_loudnorm_report() {
+        assert_eq!(
+            LoudnessMeasurement::parse(LOUDNORM_REPORT),
+            Some(LoudnessMeasurement {
+                input_i: "-27.61".to_string(),
+                input_tp: "-4.47".to_string(),
+                input_lra: "18.06".to_string(),
+                input_thresh: "-39.20".to_string(),
+                target_offset: "0.05".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn silent_tracks_have_no_measurement() {
+        let report = LOUDNORM_REPORT.replace("-27.61", "-inf");
+
+        assert_eq!(LoudnessMeasurement::parse(&report), None);
+        assert_eq!(LoudnessMeasurement::parse("Conversion failed!"), None);
+    }
+
+    #[test]
+    fn normalized_profile_applies_the_measured_gain_linearly() {
+        let loudness = LoudnessMeasurement::parse(LOUDNORM_REPORT).unwrap();
+        let args = AudioProfile::Normalized.ffmpeg_args(Some(&loudness));
+
+        assert_eq!(
+            args[..2],
+            [
+                "-af",
+                "loudnorm=I=-23:LRA=11:TP=-1:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.20:offset=0.05:linear=true"
+            ]
+        );
+        assert_eq!(AudioProfile::Original.ffmpeg_args(Some(&loudness)), ["-c:a", "copy"]);
+    }
+
+    #[test]
+    fn night_profile_is_dynamic() {
+        let loudness = LoudnessMeasurement::parse(LOUDNORM_REPORT).unwrap();
+        let args = AudioProfile::Night.ffmpeg_args(Some(&loudness));
+
+        assert_eq!(args[0], "-af");
+        assert!(args[1].starts_with("acompressor="));
+        assert!(args[1].ends_with(
+            "loudnorm=I=-23:LRA=5:TP=-1:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.20:offset=0.05"
+        ));
+    }
+
+    #[test]
+    fn segments_are_normalised_on_their_own_until_the_track_is_measured() {
+        assert_eq!(
+            AudioProfile::Normalized.ffmpeg_args(None),
+            ["-af", "loudnorm=I=-23:LRA=11:TP=-1", "-c:a", "aac", "-b:a", "192k"]
+        );
+        assert_eq!(AudioProfile::Original.ffmpeg_args(None), ["-c:a", "copy"]);
+    }
+}
@@ -1,11 +1,26 @@
 use crate::error::ApiError;
+use crate::infrastructure::authorization::security::{GetUserFromSession, Security};
+use crate::infrastructure::authorization::stream::{StreamAuth, StreamSigner};
+use crate::infrastructure::models::torrent::Torrent;
+use crate::infrastructure::models::user::User;
+use crate::infrastructure::torrent::{get_torrent_file, get_torrent_handle};
+use crate::infrastructure::transcode::StreamKind;
+use crate::state::ApplicationState;
+use crate::torrents::resume_for_playback;
+use crate::transcode::error::TranscodeError;
+use crate::transcode::profile::AudioProfile;
 use actix_web::{web, HttpResponse};
-use apistos::web::{get, resource, scope, ServiceConfig};
+use apistos::web::{get, post, resource, scope, ServiceConfig};
 use apistos::{api_operation, ApiComponent};
+use chrono::NaiveDateTime;
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
 use tokio::fs;
 use tokio::process::Command;
+use uuid::Uuid;
 
 // **
 // * TODO:
@@ -16,14 +31,15 @@ use tokio::process::Command;
 // **
 
 const SEGMENT_DURATION: u32 = 5;
-const CACHE_FOLDER: &str = "./cache";
 
 pub fn config_transcode(cfg: &mut ServiceConfig) {
     cfg.service(
         scope("/transcode")
             .service(resource("/start.mpd").route(get().to(get_manifest)))
+            .service(resource("/streams").route(get().to(get_active_streams)))
             .service(
                 scope("/session")
+                    .service(resource("").route(post().to(create_session)))
                     .service(resource("/{session_id}/{representation_id}/header").route(get().to(get_init_segment)))
                     .service(
                         resource("/{session_id}/{representation_id}/{segment_number}.m4s").route(get().to(get_segment)),
@@ -33,9 +49,16 @@ pub fn config_transcode(cfg: &mut ServiceConfig) {
 }
 
 mod utils {
-    use crate::error::ApiError;
-    use crate::transcode::route::{CACHE_FOLDER, SEGMENT_DURATION};
+    use crate::infrastructure::torrent::{get_torrent_file, get_torrent_handle};
+    use crate::infrastructure::transcode::CACHE_FOLDER;
+    use crate::state::ApplicationState;
+    use crate::transcode::error::TranscodeError;
+    use crate::transcode::profile::{AudioProfile, LoudnessMeasurement};
+    use crate::transcode::route::SEGMENT_DURATION;
+    use regex::Regex;
+    use std::collections::HashSet;
     use std::path::PathBuf;
+    use std::sync::{LazyLock, Mutex};
     use tokio::fs;
     use tokio::process::Command;
 
@@ -47,40 +70,182 @@ mod utils {
         Ok(())
     }
 
-    pub fn get_file_for_session(session_id: &str) -> Option<String> {
-        Some(format!("./downloads/video.mkv"))
+    static INITIALIZATION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"initialization="[^"]*""#).unwrap());
+    static MEDIA_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"media="[^"]*""#).unwrap());
+
+    /// Loudness passes started, keyed by the file they write, so the requests of a session do not decode the
+    /// whole track again while one runs. A failed pass stays in so it is not retried on every segment
+    static MEASURING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+    /// Point the segment templates of the generated manifest at the session routes, carrying the stream token
+    /// so players that cannot send cookies are able to fetch the segments
+    pub fn rewrite_segment_urls(mpd: &str, session_id: &str, token: &str, audio_profile: AudioProfile) -> String {
+        let query = format!(
+            "token={}&amp;audio_profile={}",
+            urlencoding::encode(token),
+            audio_profile.as_str()
+        );
+
+        let mpd = INITIALIZATION_RE.replace_all(
+            mpd,
+            format!(
+                r#"initialization="session/{}/$$RepresentationID$$/header?{}""#,
+                session_id, query
+            ),
+        );
+        let mpd = MEDIA_RE.replace_all(
+            &mpd,
+            format!(
+                r#"media="session/{}/$$RepresentationID$$/$$Number$$.m4s?{}""#,
+                session_id, query
+            ),
+        );
+
+        mpd.into_owned()
     }
 
-    pub async fn init_dash(session_id: &str, input_file: &str) -> Result<(String, String, String), ApiError> {
-        let session_folder = format!("./{}/{}", CACHE_FOLDER, session_id);
-        let mpd_file_path = format!("{}/start.mpd", session_folder);
-        let unused_mpd_file_path = format!("{}/unused.mpd", session_folder);
+    /// Path of the file played by a session and whether it is fully downloaded
+    pub fn get_file_for_session(state: &ApplicationState, session_id: &str) -> Option<(String, bool)> {
+        let session = state.transcode_sessions().get(session_id)?;
+        let complete = get_torrent_handle(state.manager(), &session.info_hash)
+            .and_then(|handle| get_torrent_file(state.download_dir(), &handle, session.file_idx))
+            .map(|file| file.is_finished())
+            .unwrap_or(false);
 
-        prepare_output_folder(&session_folder)
+        Some((session.path.to_string_lossy().into_owned(), complete))
+    }
+
+    /// Audio profiles get their own folder so switching profile never overwrites the segments of another one
+    pub fn session_folder(session_id: &str, audio_profile: AudioProfile) -> String {
+        match audio_profile.cache_key() {
+            Some(key) => format!("{}/{}/{}", CACHE_FOLDER, session_id, key),
+            None => format!("{}/{}", CACHE_FOLDER, session_id),
+        }
+    }
+
+    /// Loudness of the whole audio track, `None` until it is known or when the profile does not normalise.
+    ///
+    /// The measuring pass decodes the whole track, which takes minutes for a film, so it runs in the background
+    /// once the download is complete, a partial file would yield a measurement of its head only. Segments are
+    /// normalised on their own until then
+    pub async fn measure_loudness(
+        session_id: &str,
+        input_file: &str,
+        input_complete: bool,
+        audio_profile: AudioProfile,
+    ) -> Option<LoudnessMeasurement> {
+        let filter = audio_profile.measure_filter()?;
+
+        let measurement_path = format!("{}/loudness.json", session_folder(session_id, audio_profile));
+        if let Some(measurement) = read_loudness(&measurement_path).await {
+            return Some(measurement);
+        }
+
+        if input_complete && MEASURING.lock().unwrap().insert(measurement_path.clone()) {
+            let session_id = session_id.to_string();
+            let input_file = input_file.to_string();
+            tokio::spawn(async move {
+                if run_loudness_pass(&session_id, &input_file, &filter, &measurement_path).await {
+                    MEASURING.lock().unwrap().remove(&measurement_path);
+                }
+            });
+        }
+
+        None
+    }
+
+    async fn read_loudness(measurement_path: &str) -> Option<LoudnessMeasurement> {
+        let measurement = fs::read(measurement_path).await.ok()?;
+        serde_json::from_slice(&measurement).ok()
+    }
+
+    /// Returns whether the measurement was kept
+    async fn run_loudness_pass(session_id: &str, input_file: &str, filter: &str, measurement_path: &str) -> bool {
+        tracing::info!("Measuring the loudness of session {}", session_id);
+
+        let output = match Command::new("ffmpeg")
+            .args([
+                "-hide_banner",
+                "-nostats",
+                "-i",
+                input_file,
+                "-map",
+                "0:a:0",
+                "-af",
+                filter,
+                "-f",
+                "null",
+                "-",
+            ])
+            .output()
             .await
-            .map_err(|_| ApiError::InternalServerError)?;
+        {
+            Ok(output) => output,
+            Err(err) => {
+                tracing::error!("Failed to spawn ffmpeg process: {}", err);
+                return false;
+            }
+        };
+        if !output.status.success() {
+            tracing::error!("Loudness pass of session {} failed: {}", session_id, output.status);
+            return false;
+        }
+
+        let Some(measurement) = LoudnessMeasurement::parse(&String::from_utf8_lossy(&output.stderr)) else {
+            tracing::error!("No loudness measurement for session {}", session_id);
+            return false;
+        };
+
+        let kept = match serde_json::to_vec(&measurement) {
+            Ok(serialized) => fs::write(measurement_path, serialized).await,
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = kept {
+            tracing::warn!("Failed to keep the loudness of session {}: {}", session_id, err);
+            return false;
+        }
+
+        true
+    }
+
+    pub async fn init_dash(
+        session_id: &str,
+        input_file: &str,
+        input_complete: bool,
+        audio_profile: AudioProfile,
+    ) -> Result<(String, String, String), TranscodeError> {
+        let session_folder = session_folder(session_id, audio_profile);
+        let mpd_file_path = format!("{}/unused.mpd", session_folder);
+
+        prepare_output_folder(&session_folder).await.map_err(|err| {
+            tracing::error!("Failed to create the folder of session {}: {}", session_id, err);
+            TranscodeError::ManifestUnavailable
+        })?;
+
+        let loudness = measure_loudness(session_id, input_file, input_complete, audio_profile).await;
 
         let mut ffmpeg = Command::new("ffmpeg")
-            .args(&[
-                "-y",
-                "-i",
-                &input_file,
-                "-c:v",
-                "copy",
-                "-c:a",
-                "copy",
+            .args(["-y", "-i", input_file, "-c:v", "copy"])
+            .args(audio_profile.ffmpeg_args(loudness.as_ref()))
+            .args([
                 "-seg_duration",
                 &SEGMENT_DURATION.to_string(),
                 "-f",
                 "dash",
                 "-t",
                 "0",
-                &unused_mpd_file_path,
+                &mpd_file_path,
             ])
             .spawn()
-            .map_err(|_| ApiError::InternalServerError)?;
-
-        ffmpeg.wait().await.map_err(|_| ApiError::InternalServerError)?;
+            .map_err(|err| {
+                tracing::error!("Failed to spawn ffmpeg process: {}", err);
+                TranscodeError::ManifestUnavailable
+            })?;
+
+        ffmpeg.wait().await.map_err(|err| {
+            tracing::error!("FFmpeg process failed: {}", err);
+            TranscodeError::ManifestUnavailable
+        })?;
 
         let init_video_segment = format!("{}/init-stream0.m4s", session_folder);
         let init_audio_segment = format!("{}/init-stream1.m4s", session_folder);
@@ -89,28 +254,158 @@ mod utils {
     }
 }
 
+#[derive(Deserialize, ApiComponent, JsonSchema)]
+struct CreateSession {
+    info_hash: String,
+    file_idx: usize,
+}
+
+#[derive(Serialize, ApiComponent, JsonSchema)]
+struct SessionCreated {
+    session_id: String,
+    token: String,
+    manifest_url: String,
+    direct_url: String,
+}
+
 #[derive(Deserialize, ApiComponent, JsonSchema)]
 struct GetManifestParams {
     session_id: String,
+    token: String,
+    #[serde(default)]
+    audio_profile: AudioProfile,
+}
+
+#[derive(Serialize, ApiComponent, JsonSchema)]
+struct StreamUser {
+    id: Uuid,
+    username: String,
+}
+
+#[derive(Serialize, ApiComponent, JsonSchema)]
+struct ActiveStream {
+    session_id: String,
+    /// `None` for anonymous DLNA renderers
+    user: Option<StreamUser>,
+    info_hash: String,
+    file_idx: usize,
+    file_name: String,
+    kind: StreamKind,
+    quality: String,
+    started_at: NaiveDateTime,
+    last_active_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, ApiComponent, JsonSchema)]
+struct StreamParams {
+    #[serde(default)]
+    audio_profile: AudioProfile,
+}
+
+#[api_operation(
+    tag = "transcode",
+    operation_id = "create_session",
+    summary = "Create a transcode session for a torrent file"
+)]
+pub async fn create_session(
+    body: web::Json<CreateSession>,
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<SessionCreated>, ApiError> {
+    let body = body.into_inner();
+    let user = security.get_user(&pool).await?;
+
+    let handle = get_torrent_handle(state.manager(), &body.info_hash)?;
+    let file = get_torrent_file(state.download_dir(), &handle, body.file_idx)?;
+
+    // Prefer the organized copy so playback keeps working from the library layout
+    let path = match Torrent::get_by_info_hash(&pool, &body.info_hash).await? {
+        Some(Torrent {
+            file_idx: Some(file_idx),
+            library_path: Some(library_path),
+            ..
+        }) if file_idx == body.file_idx && library_path.is_file() => library_path,
+        _ => file.path,
+    };
+
+    let session = state
+        .transcode_sessions()
+        .create(body.info_hash, body.file_idx, path, user.id, !user.is_admin())
+        .ok_or(TranscodeError::StreamLimitReached)?;
+
+    // The session counts against the stream limit, it must not outlive a failed start
+    let started = async {
+        resume_for_playback(&state, &handle).await?;
+        Torrent::mark_watched(&pool, &session.info_hash).await?;
+        Ok::<_, ApiError>(())
+    }
+    .await;
+    if let Err(err) = started {
+        state.transcode_sessions().remove(&session.id);
+        return Err(err);
+    }
+
+    state.streaming_priorities().open(handle.clone(), session.file_idx);
+
+    let signer = state.stream_signer();
+    let token = signer.sign(&StreamSigner::session_resource(&session.id), Some(user.id));
+    let file_token = signer.sign(
+        &StreamSigner::file_resource(&session.info_hash, session.file_idx),
+        Some(user.id),
+    );
+
+    Ok(web::Json(SessionCreated {
+        manifest_url: format!(
+            "/api/transcode/start.mpd?session_id={}&token={}",
+            session.id,
+            urlencoding::encode(&token)
+        ),
+        direct_url: format!(
+            "/api/torrents/{}/files/{}/stream?token={}",
+            session.info_hash,
+            session.file_idx,
+            urlencoding::encode(&file_token)
+        ),
+        session_id: session.id,
+        token,
+    }))
 }
 
 #[api_operation(tag = "transcode", operation_id = "get_manifest", summary = "Get the mpd manifest")]
-pub async fn get_manifest(query: web::Query<GetManifestParams>) -> HttpResponse {
+pub async fn get_manifest(
+    query: web::Query<GetManifestParams>,
+    _auth: StreamAuth,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<HttpResponse, ApiError> {
     let get_manifest_params = query.into_inner();
     let session_id = get_manifest_params.session_id;
 
-    let file_path = utils::get_file_for_session(&session_id).unwrap();
-
-    match utils::init_dash(&session_id, &file_path).await {
-        Ok((manifest_path, _, _)) => {
-            let mpd_content = fs::read_to_string(&manifest_path).await.unwrap();
+    let (file_path, file_complete) =
+        utils::get_file_for_session(&state, &session_id).ok_or(TranscodeError::SessionNotFound)?;
+    state.transcode_sessions().touch(&session_id);
+
+    let (manifest_path, _, _) = utils::init_dash(
+        &session_id,
+        &file_path,
+        file_complete,
+        get_manifest_params.audio_profile,
+    )
+    .await?;
+    let mpd_content = fs::read_to_string(&manifest_path).await.map_err(|err| {
+        tracing::error!("Failed to read the manifest of session {}: {}", session_id, err);
+        TranscodeError::ManifestUnavailable
+    })?;
+    let mpd_content = utils::rewrite_segment_urls(
+        &mpd_content,
+        &session_id,
+        &get_manifest_params.token,
+        get_manifest_params.audio_profile,
+    );
 
-            HttpResponse::Ok()
-                .content_type("application/dash+xml")
-                .body(mpd_content)
-        }
-        Err(err) => HttpResponse::InternalServerError().body(format!("Error generating mpd manifest: {}", err)),
-    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/dash+xml")
+        .body(mpd_content))
 }
 
 #[api_operation(
@@ -118,16 +413,22 @@ pub async fn get_manifest(query: web::Query<GetManifestParams>) -> HttpResponse
     operation_id = "get_init_segment",
     summary = "Get the initialization segment for MPEG-DASH"
 )]
-pub async fn get_init_segment(params: web::Path<(String, String)>) -> Result<HttpResponse, ApiError> {
+pub async fn get_init_segment(
+    params: web::Path<(String, String)>,
+    query: web::Query<StreamParams>,
+    _auth: StreamAuth,
+) -> Result<HttpResponse, ApiError> {
     let (session_id, representation_id) = params.into_inner();
+    let audio_profile = query.into_inner().audio_profile;
 
     tracing::info!(
-        "Get init segment for session {} and representation {}",
+        "Get init segment for session {} and representation {} with audio profile {:?}",
         session_id,
-        representation_id
+        representation_id,
+        audio_profile
     );
 
-    let cache_folder = format!("./cache/{}", session_id);
+    let cache_folder = utils::session_folder(&session_id, audio_profile);
     let init_file = format!("{}/init-stream{}.m4s", cache_folder, representation_id);
 
     utils::prepare_output_folder(&cache_folder)
@@ -145,9 +446,17 @@ pub async fn get_init_segment(params: web::Path<(String, String)>) -> Result<Htt
 }
 
 #[api_operation(tag = "transcode", operation_id = "get_segment", summary = "Get a media segment")]
-pub async fn get_segment(params: web::Path<(String, String, String)>) -> Result<HttpResponse, ApiError> {
+pub async fn get_segment(
+    params: web::Path<(String, String, String)>,
+    query: web::Query<StreamParams>,
+    _auth: StreamAuth,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<HttpResponse, ApiError> {
     let (session_id, representation_id, segment_number) = params.into_inner();
-    let input_file = utils::get_file_for_session(&session_id).ok_or(ApiError::InternalServerError)?;
+    let (input_file, input_complete) =
+        utils::get_file_for_session(&state, &session_id).ok_or(TranscodeError::SessionNotFound)?;
+    let requested_profile = query.into_inner().audio_profile;
+    state.transcode_sessions().touch(&session_id);
 
     tracing::info!(
         "Create segment {} for session {} and representation {}",
@@ -158,14 +467,31 @@ pub async fn get_segment(params: web::Path<(String, String, String)>) -> Result<
 
     let start_time = segment_number.parse::<u32>().unwrap_or(0) * SEGMENT_DURATION;
 
+    if let Some(session) = state.transcode_sessions().get(&session_id) {
+        state
+            .streaming_priorities()
+            .set_playhead_seconds(&session.info_hash, session.file_idx, start_time as f64)
+            .await;
+    }
+
     let stream_type = if representation_id == "0" { "v:0" } else { "a:0" };
 
+    // Video segments are shared between every audio profile
+    let audio_profile = match representation_id.as_str() {
+        "0" => AudioProfile::Original,
+        _ => requested_profile,
+    };
+
     let segment_duration = match representation_id.as_str() {
         "1" => start_time + SEGMENT_DURATION + 2,
         _ => start_time + SEGMENT_DURATION,
     };
 
-    let cache_folder = format!("./cache/{}/{}", session_id, representation_id);
+    let cache_folder = format!(
+        "{}/{}",
+        utils::session_folder(&session_id, audio_profile),
+        representation_id
+    );
     let cache_file_path = format!("{}/segment_{}.m4s", cache_folder, segment_number);
     let unused_mpd_file_path = format!("{}/unused.mpd", cache_folder);
 
@@ -173,8 +499,10 @@ pub async fn get_segment(params: web::Path<(String, String, String)>) -> Result<
         .await
         .map_err(|_| ApiError::InternalServerError)?;
 
+    let loudness = utils::measure_loudness(&session_id, &input_file, input_complete, audio_profile).await;
+
     Command::new("ffmpeg")
-        .args(&[
+        .args([
             "-y",
             "-ss",
             &start_time.to_string(),
@@ -197,10 +525,9 @@ pub async fn get_segment(params: web::Path<(String, String, String)>) -> Result<
             // "20",
             // "-profile:v",
             // "main",
-            "-c:a",
-            "copy",
-            // "-b:a",
-            // "128k",
+        ])
+        .args(audio_profile.ffmpeg_args(loudness.as_ref()))
+        .args([
             "-movflags",
             "frag_keyframe",
             "-single_file_name",
@@ -233,3 +560,51 @@ pub async fn get_segment(params: web::Path<(String, String, String)>) -> Result<
         .content_type("application/octet-stream")
         .body(cached_data))
 }
+
+#[api_operation(
+    tag = "transcode",
+    operation_id = "get_active_streams",
+    summary = "List the streams currently being watched, admin only"
+)]
+pub async fn get_active_streams(
+    security: Security,
+    pool: web::Data<SqlitePool>,
+    state: web::Data<Arc<ApplicationState>>,
+) -> Result<web::Json<Vec<ActiveStream>>, ApiError> {
+    security.get_admin(&pool).await?;
+
+    let sessions = state.transcode_sessions().active();
+
+    let mut users = HashMap::new();
+    for user_id in sessions.iter().filter_map(|session| session.user_id) {
+        if let Entry::Vacant(entry) = users.entry(user_id) {
+            if let Ok(user) = User::get_by_id(&pool, user_id).await {
+                entry.insert(user.username);
+            }
+        }
+    }
+
+    let streams = sessions
+        .into_iter()
+        .map(|session| ActiveStream {
+            user: session.user_id.map(|id| StreamUser {
+                id,
+                username: users.get(&id).cloned().unwrap_or_default(),
+            }),
+            file_name: session
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            session_id: session.id,
+            info_hash: session.info_hash,
+            file_idx: session.file_idx,
+            kind: session.kind,
+            quality: session.quality,
+            started_at: session.created_at,
+            last_active_at: session.last_active_at,
+        })
+        .collect();
+
+    Ok(web::Json(streams))
+}
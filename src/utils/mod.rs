@@ -1,2 +1,3 @@
 pub mod cors;
+pub mod net;
 pub mod telemetry;
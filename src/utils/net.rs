@@ -0,0 +1,127 @@
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+/// CIDR block like `192.168.1.0/24`, a bare address is a block of one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(IpAddr::V6(ip)),
+            ip => ip,
+        };
+
+        match (self.address, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid network {}: expected an address or a CIDR block", value);
+        let (address, prefix) = match value.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value.trim(), None),
+        };
+
+        let address = IpAddr::from_str(address).map_err(|_| invalid())?;
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(invalid)?,
+            None => max_prefix,
+        };
+
+        Ok(Self { address, prefix })
+    }
+}
+
+/// Loopback, private and link-local addresses, the ones only reachable from inside the local network
+pub fn is_local_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_local_address(IpAddr::V4(ip)),
+            None => ip.is_loopback() || ip.is_unspecified() || is_unique_local(&ip) || is_unicast_link_local(&ip),
+        },
+    }
+}
+
+/// `fc00::/7`
+fn is_unique_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xfe00 == 0xfc00
+}
+
+/// `fe80::/10`
+fn is_unicast_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(value: &str) -> IpNetwork {
+        value.parse().unwrap()
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn block_contains_its_addresses_only() {
+        let lan = network("192.168.1.0/24");
+
+        assert!(lan.contains(ip("192.168.1.42")));
+        assert!(!lan.contains(ip("192.168.2.42")));
+        assert!(!lan.contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn bare_address_is_a_block_of_one() {
+        let host = network("10.0.0.5");
+
+        assert!(host.contains(ip("10.0.0.5")));
+        assert!(!host.contains(ip("10.0.0.6")));
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_match_ipv4_blocks() {
+        assert!(network("192.168.1.0/24").contains(ip("::ffff:192.168.1.42")));
+    }
+
+    #[test]
+    fn ipv6_blocks() {
+        let block = network("fd00::/8");
+
+        assert!(block.contains(ip("fd12:3456::1")));
+        assert!(!block.contains(ip("2001:db8::1")));
+        assert!(network("::/0").contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn invalid_networks_are_rejected() {
+        assert!("192.168.1.0/33".parse::<IpNetwork>().is_err());
+        assert!("192.168.1/24".parse::<IpNetwork>().is_err());
+        assert!("lan".parse::<IpNetwork>().is_err());
+    }
+}